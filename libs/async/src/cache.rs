@@ -1,25 +1,63 @@
+use std::any::Any;
 use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
 use std::marker::PhantomData;
-use std::sync::Arc;
+#[cfg(feature = "snapshot")]
+use std::path::{Path, PathBuf};
+use std::future::{poll_fn, Future};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::Poll;
 use std::time::{Duration, Instant, SystemTime};
 
 use arc_swap::ArcSwap;
-use chrono::DateTime;
-use mirror_cache_core::collections::{UpdatingMap, UpdatingObject, UpdatingSet};
+use chrono::{DateTime, Utc};
+use mirror_cache_core::collections::{Trie, UpdateHook, UpdatingBTreeMap, UpdatingMap, UpdatingObject, UpdatingSet, UpdatingTrie, UpdatingVec, Versioned};
+#[cfg(feature = "rand")]
+use mirror_cache_core::collections::{UpdatingWeightedSet, WeightedSet};
+#[cfg(feature = "regex")]
+use mirror_cache_core::collections::{RegexSet, UpdatingRegexSet};
 use mirror_cache_core::metrics::Metrics;
 use mirror_cache_core::processors::RawConfigProcessor;
-use mirror_cache_core::util::{Absent, Error, FailureFn, FallbackFn, Holder, Result, UpdateFn};
-use tokio::{task, time};
+#[cfg(feature = "snapshot")]
+use mirror_cache_core::snapshot;
+use mirror_cache_core::util::{catch_panic, panic_error, random_jitter, report_failure, Absent, CacheEvent, Error, FailureFn, FallbackFn, Health, History, Holder, Pin, RefreshOutcome, RefreshStatus, Result, ServedDataset, UpdateFn};
+#[cfg(feature = "snapshot")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "snapshot")]
+use serde::Serialize;
+use tokio::{select, task, time};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{broadcast, oneshot, watch, Notify};
+use tokio::runtime::Handle;
 use tokio::task::JoinHandle;
 
 use crate::sources::sources::ConfigSource;
 
+// Subscribers that fall further behind than this miss the oldest events.
+const EVENT_CAPACITY: usize = 64;
+
 pub struct MirrorCache<O> {
     collection: Arc<O>,
+    refresh_requests: UnboundedSender<oneshot::Sender<Polled>>,
+    status: Arc<Mutex<RefreshStatus>>,
+    // Becomes true when a real dataset is first swapped in.
+    loaded: watch::Receiver<bool>,
+    // A broadcast::Sender<CacheEvent<E>>, type erased since the cache isn't generic over the
+    // version.
+    events: Arc<dyn Any + Send + Sync>,
+    restore: Restore,
+    pins: Arc<Mutex<Pin>>,
+    // The Holder<E, T> being served, type erased since the cache isn't generic over either.
+    #[cfg_attr(not(feature = "snapshot"), allow(dead_code))]
+    served: Arc<dyn Any + Send + Sync>,
+    wake: Arc<Notify>,
+    // Cleared when a poll panics somewhere other than a processor or callback, until restart().
+    running: Arc<AtomicBool>,
 
-    #[allow(dead_code)]
     join_handle: JoinHandle<()>,
 }
 
@@ -39,24 +77,69 @@ impl<O: 'static> MirrorCache<O> {
         source: C,
         processor: P,
         interval: Duration,
+        mut settings: Settings,
+        snapshots: Option<Snapshots<E, T>>,
         on_update: Option<U>,
         on_failure: Option<F>,
         maybe_metrics: Option<M>,
         fallback: Option<A>,
         constructor: fn(Holder<E, T>) -> O,
-    ) -> Result<MirrorCache<O>> {
+    ) -> Result<MirrorCache<O>> where O: UpdateHook<E, T> + Send + Sync {
+        // What's being served, and what was fetched most recently. They only differ while pinned
+        // or after a rollback.
         let holder: Holder<E, T> = Arc::new(ArcSwap::new(Arc::new(None)));
+        let latest: Holder<E, T> = Arc::new(ArcSwap::new(Arc::new(None)));
+        let trigger = source.refresh_trigger();
         let metrics = maybe_metrics.map(Arc::new);
+        let (events, _) = broadcast::channel::<CacheEvent<E>>(EVENT_CAPACITY);
+        let status = Arc::new(Mutex::new(RefreshStatus::new()));
+        processor.bind_served(ServedDataset::new(holder.clone(), status.clone()));
         let updater =
-            Arc::new(Updater::new(holder.clone(), source, processor, metrics.clone()));
+            Arc::new(Updater::new(latest.clone(), source, processor, metrics.clone()));
+
+        // A snapshot saved by an earlier run is restored first, so the initial fetch only has to find
+        // anything newer and it's served if the source can't be reached. A missing or unreadable
+        // snapshot just means starting cold.
+        let restored = match snapshots.as_ref().map(|snapshots| (snapshots.load)()) {
+            Some(Ok(dataset)) => {
+                let dataset = Arc::new(Some(dataset));
+                latest.store(dataset.clone());
+                holder.store(dataset);
+                status.lock().unwrap_or_else(PoisonError::into_inner).swapped();
+                true
+            }
+            _ => false,
+        };
 
-        match updater.update().await {
+        let initial_fetch = match settings.initial_fetch_timeout {
+            _ if settings.lazy_start => Ok(Arc::new(None)),
+            None => updater.update().await,
+            Some(timeout) => time::timeout(timeout, updater.update()).await.unwrap_or_else(|_| {
+                Err(Error::new(format!("Initial fetch timed out after {:?}", timeout).as_str()))
+            }),
+        };
+        holder.store(latest.load_full());
+        let (loaded_tx, loaded) = {
+            let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+            match initial_fetch.as_ref() {
+                _ if settings.lazy_start => {}
+                Ok(init) if init.is_some() => status.updated(),
+                Ok(_) => status.checked(),
+                Err(e) => status.failed(e),
+            }
+            watch::channel(status.is_loaded())
+        };
+
+        match initial_fetch {
+            Err(e) if restored => settings.check_escalation(&status, &e, metrics.as_deref()),
             Err(e) => {
                 match fallback {
                     Some(fallback_fun) => {
                         let fallback_state =
                             Arc::new(Some((None, fallback_fun.get_fallback())));
                         holder.as_ref().store(fallback_state);
+                        let _ = events.send(CacheEvent::FallbackUsed);
+                        settings.check_escalation(&status, &e, metrics.as_deref());
                         if let Some(m) = metrics {
                             m.fallback_invoked();
                         }
@@ -66,11 +149,20 @@ impl<O: 'static> MirrorCache<O> {
             }
             Ok(init) => {
                 match init.as_ref() {
+                    None if restored => {
+                        // The source still serves the restored version, so the callback won't
+                        // otherwise hear of it until something changes.
+                        let dataset = holder.load_full();
+                        if let (Some((v, s)), Some(update_callback)) = (dataset.as_ref(), on_update.borrow()) {
+                            update_callback.updated(&None, v, s);
+                        }
+                    }
                     None => {
                         match fallback {
                             Some(fallback_fun) => {
                                 let fallback_state = Arc::new(Some((None, fallback_fun.get_fallback())));
                                 holder.as_ref().store(fallback_state);
+                                let _ = events.send(CacheEvent::FallbackUsed);
                                 if let Some(m) = metrics {
                                     m.fallback_invoked();
                                 }
@@ -79,6 +171,10 @@ impl<O: 'static> MirrorCache<O> {
                         }
                     }
                     Some((v, s)) => {
+                        let _ = events.send(CacheEvent::Updated { version: v.clone() });
+                        if let Some(snapshots) = &snapshots {
+                            let _ = (snapshots.save)(v, s);
+                        }
                         if let Some(update_callback) = on_update.borrow() {
                             update_callback.updated(&None, v, s);
                         }
@@ -87,13 +183,73 @@ impl<O: 'static> MirrorCache<O> {
             }
         };
 
+        let history = Arc::new(History::new(settings.history));
+        history.record(holder.load_full());
+
         let collection = Arc::new(constructor(holder.clone()));
-        let forever = task::spawn(
-            fetch_loop(holder, updater, interval, on_update, on_failure)
-        );
+        let served: Arc<dyn Any + Send + Sync> = Arc::new(holder.clone());
+        let pins = Arc::new(Mutex::new(Pin::default()));
+        let restore: Restore = {
+            let (holder, collection, history, events, pins, status) =
+                (holder.clone(), collection.clone(), history.clone(), events.clone(), pins.clone(), status.clone());
+            Box::new(move |matches, pin| {
+                let Some(matches) = matches.downcast_ref::<VersionMatcher<E>>() else {
+                    return Err(Error::new("Version type didn't match the collection's version type"));
+                };
+
+                let current = holder.load_full();
+                let target = match current.as_ref() {
+                    Some((Some(v), _)) if matches(v) => current.clone(),
+                    _ => history.find(|v| matches(v))
+                        .ok_or_else(|| Error::new("Version isn't in the retained history"))?,
+                };
+
+                let previous = {
+                    let mut pins = pins.lock().unwrap_or_else(PoisonError::into_inner);
+                    if pin {
+                        pins.pin();
+                    }
+                    if Arc::ptr_eq(&target, &current) {
+                        return Ok(());
+                    }
+                    holder.swap(target.clone())
+                };
+
+                status.lock().unwrap_or_else(PoisonError::into_inner).swapped();
+                if let Some(current) = target.as_ref() {
+                    collection.swapped(&previous, current);
+                    let _ = events.send(CacheEvent::RolledBack { version: current.0.clone() });
+                }
+                Ok(())
+            })
+        };
+
+        let wake = Arc::new(Notify::new());
+        if let Some(t) = trigger {
+            let notify = wake.clone();
+            t.bind(move || notify.notify_one());
+        }
+
+        let (refresh_requests, requests) = unbounded_channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let runtime = settings.runtime.take();
+        let fetching = fetch_loop(holder, collection.clone(), updater, interval, settings, snapshots, status.clone(), loaded_tx, events.clone(), history, pins.clone(), wake.clone(), running.clone(), requests, on_update, on_failure);
+        let forever = match runtime {
+            Some(runtime) => runtime.spawn(fetching),
+            None => task::spawn(fetching),
+        };
 
         Ok(MirrorCache {
             collection,
+            refresh_requests,
+            status,
+            loaded,
+            events: Arc::new(events),
+            restore,
+            pins,
+            served,
+            wake,
+            running,
             join_handle: forever,
         })
     }
@@ -102,6 +258,158 @@ impl<O: 'static> MirrorCache<O> {
         self.collection.clone()
     }
 
+    pub fn health<E>(&self) -> Health<E> where O: Versioned<E> {
+        let status = self.status.lock().unwrap_or_else(PoisonError::into_inner);
+        status.health(self.collection.current_version())
+    }
+
+    // Why updates last failed, without needing a failure callback to record it.
+    pub fn last_error(&self) -> Option<(Error, DateTime<Utc>)> {
+        self.status.lock().unwrap_or_else(PoisonError::into_inner).last_error()
+    }
+
+    // Each subscriber gets every event from then on, lagging receivers miss the oldest. The
+    // initial fetch happens during build(), before anyone can subscribe.
+    pub fn subscribe<E: Clone + Send + 'static>(&self) -> broadcast::Receiver<CacheEvent<E>> where O: Versioned<E> {
+        match self.events.downcast_ref::<broadcast::Sender<CacheEvent<E>>>() {
+            Some(events) => events.subscribe(),
+            None => panic!("Event type didn't match the collection's version type"),
+        }
+    }
+
+    // Atomically swaps the newest retained dataset with the given version back in, for example to
+    // recover quickly from a bad push. Only datasets kept by with_history() can be restored.
+    // Polling carries on, and the next new version found replaces it unless pinned, or
+    // roll_forward() swaps the latest one found back in without waiting for a new version.
+    pub fn rollback_to<E: PartialEq + Clone + 'static>(&self, version: &E) -> Result<()> where O: Versioned<E> {
+        let wanted = version.clone();
+        let matches: VersionMatcher<E> = Box::new(move |v| *v == wanted);
+        (self.restore)(&matches, false)
+    }
+
+    // Freezes the served dataset at the given version, which must be the one being served or one
+    // retained by with_history(). Polling carries on in the background, and anything newer it
+    // finds is held back until unpin().
+    pub fn pin<E: PartialEq + Clone + 'static>(&self, version: &E) -> Result<()> where O: Versioned<E> {
+        let wanted = version.clone();
+        let matches: VersionMatcher<E> = Box::new(move |v| *v == wanted);
+        (self.restore)(&matches, true)
+    }
+
+    // Lifts a pin, swapping the latest dataset found back in if it isn't the one being served, and
+    // polling immediately. Returns whether a dataset was swapped in.
+    pub async fn unpin(&self) -> bool {
+        self.release().await
+    }
+
+    // Undoes rollback_to(), swapping the latest dataset found back in if it isn't the one being
+    // served, and polling immediately. Returns whether a dataset was swapped in.
+    pub async fn roll_forward(&self) -> bool {
+        self.release().await
+    }
+
+    async fn release(&self) -> bool {
+        self.pins.lock().unwrap_or_else(PoisonError::into_inner).release();
+        let updated = self.refresh_now().await;
+        // A poll already in flight may have checked before the release, the next one acts on it.
+        if self.pins.lock().unwrap_or_else(PoisonError::into_inner).is_released() {
+            return self.refresh_now().await || updated;
+        }
+        updated
+    }
+
+    // Waits until a real dataset rather than a fallback has been loaded, for work that needs live
+    // config after a lazy start or a failed initial fetch. Returns whether one was in time.
+    pub async fn await_ready(&self, timeout: Duration) -> bool {
+        let mut loaded = self.loaded.clone();
+        let ready = time::timeout(timeout, loaded.wait_for(|loaded| *loaded)).await;
+        matches!(ready, Ok(Ok(_)))
+    }
+
+    pub fn last_updated(&self) -> DateTime<Utc> {
+        self.status.lock().unwrap_or_else(PoisonError::into_inner).last_updated()
+    }
+
+    // How long the dataset being served has been in place, for deciding whether it's too old to
+    // act on.
+    pub fn data_age(&self) -> Duration {
+        (Utc::now() - self.last_updated()).to_std().unwrap_or(Duration::ZERO)
+    }
+
+    // Serializes the dataset being served and its version, in the format used by
+    // with_snapshot_persistence(), so tooling can capture exactly what an instance was serving.
+    #[cfg(feature = "snapshot")]
+    pub fn export_snapshot<E, T>(&self) -> Result<Vec<u8>>
+        where O: UpdateHook<E, T>, E: Serialize + 'static, T: Serialize + 'static {
+        let served = self.served_holder::<E, T>()?.load_full();
+        match served.as_ref() {
+            Some((version, dataset)) => snapshot::to_bytes(version, dataset),
+            None => Err(Error::new("Nothing is being served")),
+        }
+    }
+
+    // Writes the export to a file, which with_snapshot_persistence() on another instance can
+    // start from.
+    #[cfg(feature = "snapshot")]
+    pub fn export_snapshot_to<E, T, PP: AsRef<Path>>(&self, path: PP) -> Result<()>
+        where O: UpdateHook<E, T>, E: Serialize + 'static, T: Serialize + 'static {
+        let served = self.served_holder::<E, T>()?.load_full();
+        match served.as_ref() {
+            Some((version, dataset)) => snapshot::save(path.as_ref(), version, dataset),
+            None => Err(Error::new("Nothing is being served")),
+        }
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn served_holder<E: 'static, T: 'static>(&self) -> Result<&Holder<E, T>> {
+        self.served.downcast_ref::<Holder<E, T>>()
+            .ok_or_else(|| Error::new("Dataset type didn't match the collection's dataset type"))
+    }
+
+    // False once the fetch loop has died, after which the dataset being served won't change
+    // until restart(). A loop whose task has ended, for example with its runtime, counts as dead.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire) && !self.join_handle.is_finished()
+    }
+
+    // Brings a dead fetch loop back, polling straight away from the dataset currently held.
+    // Returns false if it was already running, or its task has ended and there's nothing to wake.
+    pub fn restart(&self) -> bool {
+        if self.join_handle.is_finished() || self.running.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+
+        self.wake.notify_one();
+        true
+    }
+
+    // Has the fetch loop fetch and process immediately rather than waiting out the interval,
+    // returning whether a new dataset was swapped in. Requests made while a poll is in flight are
+    // answered with its outcome rather than fetching again.
+    pub async fn refresh_now(&self) -> bool {
+        matches!(self.poll_now().await, Polled::Updated)
+    }
+
+    // Like refresh_now(), but says which version was swapped in or why the refresh failed, for
+    // admin endpoints and deployment hooks confirming a push took effect.
+    pub async fn refresh_and_wait<E>(&self) -> Result<RefreshOutcome<E>> where O: Versioned<E> {
+        match self.poll_now().await {
+            Polled::Updated => Ok(RefreshOutcome::Updated { version: self.collection.current_version() }),
+            Polled::NoChange => Ok(RefreshOutcome::NoChange),
+            Polled::Failed(e) => Err(e),
+        }
+    }
+
+    async fn poll_now(&self) -> Polled {
+        let stopped = || Polled::Failed(Error::new("The fetch loop has stopped"));
+        let (reply, outcome) = oneshot::channel();
+        if self.refresh_requests.send(reply).is_err() {
+            return stopped();
+        }
+
+        outcome.await.unwrap_or_else(|_| stopped())
+    }
+
     pub fn map_builder<
         K: Eq + Hash + Send + Sync + 'static,
         V: Send + Sync + 'static,
@@ -114,6 +422,18 @@ impl<O: 'static> MirrorCache<O> {
         builder(UpdatingMap::new)
     }
 
+    pub fn btree_map_builder<
+        K: Ord + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, BTreeMap<K, Arc<V>>> + Send + Sync + 'static,
+        D: Into<Duration>,
+    >() -> Builder<UpdatingBTreeMap<E, K, V>, BTreeMap<K, Arc<V>>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingBTreeMap::new)
+    }
+
     pub fn set_builder<
         V: Eq + Hash + Send + Sync + 'static,
         S: 'static,
@@ -125,6 +445,17 @@ impl<O: 'static> MirrorCache<O> {
         builder(UpdatingSet::new)
     }
 
+    pub fn vec_builder<
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, Vec<Arc<V>>> + Send + Sync + 'static,
+        D: Into<Duration>,
+    >() -> Builder<UpdatingVec<E, V>, Vec<Arc<V>>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingVec::new)
+    }
+
     pub fn object_builder<
         V: Send + Sync + 'static,
         S: 'static,
@@ -135,9 +466,53 @@ impl<O: 'static> MirrorCache<O> {
     >() -> Builder<UpdatingObject<E, V>, Arc<V>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
         builder(UpdatingObject::new)
     }
+
+    pub fn trie_builder<
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, Trie<V>> + Send + Sync + 'static,
+        D: Into<Duration>
+    >() -> Builder<UpdatingTrie<E, V>, Trie<V>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingTrie::new)
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn regex_set_builder<
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, RegexSet> + Send + Sync + 'static,
+        D: Into<Duration>
+    >() -> Builder<UpdatingRegexSet<E>, RegexSet, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingRegexSet::new)
+    }
+
+    #[cfg(feature = "rand")]
+    pub fn weighted_set_builder<
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, WeightedSet<V>> + Send + Sync + 'static,
+        D: Into<Duration>
+    >() -> Builder<UpdatingWeightedSet<E, V>, WeightedSet<V>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingWeightedSet::new)
+    }
+}
+
+// Stops the fetch loop once the cache is dropped. Collections handed out by cache() keep serving
+// the last dataset.
+impl<O> Drop for MirrorCache<O> {
+    fn drop(&mut self) {
+        self.join_handle.abort();
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn fetch_loop<
+    O: UpdateHook<E, T>,
     S: Send + Sync,
     T,
     E: Clone,
@@ -148,37 +523,172 @@ async fn fetch_loop<
     M: Metrics<E> + Send + Sync + 'static,
 >(
     holder: Holder<E, T>,
+    collection: Arc<O>,
     updater: Arc<Updater<S, T, E, C, P, M>>,
     interval: Duration,
+    settings: Settings,
+    snapshots: Option<Snapshots<E, T>>,
+    status: Arc<Mutex<RefreshStatus>>,
+    loaded: watch::Sender<bool>,
+    events: broadcast::Sender<CacheEvent<E>>,
+    history: Arc<History<E, T>>,
+    pins: Arc<Mutex<Pin>>,
+    wake: Arc<Notify>,
+    running: Arc<AtomicBool>,
+    mut requests: UnboundedReceiver<oneshot::Sender<Polled>>,
     on_update: Option<U>,
     on_failure: Option<F>,
 ) {
     let mut last_success = DateTime::from(SystemTime::now());
-    let mut interval_ticker = time::interval(interval);
+    let mut interval_ticker = time::interval_at(time::Instant::now() + random_jitter(settings.fetch_jitter), interval);
+    let mut waiting: Vec<oneshot::Sender<Polled>> = vec![];
 
     loop {
-        let previous = {
-            holder.load_full().clone()
-        };
+        // A dead loop answers refresh requests with no update until restart() wakes it.
+        if !running.load(Ordering::Acquire) {
+            select! {
+                _ = wake.notified() => {},
+                Some(reply) = requests.recv() => {
+                    let _ = reply.send(Polled::Failed(Error::new("The fetch loop isn't running")));
+                },
+            }
+            interval_ticker.reset();
+            continue;
+        }
+
+        // A panic anywhere other than a processor or callback, such as in the source, leaves the
+        // poll's state unknown, so the loop stops polling and reports it until restart().
+        let polled = catch_panic_async("Refresh loop", async {
+            let previous = {
+                holder.load_full().clone()
+            };
+
+            // After a pin is released the first poll swaps the latest dataset found back in, if an
+            // older one is being served.
+            let mut fetched = None;
+            if pins.lock().unwrap_or_else(PoisonError::into_inner).take_released() {
+                let latest = updater.holder.load_full();
+                fetched = Some(latest).filter(|latest| !Arc::ptr_eq(latest, &previous));
+            }
 
-        match updater.as_ref().update().await {
-            Ok(a) => if let Some((v, t)) = a.as_ref() {
-                last_success = DateTime::from(SystemTime::now());
-                if let Some(update_callback) = &on_update {
-                    update_callback.updated(&previous, v, t)
+            let (retry_at, failure) = match updater.as_ref().update().await {
+                Ok(a) => {
+                    status.lock().unwrap_or_else(PoisonError::into_inner).checked();
+                    if a.is_some() {
+                        fetched = Some(a);
+                    } else {
+                        let _ = events.send(CacheEvent::CheckNoChange);
+                    }
+                    (None, None)
                 }
-            },
-            Err(e) => {
-                if let Some(failure_callback) = &on_failure {
+                Err(e) => {
+                    status.lock().unwrap_or_else(PoisonError::into_inner).failed(&e);
+                    let _ = events.send(CacheEvent::Failed { error: e.clone() });
                     let last = previous.as_ref().as_ref().map(|(v, _)| (v.clone(), last_success));
-                    failure_callback.failed(&e, last)
+                    report_failure(&e, last, on_failure.as_ref(), updater.metrics.as_deref());
+                    settings.check_escalation(&status, &e, updater.metrics.as_deref());
+                    (e.retry_at(), Some(e))
+                }
+            };
+
+            let swapped_in = fetched
+                .filter(|a| a.is_some())
+                .filter(|a| pins.lock().unwrap_or_else(PoisonError::into_inner).swap_in(&holder, a.clone()));
+
+            let updated = match swapped_in.as_ref().and_then(|a| a.as_ref().as_ref().map(|current| (a, current))) {
+                Some((dataset, current)) => {
+                    status.lock().unwrap_or_else(PoisonError::into_inner).swapped();
+                    loaded.send_replace(true);
+                    history.record(dataset.clone());
+                    // Failing to save a snapshot shouldn't fail the update, the next one will try
+                    // again.
+                    if let Some(snapshots) = &snapshots {
+                        let _ = (snapshots.save)(&current.0, &current.1);
+                    }
+                    let _ = events.send(CacheEvent::Updated { version: current.0.clone() });
+                    last_success = DateTime::from(SystemTime::now());
+                    collection.swapped(&previous, current);
+                    if let Some(update_callback) = &on_update {
+                        let outcome = catch_panic("Update callback", || {
+                            update_callback.updated(&previous, &current.0, &current.1);
+                            Ok(())
+                        });
+                        if let Err(e) = outcome {
+                            if let Some(m) = updater.metrics.as_deref() {
+                                m.process_error(&e);
+                            }
+                            report_failure(&e, Some((current.0.clone(), last_success)), on_failure.as_ref(), updater.metrics.as_deref());
+                        }
+                    }
+                    true
+                }
+                None => false,
+            };
+
+            settings.check_staleness(&status, updater.metrics.as_deref());
+            let outcome = match (updated, failure) {
+                (true, _) => Polled::Updated,
+                (false, Some(e)) => Polled::Failed(e),
+                (false, None) => Polled::NoChange,
+            };
+            (outcome, retry_at)
+        }).await;
+        let (outcome, retry_at) = polled.unwrap_or_else(|e| {
+            running.store(false, Ordering::Release);
+            let last_updated = {
+                let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+                status.failed(&e);
+                status.last_updated()
+            };
+            let _ = events.send(CacheEvent::Failed { error: e.clone() });
+            let last = holder.load().as_ref().as_ref().map(|(v, _)| (v.clone(), last_updated));
+            report_failure(&e, last, on_failure.as_ref(), updater.metrics.as_deref());
+            (Polled::Failed(e), None)
+        });
+
+        // Refreshes requested while this poll was in flight are answered by it.
+        while let Ok(reply) = requests.try_recv() {
+            waiting.push(reply);
+        }
+        for reply in waiting.drain(..) {
+            let _ = reply.send(outcome.clone());
+        }
+
+        // Polls, including triggered ones, are held off until the source says it's worth trying
+        // again. Refreshes requested in the meantime are told so straight away.
+        if let Some((at, Ok(delay))) = retry_at.map(|at| (at, (at - Utc::now()).to_std())) {
+            let mut held_off = pin!(time::sleep(delay));
+            loop {
+                select! {
+                    _ = &mut held_off => break,
+                    Some(reply) = requests.recv() => {
+                        let error = Error::new(format!("Polling is held off until {}", at).as_str()).with_retry_at(at);
+                        let _ = reply.send(Polled::Failed(error));
+                    },
                 }
             }
+            interval_ticker.reset();
+            continue;
+        }
+
+        select! {
+            _ = interval_ticker.tick() => {},
+            _ = wake.notified() => {},
+            Some(reply) = requests.recv() => waiting.push(reply),
         }
-        interval_ticker.tick().await;
     }
 }
 
+// Polls a future, turning a panic into an error the way catch_panic() does for closures.
+async fn catch_panic_async<T, F: Future<Output = T>>(what: &str, fut: F) -> Result<T> {
+    let mut fut = pin!(fut);
+    poll_fn(|cx| match panic::catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+        Ok(Poll::Ready(t)) => Poll::Ready(Ok(t)),
+        Ok(Poll::Pending) => Poll::Pending,
+        Err(payload) => Poll::Ready(Err(panic_error(what, payload))),
+    }).await
+}
+
 struct Updater<
     S: Send + Sync,
     T,
@@ -229,7 +739,7 @@ impl<
         let process_start = Instant::now();
         let update = match raw_update {
             Ok(None) => None,
-            Ok(Some((v, s))) => Some((v, self.processor.process(s))),
+            Ok(Some((v, s))) => Some((v, catch_panic("Processor", || self.processor.process(s)))),
             Err(e) => {
                 if let Some(m) = metrics {
                     m.fetch_error(&e)
@@ -271,6 +781,105 @@ impl<
     }
 }
 
+// What a poll came to. The version swapped in is read from the collection, so this needn't be
+// generic over it.
+#[derive(Clone)]
+enum Polled {
+    Updated,
+    NoChange,
+    Failed(Error),
+}
+
+// Matches a version being restored, boxed so it can be passed through a Restore.
+type VersionMatcher<E> = Box<dyn Fn(&E) -> bool>;
+
+// Swaps the dataset being served or one from history back in, optionally pinning it. Takes a
+// VersionMatcher<E>, type erased since the cache isn't generic over the version.
+type Restore = Box<dyn Fn(&dyn Any, bool) -> Result<()> + Send + Sync>;
+
+type StaleFn = Box<dyn Fn(Option<DateTime<Utc>>) + Send + Sync>;
+
+type EscalationFn = Box<dyn Fn(&Error, Option<DateTime<Utc>>) + Send + Sync>;
+
+type SaveFn<E, T> = Box<dyn Fn(&Option<E>, &T) -> Result<()> + Send + Sync>;
+
+type LoadFn<E, T> = Box<dyn Fn() -> Result<(Option<E>, T)> + Send + Sync>;
+
+// Saves and restores datasets for with_snapshot_persistence(), boxed so that only caches using it
+// need serializable datasets.
+#[cfg_attr(not(feature = "snapshot"), allow(dead_code))]
+struct Snapshots<E, T> {
+    save: SaveFn<E, T>,
+    load: LoadFn<E, T>,
+}
+
+// Optional tuning with sensible defaults, carried through the builder and into polling as one.
+#[derive(Default)]
+struct Settings {
+    fetch_jitter: Duration,
+    max_staleness: Option<Duration>,
+    on_stale: Option<StaleFn>,
+    // How many refreshes in a row must fail before escalating, and what to call when they have.
+    escalation: Option<(u64, EscalationFn)>,
+    history: usize,
+    initial_fetch_timeout: Option<Duration>,
+    lazy_start: bool,
+    runtime: Option<Handle>,
+}
+
+impl Settings {
+    // Checks after a failure whether the run of failures has reached the escalation threshold.
+    // Runs are ended by any successful check, so this fires once per run.
+    fn check_escalation<E, M: Metrics<E>>(&self, status: &Mutex<RefreshStatus>, err: &Error, metrics: Option<&M>) {
+        let Some((threshold, on_escalation)) = &self.escalation else {
+            return;
+        };
+
+        let (failures, last_successful_check) = {
+            let status = status.lock().unwrap_or_else(PoisonError::into_inner);
+            (status.consecutive_failures(), status.last_successful_check())
+        };
+        if failures != *threshold {
+            return;
+        }
+
+        let outcome = catch_panic("Escalation callback", || {
+            on_escalation(err, last_successful_check);
+            Ok(())
+        });
+        if let (Err(e), Some(m)) = (outcome, metrics) {
+            m.process_error(&e);
+        }
+    }
+
+    // Checks for staleness after a poll, notifying once per stale episode.
+    fn check_staleness<E, M: Metrics<E>>(&self, status: &Mutex<RefreshStatus>, metrics: Option<&M>) {
+        let Some(max_staleness) = self.max_staleness else {
+            return;
+        };
+
+        let (became_stale, last_successful_check) = {
+            let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+            (status.became_stale(max_staleness), status.last_successful_check())
+        };
+
+        if became_stale {
+            if let Some(m) = metrics {
+                m.stale(&last_successful_check);
+            }
+            if let Some(on_stale) = &self.on_stale {
+                let outcome = catch_panic("Stale callback", || {
+                    on_stale(last_successful_check);
+                    Ok(())
+                });
+                if let (Err(e), Some(m)) = (outcome, metrics) {
+                    m.process_error(&e);
+                }
+            }
+        }
+    }
+}
+
 pub struct Builder<
     O,
     T,
@@ -286,6 +895,8 @@ pub struct Builder<
 > {
     constructor: fn(Holder<E, T>) -> O,
     fetch_interval: Option<D>,
+    settings: Settings,
+    snapshots: Option<Snapshots<E, T>>,
     config_source: Option<C>,
     config_processor: Option<P>,
     failure_callback: Option<F>,
@@ -296,7 +907,7 @@ pub struct Builder<
 }
 
 impl<
-    O: Send + Sync + 'static,
+    O: UpdateHook<E, T> + Send + Sync + 'static,
     T: Send + Sync + 'static,
     S: Send + Sync + 'static,
     E: Send + Sync + Clone + 'static,
@@ -323,10 +934,71 @@ impl<
         self
     }
 
+    // Offsets polling by a random delay of up to the given duration, so a fleet of instances
+    // started together doesn't poll the upstream in lockstep.
+    pub fn with_fetch_jitter(mut self, max_jitter: Duration) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.fetch_jitter = max_jitter;
+        self
+    }
+
+    // Fires the stale callback and the stale() metric once the cache has gone longer than this
+    // without a successful check, whether from failed fetches or processing. Fires again only
+    // after a successful check brings it back.
+    pub fn with_max_staleness(mut self, max_staleness: Duration) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.max_staleness = Some(max_staleness);
+        self
+    }
+
+    // Stops build() waiting on a hanging upstream. A timed out initial fetch is treated like a
+    // failed one, using the fallback if there is one and failing otherwise.
+    pub fn with_initial_fetch_timeout(mut self, timeout: Duration) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.initial_fetch_timeout = Some(timeout);
+        self
+    }
+
+    // Has build() return straight away serving the fallback, which is required, and leaves the
+    // first fetch to the background. For services that must be up before touching the network.
+    pub fn with_lazy_start(mut self) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.lazy_start = true;
+        self
+    }
+
+    // Spawns the fetch loop on the given runtime, such as a dedicated IO runtime, rather than the
+    // one build() is called from.
+    pub fn with_runtime(mut self, runtime: Handle) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.runtime = Some(runtime);
+        self
+    }
+
+    // Retains the last n datasets swapped in, including the one being served, so rollback_to()
+    // can bring one back.
+    pub fn with_history(mut self, n: usize) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.history = n;
+        self
+    }
+
+    // Called with the time of the last successful check, if there ever was one.
+    pub fn with_stale_callback<SF>(mut self, on_stale: SF) -> Builder<O, T, S, E, C, P, D, U, F, A, M>
+        where SF: Fn(Option<DateTime<Utc>>) + Send + Sync + 'static {
+        self.settings.on_stale = Some(Box::new(on_stale));
+        self
+    }
+
+    // Called once n refreshes in a row have failed, with the latest error and the time of the
+    // last successful check, if there ever was one. It isn't called again until a refresh has
+    // succeeded, so it can tell a blip apart from an upstream that's down.
+    pub fn with_failure_escalation<EF>(mut self, n: u64, on_escalation: EF) -> Builder<O, T, S, E, C, P, D, U, F, A, M>
+        where EF: Fn(&Error, Option<DateTime<Utc>>) + Send + Sync + 'static {
+        self.settings.escalation = Some((n, Box::new(on_escalation)));
+        self
+    }
+
     pub fn with_update_callback<UU: UpdateFn<T, E>>(self, callback: UU) -> Builder<O, T, S, E, C, P, D, UU, F, A, M> {
         Builder {
             constructor: self.constructor,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: self.failure_callback,
@@ -341,6 +1013,8 @@ impl<
         Builder {
             constructor: self.constructor,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: Some(callback),
@@ -355,6 +1029,8 @@ impl<
         Builder {
             constructor: self.constructor,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: self.failure_callback,
@@ -369,6 +1045,8 @@ impl<
         Builder {
             constructor: self.constructor,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: self.failure_callback,
@@ -392,10 +1070,16 @@ impl<
             return Err(Error::new("No  fetch interval specified"));
         }
 
+        if self.settings.lazy_start && self.fallback.is_none() {
+            return Err(Error::new("Lazy start requires a fallback"));
+        }
+
         MirrorCache::construct_and_start(
             self.config_source.unwrap(),
             self.config_processor.unwrap(),
             self.fetch_interval.unwrap().into(),
+            self.settings,
+            self.snapshots,
             self.update_callback,
             self.failure_callback,
             self.metrics,
@@ -405,6 +1089,23 @@ impl<
     }
 }
 
+#[cfg(feature = "snapshot")]
+impl<O, T, S, E, C, P, D, U, F, A, M> Builder<O, T, S, E, C, P, D, U, F, A, M>
+    where T: Serialize + DeserializeOwned + 'static, E: Serialize + DeserializeOwned + 'static {
+    // Saves each dataset swapped in, with its version, to the given file, and restores it at
+    // startup ahead of the initial fetch. A cold start then serves recent real config rather than
+    // the fallback while the source is unreachable, and only fetches if there's anything newer.
+    pub fn with_snapshot_persistence<PP: Into<PathBuf>>(mut self, path: PP) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        let path = path.into();
+        let load_path = path.clone();
+        self.snapshots = Some(Snapshots {
+            save: Box::new(move |version, dataset| snapshot::save(&path, version, dataset)),
+            load: Box::new(move || snapshot::load(&load_path)),
+        });
+        self
+    }
+}
+
 fn builder<
     O: Sync + Send + 'static,
     T: Send + Sync + 'static,
@@ -417,6 +1118,8 @@ fn builder<
     Builder {
         constructor,
         fetch_interval: None,
+        settings: Settings::default(),
+        snapshots: None,
         config_source: None,
         config_processor: None,
         failure_callback: None,
@@ -0,0 +1,190 @@
+pub use reqwest::Client;
+
+use std::collections::HashMap;
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const API_VERSION: &str = "1.0";
+
+#[derive(Deserialize)]
+struct KeyValuePage {
+    items: Vec<KeyValue>,
+    #[serde(rename = "@nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KeyValue {
+    key: String,
+    value: Option<String>,
+}
+
+enum Credential {
+    AccessKey { id: String, secret: Vec<u8> },
+    Token(String),
+}
+
+pub struct AzureAppConfigSource {
+    client: Client,
+    endpoint: Url,
+    key_filter: String,
+    label_filter: Option<String>,
+    credential: Credential,
+}
+
+impl AzureAppConfigSource {
+    pub fn from_connection_string<S: Into<String>>(client: Client, connection_string: &str, key_filter: S) -> Result<AzureAppConfigSource> {
+        let mut endpoint = None;
+        let mut id = None;
+        let mut secret = None;
+        for part in connection_string.split(';') {
+            match part.split_once('=') {
+                Some(("Endpoint", v)) => endpoint = Some(v),
+                Some(("Id", v)) => id = Some(v),
+                Some(("Secret", v)) => secret = Some(v),
+                _ => {}
+            }
+        }
+
+        match (endpoint, id, secret) {
+            (Some(endpoint), Some(id), Some(secret)) => Ok(AzureAppConfigSource {
+                client,
+                endpoint: Url::parse(endpoint)?,
+                key_filter: key_filter.into(),
+                label_filter: None,
+                credential: Credential::AccessKey {
+                    id: String::from(id),
+                    secret: BASE64.decode(secret)?,
+                },
+            }),
+            _ => Err(Error::new("Connection string must contain Endpoint, Id, and Secret")),
+        }
+    }
+
+    pub fn with_token<S: Into<String>>(client: Client, endpoint: &str, token: S, key_filter: S) -> Result<AzureAppConfigSource> {
+        Ok(AzureAppConfigSource {
+            client,
+            endpoint: Url::parse(endpoint)?,
+            key_filter: key_filter.into(),
+            label_filter: None,
+            credential: Credential::Token(token.into()),
+        })
+    }
+
+    pub fn with_label<S: Into<String>>(mut self, label_filter: S) -> AzureAppConfigSource {
+        self.label_filter = Some(label_filter.into());
+        self
+    }
+
+    fn first_page(&self) -> Result<Url> {
+        let mut url = self.endpoint.join("/kv")?;
+        url.query_pairs_mut()
+            .append_pair("key", self.key_filter.as_str())
+            .append_pair("api-version", API_VERSION);
+        if let Some(label) = &self.label_filter {
+            url.query_pairs_mut().append_pair("label", label.as_str());
+        }
+        Ok(url)
+    }
+
+    fn signed(&self, url: &Url) -> Result<RequestBuilder> {
+        let req = self.client.request(Method::GET, url.clone());
+        match &self.credential {
+            Credential::Token(token) => Ok(req.bearer_auth(token)),
+            Credential::AccessKey { id, secret } => {
+                let host = url.host_str().unwrap_or_default();
+                let path_and_query = match url.query() {
+                    Some(q) => format!("{}?{}", url.path(), q),
+                    None => String::from(url.path()),
+                };
+                let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+                let content_hash = BASE64.encode(Sha256::digest(b""));
+                let to_sign = format!("GET\n{}\n{};{};{}", path_and_query, date, host, content_hash);
+
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret)?;
+                mac.update(to_sign.as_bytes());
+                let signature = BASE64.encode(mac.finalize().into_bytes());
+
+                Ok(req.header("x-ms-date", date)
+                    .header("x-ms-content-sha256", content_hash)
+                    .header("Authorization", format!(
+                        "HMAC-SHA256 Credential={}&SignedHeaders=x-ms-date;host;x-ms-content-sha256&Signature={}",
+                        id, signature
+                    )))
+            }
+        }
+    }
+
+    fn get_etag(headers: &reqwest::header::HeaderMap) -> String {
+        headers.get("ETag")
+            .and_then(|h| h.to_str().ok())
+            .map(String::from)
+            .unwrap_or_default()
+    }
+
+    // The version is the ETags of every page joined together, as each only covers its own items.
+    async fn fetch_pages(&self, first_etag: Option<&String>) -> Result<Option<(Option<String>, HashMap<String, String>)>> {
+        let mut entries = HashMap::new();
+        let mut etags = vec![];
+        let mut next = Some(self.first_page()?);
+
+        while let Some(url) = next.take() {
+            let req = match first_etag {
+                Some(etag) if etags.is_empty() => self.signed(&url)?.header("If-None-Match", etag),
+                _ => self.signed(&url)?,
+            };
+
+            let resp = req.send().await?;
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            } else if !resp.status().is_success() {
+                return Err(Error::new(format!("Fetch failed. Status: {}", resp.status().as_str()).as_str()));
+            }
+
+            etags.push(AzureAppConfigSource::get_etag(resp.headers()));
+            let page: KeyValuePage = resp.json().await?;
+            for kv in page.items {
+                entries.insert(kv.key, kv.value.unwrap_or_default());
+            }
+            if let Some(link) = page.next_link {
+                next = Some(self.endpoint.join(link.as_str())?);
+            }
+        }
+
+        Ok(Some((Some(etags.join(",")), entries)))
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, HashMap<String, String>> for AzureAppConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, HashMap<String, String>)> {
+        match self.fetch_pages(None).await? {
+            Some(update) => Ok(update),
+            None => Err(Error::new("Unconditional fetch returned no content")),
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, HashMap<String, String>)>> {
+        // A conditional request can only be made against a single page, otherwise compare after
+        // fetching everything.
+        if !version.contains(',') {
+            return self.fetch_pages(Some(version)).await;
+        }
+
+        match self.fetch_pages(None).await? {
+            Some((Some(v), _)) if &v == version => Ok(None),
+            update => Ok(update),
+        }
+    }
+}
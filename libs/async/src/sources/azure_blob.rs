@@ -0,0 +1,59 @@
+pub use azure_storage_blobs::prelude::BlobServiceClient;
+
+use std::io::Cursor;
+use async_trait::async_trait;
+use azure_core::error::ErrorKind;
+use azure_core::request_options::IfMatchCondition;
+use azure_core::StatusCode;
+use azure_storage_blobs::blob::operations::GetBlobBuilder;
+use azure_storage_blobs::prelude::BlobClient;
+use futures::StreamExt;
+
+use mirror_cache_core::util::Result;
+
+use crate::sources::sources::ConfigSource;
+
+pub struct AzureBlobConfigSource {
+    client: BlobClient,
+}
+
+impl AzureBlobConfigSource {
+    pub fn new<S: Into<String>>(client: BlobServiceClient, container: S, blob: S) -> Result<AzureBlobConfigSource> {
+        Ok(AzureBlobConfigSource {
+            client: client.container_client(container).blob_client(blob),
+        })
+    }
+
+    async fn download(request: GetBlobBuilder) -> azure_core::Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let mut version = None;
+        let mut content = Vec::new();
+        let mut stream = request.into_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            version = Some(chunk.blob.properties.etag.to_string());
+            content.extend(chunk.data.collect().await?);
+        }
+
+        Ok((version, Cursor::new(content)))
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, Cursor<Vec<u8>>> for AzureBlobConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        Ok(AzureBlobConfigSource::download(self.client.get()).await?)
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let request = self.client.get()
+            .if_match(IfMatchCondition::NotMatch(version.clone()));
+
+        match AzureBlobConfigSource::download(request).await {
+            Ok(update) => Ok(Some(update)),
+            Err(err) => match err.kind() {
+                ErrorKind::HttpResponse { status: StatusCode::NotModified, .. } => Ok(None),
+                _ => Err(err.into()),
+            }
+        }
+    }
+}
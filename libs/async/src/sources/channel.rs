@@ -0,0 +1,76 @@
+pub use tokio::sync::watch::{channel, Receiver, Sender};
+
+use std::sync::Mutex;
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+// Lets application code push payloads into a cache over a watch channel. Each change fires a
+// refresh, and the number of changes seen so far serves as the version, starting from 0 for the
+// channel's initial value. Values superseded before the cache gets to them are skipped.
+pub struct ChannelConfigSource<S> {
+    seen: Mutex<(u64, Receiver<S>)>,
+    trigger: RefreshTrigger,
+    changes: JoinHandle<()>,
+}
+
+impl<S: Send + Sync + 'static> ChannelConfigSource<S> {
+    pub fn new(rx: Receiver<S>) -> ChannelConfigSource<S> {
+        let trigger = RefreshTrigger::new();
+        let on_change = trigger.clone();
+
+        let mut changes_rx = rx.clone();
+        let changes = tokio::spawn(async move {
+            while changes_rx.changed().await.is_ok() {
+                on_change.fire();
+            }
+        });
+
+        ChannelConfigSource {
+            seen: Mutex::new((0, rx)),
+            trigger,
+            changes,
+        }
+    }
+}
+
+impl<S: Clone> ChannelConfigSource<S> {
+    fn latest(&self, version: Option<u64>) -> Result<Option<(Option<u64>, S)>> {
+        let mut seen = self.seen.lock()?;
+        let (count, rx) = &mut *seen;
+
+        // A closed channel keeps serving its last value.
+        if rx.has_changed().unwrap_or(false) {
+            *count += 1;
+        } else if version.is_some_and(|v| v >= *count) {
+            return Ok(None);
+        }
+
+        let latest = rx.borrow_and_update().clone();
+        Ok(Some((Some(*count), latest)))
+    }
+}
+
+impl<S> Drop for ChannelConfigSource<S> {
+    fn drop(&mut self) {
+        self.changes.abort();
+    }
+}
+
+#[async_trait]
+impl<S: Clone + Send + Sync> ConfigSource<u64, S> for ChannelConfigSource<S> {
+    async fn fetch(&self) -> Result<(Option<u64>, S)> {
+        self.latest(None)?.ok_or_else(|| Error::new("Channel had no value"))
+    }
+
+    async fn fetch_if_newer(&self, version: &u64) -> Result<Option<(Option<u64>, S)>> {
+        self.latest(Some(*version))
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
@@ -0,0 +1,109 @@
+use std::io;
+use std::io::{BufReader, Chain, Cursor, Read};
+use std::marker::PhantomData;
+use std::sync::{Mutex, PoisonError};
+use async_trait::async_trait;
+use flate2::bufread::GzDecoder;
+
+use mirror_cache_core::util::{RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// The bytes read to detect the format, put back in front of the rest of the payload.
+type Raw<S> = BufReader<Chain<Cursor<Vec<u8>>, S>>;
+
+#[derive(Clone, Copy)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+// Decompresses the payload of another source as it's read. Unless a format is given, it's
+// detected from the payload's magic bytes, and payloads matching neither format are passed
+// through as they are.
+pub struct DecompressingSource<C, S> {
+    inner: C,
+    format: Option<Compression>,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, S: Read> DecompressingSource<C, S> {
+    pub fn new(inner: C) -> DecompressingSource<C, S> {
+        DecompressingSource {
+            inner,
+            format: None,
+            _phantom_s: PhantomData,
+        }
+    }
+
+    pub fn with_format(mut self, format: Compression) -> DecompressingSource<C, S> {
+        self.format = Some(format);
+        self
+    }
+
+    fn decompress(&self, mut raw: S) -> Result<Decompressed<S>> {
+        // A single read can return fewer bytes than the magic numbers, so read until there are
+        // enough to tell or the payload ends.
+        let mut head = Vec::with_capacity(ZSTD_MAGIC.len());
+        if self.format.is_none() {
+            (&mut raw).take(ZSTD_MAGIC.len() as u64).read_to_end(&mut head)?;
+        }
+
+        let format = match self.format {
+            Some(format) => Some(format),
+            None if head.starts_with(GZIP_MAGIC) => Some(Compression::Gzip),
+            None if head.starts_with(ZSTD_MAGIC) => Some(Compression::Zstd),
+            None => None,
+        };
+
+        let raw = BufReader::new(Cursor::new(head).chain(raw));
+        Ok(match format {
+            Some(Compression::Gzip) => Decompressed::Gzip(GzDecoder::new(raw)),
+            Some(Compression::Zstd) => Decompressed::Zstd(Mutex::new(zstd::Decoder::with_buffer(raw)?)),
+            None => Decompressed::Plain(raw),
+        })
+    }
+}
+
+#[async_trait]
+impl<E, S, C> ConfigSource<E, Decompressed<S>> for DecompressingSource<C, S>
+    where
+        E: Sync,
+        S: Read + Send,
+        C: ConfigSource<E, S> + Send + Sync {
+    async fn fetch(&self) -> Result<(Option<E>, Decompressed<S>)> {
+        let (version, raw) = self.inner.fetch().await?;
+        Ok((version, self.decompress(raw)?))
+    }
+
+    async fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, Decompressed<S>)>> {
+        match self.inner.fetch_if_newer(version).await? {
+            Some((version, raw)) => Ok(Some((version, self.decompress(raw)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+pub enum Decompressed<S: Read> {
+    Plain(Raw<S>),
+    Gzip(GzDecoder<Raw<S>>),
+    // Only ever accessed through &mut, the Mutex just makes the payload Sync.
+    Zstd(Mutex<zstd::Decoder<'static, Raw<S>>>),
+}
+
+impl<S: Read> Read for Decompressed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decompressed::Plain(raw) => raw.read(buf),
+            Decompressed::Gzip(decoder) => decoder.read(buf),
+            Decompressed::Zstd(decoder) => decoder.get_mut().unwrap_or_else(PoisonError::into_inner).read(buf),
+        }
+    }
+}
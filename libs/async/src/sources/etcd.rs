@@ -0,0 +1,173 @@
+pub use etcd_client::Client;
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::time::Duration;
+use async_trait::async_trait;
+use etcd_client::{GetOptions, KeyValue, WatchOptions};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const WATCH_RETRY: Duration = Duration::from_secs(1);
+
+pub struct EtcdConfigSource {
+    client: Client,
+    key: String,
+    trigger: Option<RefreshTrigger>,
+    watch: Option<JoinHandle<()>>,
+}
+
+impl EtcdConfigSource {
+    pub fn new<S: Into<String>>(client: Client, key: S) -> Result<EtcdConfigSource> {
+        Ok(EtcdConfigSource {
+            client,
+            key: key.into(),
+            trigger: None,
+            watch: None,
+        })
+    }
+
+    pub fn with_watch(mut self) -> EtcdConfigSource {
+        let trigger = RefreshTrigger::new();
+        self.watch = Some(tokio::spawn(
+            watch(self.client.clone(), self.key.clone(), false, trigger.clone())
+        ));
+        self.trigger = Some(trigger);
+        self
+    }
+}
+
+impl Drop for EtcdConfigSource {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.watch {
+            handle.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigSource<i64, Cursor<Vec<u8>>> for EtcdConfigSource {
+    async fn fetch(&self) -> Result<(Option<i64>, Cursor<Vec<u8>>)> {
+        let resp = self.client.clone().get(self.key.as_str(), None).await?;
+        match resp.kvs().first() {
+            Some(kv) => Ok((Some(kv.mod_revision()), Cursor::new(kv.value().to_vec()))),
+            None => Err(Error::new("Key not found")),
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &i64) -> Result<Option<(Option<i64>, Cursor<Vec<u8>>)>> {
+        let resp = self.client.clone()
+            .get(self.key.as_str(), Some(GetOptions::new().with_keys_only()))
+            .await?;
+
+        match resp.kvs().first() {
+            Some(kv) if kv.mod_revision() == *version => Ok(None),
+            Some(_) => self.fetch().await.map(Some),
+            None => Err(Error::new("Key not found")),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.trigger.clone()
+    }
+}
+
+pub struct EtcdPrefixConfigSource {
+    client: Client,
+    prefix: String,
+    trigger: Option<RefreshTrigger>,
+    watch: Option<JoinHandle<()>>,
+}
+
+impl EtcdPrefixConfigSource {
+    pub fn new<S: Into<String>>(client: Client, prefix: S) -> Result<EtcdPrefixConfigSource> {
+        Ok(EtcdPrefixConfigSource {
+            client,
+            prefix: prefix.into(),
+            trigger: None,
+            watch: None,
+        })
+    }
+
+    pub fn with_watch(mut self) -> EtcdPrefixConfigSource {
+        let trigger = RefreshTrigger::new();
+        self.watch = Some(tokio::spawn(
+            watch(self.client.clone(), self.prefix.clone(), true, trigger.clone())
+        ));
+        self.trigger = Some(trigger);
+        self
+    }
+
+    // Any put raises the highest mod revision under the prefix and any delete lowers the
+    // count, so together they change whenever the contents do.
+    fn get_version(kvs: &[KeyValue]) -> (i64, usize) {
+        let revision = kvs.iter().map(|kv| kv.mod_revision()).max().unwrap_or(0);
+        (revision, kvs.len())
+    }
+}
+
+impl Drop for EtcdPrefixConfigSource {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.watch {
+            handle.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigSource<(i64, usize), HashMap<String, Vec<u8>>> for EtcdPrefixConfigSource {
+    async fn fetch(&self) -> Result<(Option<(i64, usize)>, HashMap<String, Vec<u8>>)> {
+        let resp = self.client.clone()
+            .get(self.prefix.as_str(), Some(GetOptions::new().with_prefix()))
+            .await?;
+
+        let mut entries = HashMap::new();
+        for kv in resp.kvs() {
+            entries.insert(String::from(kv.key_str()?), kv.value().to_vec());
+        }
+
+        Ok((Some(EtcdPrefixConfigSource::get_version(resp.kvs())), entries))
+    }
+
+    async fn fetch_if_newer(&self, version: &(i64, usize)) -> Result<Option<(Option<(i64, usize)>, HashMap<String, Vec<u8>>)>> {
+        let resp = self.client.clone()
+            .get(self.prefix.as_str(), Some(GetOptions::new().with_prefix().with_keys_only()))
+            .await?;
+
+        if &EtcdPrefixConfigSource::get_version(resp.kvs()) == version {
+            return Ok(None);
+        }
+
+        self.fetch().await.map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.trigger.clone()
+    }
+}
+
+async fn watch(mut client: Client, key: String, prefix: bool, trigger: RefreshTrigger) {
+    loop {
+        let options = if prefix {
+            Some(WatchOptions::new().with_prefix())
+        } else {
+            None
+        };
+
+        // The watcher has to be held for as long as the stream is read, dropping it cancels the watch.
+        if let Ok((_watcher, mut stream)) = client.watch(key.as_str(), options).await {
+            while let Ok(Some(resp)) = stream.message().await {
+                if !resp.events().is_empty() {
+                    trigger.fire();
+                }
+            }
+        }
+
+        // The stream dropped, polling carries on at the fetch interval until it's re-established.
+        time::sleep(WATCH_RETRY).await;
+    }
+}
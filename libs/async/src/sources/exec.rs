@@ -0,0 +1,90 @@
+use std::ffi::{OsStr, OsString};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::process::Output;
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use mirror_cache_core::util::{Error, Result};
+use sha2::{Digest, Sha256};
+
+use crate::sources::sources::ConfigSource;
+
+// Runs a command on every fetch and serves its stdout. There's no way to ask a command whether its
+// output changed, so a SHA-256 hash of the output serves as the version and lets unchanged output
+// skip processing. Unlike std's hashers it's stable across releases, so versions saved by an
+// earlier build still match.
+pub struct ExecConfigSource {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+}
+
+impl ExecConfigSource {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> ExecConfigSource {
+        ExecConfigSource {
+            program: program.as_ref().to_os_string(),
+            args: vec![],
+            envs: vec![],
+            current_dir: None,
+        }
+    }
+
+    pub fn with_args<I: IntoIterator<Item=S>, S: AsRef<OsStr>>(mut self, args: I) -> ExecConfigSource {
+        self.args.extend(args.into_iter().map(|a| a.as_ref().to_os_string()));
+        self
+    }
+
+    pub fn with_env<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, value: V) -> ExecConfigSource {
+        self.envs.push((key.as_ref().to_os_string(), value.as_ref().to_os_string()));
+        self
+    }
+
+    pub fn with_current_dir<P: Into<PathBuf>>(mut self, dir: P) -> ExecConfigSource {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).envs(self.envs.iter().map(|(k, v)| (k, v)));
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    async fn run(&self) -> Result<(String, Vec<u8>)> {
+        let output = self.command().output().await?;
+        check_status(&output)?;
+
+        Ok((format!("{:x}", Sha256::digest(&output.stdout)), output.stdout))
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, Cursor<Vec<u8>>> for ExecConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let (hash, stdout) = self.run().await?;
+        Ok((Some(hash), Cursor::new(stdout)))
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let (hash, stdout) = self.run().await?;
+        if &hash == version {
+            return Ok(None);
+        }
+
+        Ok(Some((Some(hash), Cursor::new(stdout))))
+    }
+}
+
+fn check_status(output: &Output) -> Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(Error::new(format!("Command failed with {}: {}", output.status, stderr.trim()).as_str()))
+}
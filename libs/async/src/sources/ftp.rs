@@ -0,0 +1,100 @@
+pub use suppaftp::native_tls::TlsConnector;
+
+use std::io::Cursor;
+use async_trait::async_trait;
+
+use chrono::NaiveDateTime;
+use suppaftp::{NativeTlsConnector, NativeTlsFtpStream};
+use suppaftp::types::FileType;
+use tokio::task;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+pub struct FtpConfigSource {
+    target: FtpTarget,
+}
+
+impl FtpConfigSource {
+    pub fn new<S: Into<String>>(address: S, username: S, password: S, path: S) -> FtpConfigSource {
+        FtpConfigSource {
+            target: FtpTarget {
+                address: address.into(),
+                username: username.into(),
+                password: password.into(),
+                path: path.into(),
+                tls: None,
+            },
+        }
+    }
+
+    pub fn with_tls<S: Into<String>>(self, domain: S) -> Result<FtpConfigSource> {
+        Ok(self.with_tls_connector(TlsConnector::new()?, domain))
+    }
+
+    pub fn with_tls_connector<S: Into<String>>(mut self, connector: TlsConnector, domain: S) -> FtpConfigSource {
+        self.target.tls = Some((connector, domain.into()));
+        self
+    }
+
+    // The FTP client is blocking only, so the work is moved off the runtime's worker threads.
+    async fn fetch_newer_than(&self, version: Option<NaiveDateTime>) -> Result<Option<(Option<NaiveDateTime>, Cursor<Vec<u8>>)>> {
+        let target = self.target.clone();
+        task::spawn_blocking(move || target.fetch_newer_than(version)).await?
+    }
+}
+
+#[derive(Clone)]
+struct FtpTarget {
+    address: String,
+    username: String,
+    password: String,
+    path: String,
+    tls: Option<(TlsConnector, String)>,
+}
+
+impl FtpTarget {
+    fn connect(&self) -> Result<NativeTlsFtpStream> {
+        let mut stream = NativeTlsFtpStream::connect(self.address.as_str())?;
+        if let Some((connector, domain)) = &self.tls {
+            stream = stream.into_secure(NativeTlsConnector::from(connector.clone()), domain)?;
+        }
+
+        stream.login(self.username.as_str(), self.password.as_str())?;
+        stream.transfer_type(FileType::Binary)?;
+        Ok(stream)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn fetch_newer_than(&self, version: Option<NaiveDateTime>) -> Result<Option<(Option<NaiveDateTime>, Cursor<Vec<u8>>)>> {
+        let mut stream = self.connect()?;
+
+        // Servers that don't support MDTM get an unconditional fetch.
+        let modified = stream.mdtm(self.path.as_str()).ok();
+        if let (Some(current), Some(previous)) = (modified, version) {
+            if current <= previous {
+                let _ = stream.quit();
+                return Ok(None);
+            }
+        }
+
+        let content = stream.retr_as_buffer(self.path.as_str())?;
+        let _ = stream.quit();
+        Ok(Some((modified, content)))
+    }
+}
+
+#[async_trait]
+impl ConfigSource<NaiveDateTime, Cursor<Vec<u8>>> for FtpConfigSource {
+    async fn fetch(&self) -> Result<(Option<NaiveDateTime>, Cursor<Vec<u8>>)> {
+        match self.fetch_newer_than(None).await? {
+            Some(update) => Ok(update),
+            None => Err(Error::new("Unconditional fetch returned no content")),
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &NaiveDateTime) -> Result<Option<(Option<NaiveDateTime>, Cursor<Vec<u8>>)>> {
+        self.fetch_newer_than(Some(*version)).await
+    }
+}
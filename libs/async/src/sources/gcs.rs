@@ -0,0 +1,137 @@
+pub use google_cloud_storage::client::{Client, ClientConfig};
+#[cfg(feature = "gcs-pubsub")]
+pub use google_cloud_pubsub::subscription::Subscription;
+
+use std::io::Cursor;
+#[cfg(feature = "gcs-pubsub")]
+use std::time::Duration;
+use async_trait::async_trait;
+use google_cloud_storage::http::objects::download::Range;
+use google_cloud_storage::http::objects::get::GetObjectRequest;
+#[cfg(feature = "gcs-pubsub")]
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+#[cfg(feature = "gcs-pubsub")]
+use tokio::time;
+
+#[cfg(feature = "gcs-pubsub")]
+use mirror_cache_core::util::Error;
+use mirror_cache_core::util::{RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+#[cfg(feature = "gcs-pubsub")]
+const PULL_MAX_MESSAGES: i32 = 100;
+#[cfg(feature = "gcs-pubsub")]
+const PULL_RETRY: Duration = Duration::from_secs(1);
+
+pub struct GcsConfigSource {
+    client: Client,
+    bucket: String,
+    path: String,
+    trigger: Option<RefreshTrigger>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl GcsConfigSource {
+    pub fn new<S: Into<String>>(client: Client, bucket: S, path: S) -> GcsConfigSource {
+        GcsConfigSource {
+            client,
+            bucket: bucket.into(),
+            path: path.into(),
+            trigger: None,
+            watcher: None,
+        }
+    }
+
+    // Listens on a Pub/Sub subscription to the bucket's object change notifications, refreshing as
+    // soon as the object changes. Polling carries on at the fetch interval in case notifications
+    // are delayed or lost. The listener runs on the current tokio runtime, so this fails outside
+    // of one.
+    #[cfg(feature = "gcs-pubsub")]
+    pub fn with_pubsub_notifications(mut self, subscription: Subscription) -> Result<GcsConfigSource> {
+        let runtime = Handle::try_current()
+            .map_err(|e| Error::new(format!("Pub/Sub notifications need a tokio runtime: {}", e).as_str()))?;
+
+        let trigger = RefreshTrigger::new();
+        self.watcher = Some(runtime.spawn(
+            watch_subscription(subscription, self.bucket.clone(), self.path.clone(), trigger.clone())
+        ));
+        self.trigger = Some(trigger);
+        Ok(self)
+    }
+
+    fn request(&self, generation: Option<i64>) -> GetObjectRequest {
+        GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: self.path.clone(),
+            generation,
+            ..Default::default()
+        }
+    }
+
+    async fn fetch_generation(&self, generation: i64) -> Result<(Option<i64>, Cursor<Vec<u8>>)> {
+        let content = self.client
+            .download_object(&self.request(Some(generation)), &Range::default())
+            .await?;
+
+        Ok((Some(generation), Cursor::new(content)))
+    }
+}
+
+#[async_trait]
+impl ConfigSource<i64, Cursor<Vec<u8>>> for GcsConfigSource {
+    async fn fetch(&self) -> Result<(Option<i64>, Cursor<Vec<u8>>)> {
+        let object = self.client.get_object(&self.request(None)).await?;
+        self.fetch_generation(object.generation).await
+    }
+
+    async fn fetch_if_newer(&self, version: &i64) -> Result<Option<(Option<i64>, Cursor<Vec<u8>>)>> {
+        let object = self.client.get_object(&self.request(None)).await?;
+        if object.generation == *version {
+            return Ok(None);
+        }
+
+        self.fetch_generation(object.generation).await.map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.trigger.clone()
+    }
+}
+
+impl Drop for GcsConfigSource {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.watcher {
+            handle.abort();
+        }
+    }
+}
+
+// Every message is acknowledged whether or not it concerns the watched object, so the
+// subscription should be dedicated to this cache.
+#[cfg(feature = "gcs-pubsub")]
+async fn watch_subscription(subscription: Subscription, bucket: String, path: String, trigger: RefreshTrigger) {
+    loop {
+        let messages = match subscription.pull(PULL_MAX_MESSAGES, None).await {
+            Ok(messages) => messages,
+            Err(_) => {
+                time::sleep(PULL_RETRY).await;
+                continue;
+            }
+        };
+
+        let changed = messages.iter().any(|m| {
+            let attributes = &m.message.attributes;
+            attributes.get("bucketId") == Some(&bucket) && attributes.get("objectId") == Some(&path)
+        });
+        if changed {
+            trigger.fire();
+        }
+
+        for message in messages {
+            // Failed acks are redelivered and at worst cause a spare fetch.
+            let _ = message.ack().await;
+        }
+    }
+}
@@ -2,18 +2,36 @@ pub use octocrab::Octocrab;
 
 use std::io::Cursor;
 use async_trait::async_trait;
+use chrono::{Duration, TimeZone, Utc};
+use octocrab::models::repos::Tag;
+use octocrab::models::RateLimit;
+use octocrab::{FromResponse, Page};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, ETAG, IF_NONE_MATCH};
+use reqwest::{Client, Proxy, Response, StatusCode, Url};
+use semver::{Version, VersionReq};
 
 use mirror_cache_core::util::{Error, Result};
 
 use crate::sources::sources::ConfigSource;
 
+const RAW_MEDIA_TYPE: &str = "application/vnd.github.raw";
+const SECONDARY_RATE_LIMIT_BACKOFF: Duration = Duration::seconds(60);
+const USER_AGENT: &str = "mirror-cache";
+
+enum Target {
+    Branch(String),
+    // The highest tag satisfying the requirement, tags may carry a leading 'v'.
+    SemverTag(VersionReq),
+}
 
 pub struct GitHubConfigSource {
     client: Octocrab,
     owner: String,
     repo: String,
-    branch: String,
-    path: String
+    target: Target,
+    path: String,
+    max_size: Option<u64>,
+    proxied: Option<Client>,
 }
 
 impl GitHubConfigSource {
@@ -22,46 +40,240 @@ impl GitHubConfigSource {
             client: octocrab,
             owner: owner.into(),
             repo: repo.into(),
-            branch: branch.into(),
+            target: Target::Branch(branch.into()),
+            path: path.into(),
+            max_size: None,
+            proxied: None,
+        })
+    }
+
+    // Follows release tags rather than a branch, so config only changes when a new tag matching
+    // the requirement, for example "^2", is pushed. The tag name is used as the version.
+    pub fn from_semver_tags<S: Into<String>>(octocrab: Octocrab, owner: S, repo: S, requirement: &str, path: S) -> Result<GitHubConfigSource> {
+        Ok(GitHubConfigSource {
+            client: octocrab,
+            owner: owner.into(),
+            repo: repo.into(),
+            target: Target::SemverTag(VersionReq::parse(requirement)?),
             path: path.into(),
+            max_size: None,
+            proxied: None,
         })
     }
 
+    // Files larger than this fail the fetch, the download is abandoned as soon as it's exceeded.
+    pub fn with_max_size(mut self, bytes: u64) -> GitHubConfigSource {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    // Routes all requests through the proxy, which may be http://, https:// or socks5://, in place
+    // of any set through the environment. Octocrab can't be given a proxy, so requests go through
+    // a client of the source's own, authenticated with the personal access token if one is given.
+    pub fn with_proxy(mut self, url: &str, credentials: Option<(&str, &str)>, token: Option<&str>) -> Result<GitHubConfigSource> {
+        let mut proxy = Proxy::all(url)?;
+        if let Some((username, password)) = credentials {
+            proxy = proxy.basic_auth(username, password);
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(token) = token {
+            let mut auth = HeaderValue::from_str(format!("Bearer {}", token).as_str())?;
+            auth.set_sensitive(true);
+            headers.insert(AUTHORIZATION, auth);
+        }
+
+        self.proxied = Some(Client::builder()
+            .user_agent(USER_AGENT)
+            .default_headers(headers)
+            .proxy(proxy)
+            .build()?);
+        Ok(self)
+    }
+
+    async fn get(&self, url: Url, query: &[(&str, &str)], headers: HeaderMap) -> Result<Response> {
+        match &self.proxied {
+            Some(client) => Ok(client.get(url).query(query).headers(headers).send().await?),
+            None => self.client._get_with_headers(url, Some(query), Some(headers)).await.map_err(github_error),
+        }
+    }
+
+    async fn get_json<R: FromResponse>(&self, url: Url) -> Result<R> {
+        let resp = self.get(url, &[], HeaderMap::new()).await?;
+        let resp = octocrab::map_github_error(resp).await.map_err(github_error)?;
+        R::from_response(resp).await.map_err(github_error)
+    }
+
+    async fn tags(&self) -> Result<Vec<Tag>> {
+        let mut next = Some(self.client
+            .absolute_url(format!("repos/{}/{}/tags?per_page=100", self.owner, self.repo))
+            .map_err(github_error)?);
+
+        let mut tags = Vec::new();
+        while let Some(url) = next {
+            let mut page: Page<Tag> = self.get_json(url).await?;
+            tags.append(&mut page.take_items());
+            next = page.next;
+        }
+
+        Ok(tags)
+    }
+
+    async fn rate_limit(&self) -> Result<RateLimit> {
+        let url = self.client.absolute_url("rate_limit").map_err(github_error)?;
+        self.get_json(url).await
+    }
+
+    // Fetches the raw file, conditionally when given the ETag of an earlier response. Unchanged
+    // files cost a single 304, which GitHub doesn't count against the rate limit.
+    async fn get_file(&self, git_ref: &str, etag: Option<&String>) -> Result<Option<(String, Vec<u8>)>> {
+        let url = self.client
+            .absolute_url(format!("repos/{}/{}/contents/{}", self.owner, self.repo, self.path))
+            .map_err(github_error)?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(RAW_MEDIA_TYPE));
+        if let Some(etag) = etag {
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_str(etag)?);
+        }
+
+        let resp = self.get(url, &[("ref", git_ref)], headers).await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let resp = octocrab::map_github_error(resp).await.map_err(github_error)?;
+        let etag = match resp.headers().get(ETAG).map(|h| h.to_str()) {
+            Some(Ok(etag)) => String::from(etag),
+            _ => return Err(Error::new("GitHub response had no ETag")),
+        };
+
+        Ok(Some((etag, read_body(resp, self.max_size).await?)))
+    }
+
+    async fn get_file_unconditionally(&self, git_ref: &str) -> Result<(String, Vec<u8>)> {
+        match self.get_file(git_ref, None).await? {
+            Some(file) => Ok(file),
+            None => Err(Error::new("Unconditional fetch returned no content")),
+        }
+    }
+
+    async fn latest_tag(&self, requirement: &VersionReq) -> Result<String> {
+        let tags = self.tags().await?;
+
+        tags.into_iter()
+            .filter_map(|tag| {
+                let version = Version::parse(tag.name.strip_prefix('v').unwrap_or(&tag.name)).ok()?;
+                requirement.matches(&version).then_some((version, tag.name))
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, name)| name)
+            .ok_or_else(|| Error::new(format!("No tag matches {}", requirement).as_str()))
+    }
+
+    async fn fetch_latest(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        match &self.target {
+            Target::Branch(branch) => {
+                let (etag, content) = self.get_file_unconditionally(branch).await?;
+                Ok((Some(etag), Cursor::new(content)))
+            }
+            Target::SemverTag(requirement) => {
+                let tag = self.latest_tag(requirement).await?;
+                let (_, content) = self.get_file_unconditionally(&tag).await?;
+                Ok((Some(tag), Cursor::new(content)))
+            }
+        }
+    }
+
+    async fn fetch_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        match &self.target {
+            Target::Branch(branch) => {
+                let file = self.get_file(branch, Some(version)).await?;
+                Ok(file.map(|(etag, content)| (Some(etag), Cursor::new(content))))
+            }
+            Target::SemverTag(requirement) => {
+                let tag = self.latest_tag(requirement).await?;
+                if &tag == version {
+                    return Ok(None);
+                }
+
+                let (_, content) = self.get_file_unconditionally(&tag).await?;
+                Ok(Some((Some(tag), Cursor::new(content))))
+            }
+        }
+    }
+
+    // Rate limited requests get a provisional retry time from github_error(). Primary limits reset
+    // at a known time though, which the rate limit endpoint reports without counting against it.
+    async fn with_reset_time<T>(&self, result: Result<T>) -> Result<T> {
+        match result {
+            Err(e) if e.retry_at().is_some() => {
+                let limits = self.rate_limit().await;
+                match limits {
+                    Ok(limits) if limits.resources.core.remaining == 0 => {
+                        match Utc.timestamp_opt(limits.resources.core.reset as i64, 0).single() {
+                            Some(reset) => Err(e.with_retry_at(reset)),
+                            None => Err(e),
+                        }
+                    }
+                    _ => Err(e),
+                }
+            }
+            other => other,
+        }
+    }
 }
 
 #[async_trait]
 impl ConfigSource<String, Cursor<Vec<u8>>> for GitHubConfigSource {
     async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
-        let handler = self.client.repos(self.owner.clone(), self.repo.clone());
-        let content_items = handler.get_content()
-                .r#ref(self.branch.clone())
-                .path(self.path.clone())
-                .send().await?;
-
-        if let Some(content_wrapper) = content_items.items.first() {
-            if let Some(raw_content) = content_wrapper.decoded_content() {
-                Ok((Some(content_wrapper.sha.clone()), Cursor::new(raw_content.into())))
-            } else {
-                Err(Error::new("File had no content, or it failed to decode"))
-            }
-        } else {
-            Err(Error::new("File not found"))
-        }
+        let result = self.fetch_latest().await;
+        self.with_reset_time(result).await
     }
 
     async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
-        let handler = self.client.repos(self.owner.clone(), self.repo.clone());
-        let commits = handler.list_commits()
-                .branch(self.branch.clone())
-                .path(self.path.clone())
-                .send().await?;
-
-        if let Some(last_commit) = commits.items.first() {
-            if &last_commit.sha == version {
-                return Ok(None);
+        let result = self.fetch_newer(version).await;
+        self.with_reset_time(result).await
+    }
+}
+
+// Reads the body a chunk at a time so an oversized file is never held in memory in full.
+async fn read_body(mut resp: Response, max_size: Option<u64>) -> Result<Vec<u8>> {
+    let too_large = |max_size: u64| {
+        Error::new(format!("File exceeds the maximum size of {} bytes", max_size).as_str())
+    };
+
+    if let (Some(max_size), Some(length)) = (max_size, resp.content_length()) {
+        if length > max_size {
+            return Err(too_large(max_size));
+        }
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if let Some(max_size) = max_size {
+            if body.len() as u64 > max_size {
+                return Err(too_large(max_size));
             }
         }
+    }
+
+    Ok(body)
+}
 
-        self.fetch().await.map(Some)
+// GitHub reports both primary and secondary rate limiting as errors mentioning the rate limit. It
+// asks that clients wait at least a minute after hitting a secondary limit, so that's the default.
+fn github_error(err: octocrab::Error) -> Error {
+    match &err {
+        octocrab::Error::GitHub { source, .. } => {
+            let error = Error::new(source.message.as_str());
+            if source.message.to_lowercase().contains("rate limit") {
+                error.with_retry_at(Utc::now() + SECONDARY_RATE_LIMIT_BACKOFF)
+            } else {
+                error
+            }
+        }
+        _ => Error::from(err),
     }
-}
\ No newline at end of file
+}
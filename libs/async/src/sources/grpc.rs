@@ -0,0 +1,173 @@
+pub use tonic::transport::Channel;
+pub use tonic::Status;
+
+use std::future::Future;
+use std::io::Cursor;
+use std::time::Duration;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const STREAM_RETRY: Duration = Duration::from_secs(1);
+const FIRST_MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The latest message received, and why the stream is down if it is.
+#[derive(Default)]
+struct Latest {
+    message: Option<(String, Vec<u8>)>,
+    down: Option<Status>,
+}
+
+// Wraps a unary RPC that returns a version alongside the raw config. The call is given the version
+// currently held, if any, so servers that support it can answer with the same version and no body
+// rather than resending unchanged data.
+pub struct GrpcConfigSource<F> {
+    channel: Channel,
+    call: F,
+}
+
+impl<F, Fut> GrpcConfigSource<F>
+    where
+        F: Fn(Channel, Option<String>) -> Fut + Send + Sync,
+        Fut: Future<Output=std::result::Result<(String, Vec<u8>), Status>> + Send {
+    pub fn new(channel: Channel, call: F) -> GrpcConfigSource<F> {
+        GrpcConfigSource {
+            channel,
+            call,
+        }
+    }
+}
+
+#[async_trait]
+impl<F, Fut> ConfigSource<String, Cursor<Vec<u8>>> for GrpcConfigSource<F>
+    where
+        F: Fn(Channel, Option<String>) -> Fut + Send + Sync,
+        Fut: Future<Output=std::result::Result<(String, Vec<u8>), Status>> + Send {
+    async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let (version, body) = (self.call)(self.channel.clone(), None).await?;
+        Ok((Some(version), Cursor::new(body)))
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let (latest, body) = (self.call)(self.channel.clone(), Some(version.clone())).await?;
+        if &latest == version {
+            return Ok(None);
+        }
+
+        Ok(Some((Some(latest), Cursor::new(body))))
+    }
+}
+
+// Holds open a server-streaming RPC in the background, each message received replaces the data
+// and fires the refresh trigger so the cache picks it up immediately. The stream is re-opened
+// if it ends or fails, and fetches fail until it is, so staleness and failure callbacks notice a
+// dead stream.
+pub struct GrpcStreamingConfigSource {
+    latest: watch::Receiver<Latest>,
+    trigger: RefreshTrigger,
+    stream: JoinHandle<()>,
+    first_message_timeout: Duration,
+}
+
+impl GrpcStreamingConfigSource {
+    pub fn new<F, Fut, St>(channel: Channel, open: F) -> GrpcStreamingConfigSource
+        where
+            F: Fn(Channel) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output=std::result::Result<St, Status>> + Send + 'static,
+            St: Stream<Item=std::result::Result<(String, Vec<u8>), Status>> + Send + Unpin + 'static {
+        let (tx, latest) = watch::channel(Latest::default());
+        let trigger = RefreshTrigger::new();
+
+        GrpcStreamingConfigSource {
+            latest,
+            trigger: trigger.clone(),
+            stream: tokio::spawn(listen(channel, open, tx, trigger)),
+            first_message_timeout: FIRST_MESSAGE_TIMEOUT,
+        }
+    }
+
+    // How long a fetch waits for the first message before failing, so build() can't hang on a
+    // stream that opens but never delivers. Thirty seconds by default.
+    pub fn with_first_message_timeout(mut self, timeout: Duration) -> GrpcStreamingConfigSource {
+        self.first_message_timeout = timeout;
+        self
+    }
+}
+
+impl Drop for GrpcStreamingConfigSource {
+    fn drop(&mut self) {
+        self.stream.abort();
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, Cursor<Vec<u8>>> for GrpcStreamingConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        // Nothing can be returned until the first message, or failure, comes in.
+        let mut latest = self.latest.clone();
+        let waited = time::timeout(self.first_message_timeout, latest.wait_for(|l| l.message.is_some() || l.down.is_some())).await;
+        let Ok(current) = waited else {
+            return Err(Error::new(format!("No message arrived on the stream within {:?}", self.first_message_timeout).as_str()));
+        };
+        let current = current?;
+
+        match (&current.down, &current.message) {
+            (Some(status), _) => Err(status.clone().into()),
+            (None, Some((version, body))) => Ok((Some(version.clone()), Cursor::new(body.clone()))),
+            (None, None) => Err(Error::new("Stream closed before receiving data")),
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        {
+            let latest = self.latest.borrow();
+            if let Some(status) = &latest.down {
+                return Err(status.clone().into());
+            }
+
+            if matches!(&latest.message, Some((latest, _)) if latest == version) {
+                return Ok(None);
+            }
+        }
+
+        self.fetch().await.map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+async fn listen<F, Fut, St>(channel: Channel, open: F, tx: watch::Sender<Latest>, trigger: RefreshTrigger)
+    where
+        F: Fn(Channel) -> Fut,
+        Fut: Future<Output=std::result::Result<St, Status>>,
+        St: Stream<Item=std::result::Result<(String, Vec<u8>), Status>> + Unpin {
+    loop {
+        let status = match open(channel.clone()).await {
+            Ok(mut stream) => {
+                tx.send_if_modified(|latest| latest.down.take().is_some());
+                loop {
+                    match stream.next().await {
+                        Some(Ok(message)) => {
+                            tx.send_modify(|latest| latest.message = Some(message));
+                            trigger.fire();
+                        }
+                        Some(Err(status)) => break status,
+                        None => break Status::unavailable("Stream ended"),
+                    }
+                }
+            }
+            Err(status) => status,
+        };
+
+        tx.send_modify(|latest| latest.down = Some(status));
+        time::sleep(STREAM_RETRY).await;
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{Cursor, Read};
+use std::marker::PhantomData;
+use std::sync::{Mutex, PoisonError};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+use mirror_cache_core::util::{RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+// Versions the payload of another source by its SHA-256, for backends with no usable version of
+// their own. The inner source is always fetched in full, but unchanged payloads are recognized and
+// skip processing. The hash is hex encoded.
+pub struct HashVersionedSource<C, E, S> {
+    inner: C,
+    _phantom_e: PhantomData<fn() -> E>,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, E, S: Read> HashVersionedSource<C, E, S> {
+    pub fn new(inner: C) -> HashVersionedSource<C, E, S> {
+        HashVersionedSource {
+            inner,
+            _phantom_e: PhantomData,
+            _phantom_s: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<E, S, C> ConfigSource<String, Cursor<Vec<u8>>> for HashVersionedSource<C, E, S>
+    where
+        E: Sync,
+        S: Read + Send,
+        C: ConfigSource<E, S> + Send + Sync {
+    async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let (_, raw) = self.inner.fetch().await?;
+        let (hash, payload) = hash(raw)?;
+        Ok((Some(hash), Cursor::new(payload)))
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let (_, raw) = self.inner.fetch().await?;
+        let (hash, payload) = hash(raw)?;
+        if &hash == version {
+            Ok(None)
+        } else {
+            Ok(Some((Some(hash), Cursor::new(payload))))
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+// The payload last handed to the cache, and the newest version found to have identical content.
+#[derive(Clone)]
+struct Handed<E> {
+    version: E,
+    hash: String,
+    latest: E,
+}
+
+// Keeps the versions of another source, but recognizes a new version whose payload is identical
+// to the last one, such as a touched file or a re-upload, and reports it as unchanged so it isn't
+// reprocessed and update callbacks stay meaningful. Later checks are made against the newest
+// version seen, so an identical payload is only downloaded once.
+pub struct ContentHashedSource<C, E, S> {
+    inner: C,
+    handed: Mutex<Option<Handed<E>>>,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, E: Clone, S: Read> ContentHashedSource<C, E, S> {
+    pub fn new(inner: C) -> ContentHashedSource<C, E, S> {
+        ContentHashedSource {
+            inner,
+            handed: Mutex::new(None),
+            _phantom_s: PhantomData,
+        }
+    }
+
+    fn hand_over(&self, version: Option<E>, hash: String, payload: Vec<u8>) -> (Option<E>, Cursor<Vec<u8>>) {
+        *self.handed.lock().unwrap_or_else(PoisonError::into_inner) = version.clone()
+            .map(|v| Handed { version: v.clone(), hash, latest: v });
+        (version, Cursor::new(payload))
+    }
+}
+
+#[async_trait]
+impl<E, S, C> ConfigSource<E, Cursor<Vec<u8>>> for ContentHashedSource<C, E, S>
+    where
+        E: Clone + PartialEq + Send + Sync,
+        S: Read + Send,
+        C: ConfigSource<E, S> + Send + Sync {
+    async fn fetch(&self) -> Result<(Option<E>, Cursor<Vec<u8>>)> {
+        let (version, raw) = self.inner.fetch().await?;
+        let (hash, payload) = hash(raw)?;
+        Ok(self.hand_over(version, hash, payload))
+    }
+
+    async fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, Cursor<Vec<u8>>)>> {
+        // Only trusted while the cache is serving what we last handed it, it may have failed to
+        // process that, in which case it's fetched and handed over again.
+        let handed = self.handed.lock().unwrap_or_else(PoisonError::into_inner).clone()
+            .filter(|h| &h.version == version);
+        let check = handed.as_ref().map_or(version, |h| &h.latest);
+
+        let (new_version, raw) = match self.inner.fetch_if_newer(check).await? {
+            Some(update) => update,
+            None => return Ok(None),
+        };
+
+        let (hash, payload) = hash(raw)?;
+        if let (Some(mut handed), Some(new_version)) = (handed, &new_version) {
+            if hash == handed.hash {
+                handed.latest = new_version.clone();
+                *self.handed.lock().unwrap_or_else(PoisonError::into_inner) = Some(handed);
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(self.hand_over(new_version, hash, payload)))
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+fn hash<S: Read>(mut raw: S) -> Result<(String, Vec<u8>)> {
+    let mut payload = Vec::new();
+    raw.read_to_end(&mut payload)?;
+    Ok((format!("{:x}", Sha256::digest(&payload)), payload))
+}
@@ -1,23 +1,182 @@
 pub use reqwest::{Client, Response};
 
+use std::cmp::min;
+use std::future::poll_fn;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
 use async_trait::async_trait;
-use reqwest::StatusCode;
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use reqwest::{Certificate, ClientBuilder, Identity, Method, Proxy, RequestBuilder, StatusCode};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::runtime::Handle;
+use tokio::{task, time};
+use mirror_cache_core::processors::bytes::BytesPayload;
 use mirror_cache_core::util::{Error, Result};
 use crate::sources::sources::ConfigSource;
 
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+type HeaderProvider = Box<dyn Fn() -> Result<Vec<(String, String)>> + Send + Sync>;
+
+enum Auth {
+    Bearer(String),
+    Basic(String, Option<String>),
+}
+
 pub struct HttpConfigSource {
     client: Client,
     url: String,
+    head_precheck: bool,
+    headers: Vec<(String, String)>,
+    auth: Option<Auth>,
+    header_provider: Option<HeaderProvider>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    max_size: Option<u64>,
 }
 
 impl HttpConfigSource {
+    // Builds the client as well as the source, for endpoints that need client certificates or
+    // custom trust roots.
+    pub fn builder<S: Into<String>>(url: S) -> HttpConfigSourceBuilder {
+        HttpConfigSourceBuilder {
+            client: Client::builder(),
+            url: url.into(),
+        }
+    }
+
     pub fn new(client: Client, url: String) -> HttpConfigSource {
         HttpConfigSource {
             client,
             url,
+            head_precheck: false,
+            headers: vec![],
+            auth: None,
+            header_provider: None,
+            max_retries: 0,
+            retry_backoff: Duration::ZERO,
+            max_size: None,
+        }
+    }
+
+    pub fn with_header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> HttpConfigSource {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_bearer_token<S: Into<String>>(mut self, token: S) -> HttpConfigSource {
+        self.auth = Some(Auth::Bearer(token.into()));
+        self
+    }
+
+    pub fn with_basic_auth<S: Into<String>>(mut self, username: S, password: Option<S>) -> HttpConfigSource {
+        self.auth = Some(Auth::Basic(username.into(), password.map(Into::into)));
+        self
+    }
+
+    // Called before every request, for headers that change over time such as short-lived tokens.
+    pub fn with_header_provider<F>(mut self, provider: F) -> HttpConfigSource
+        where F: Fn() -> Result<Vec<(String, String)>> + Send + Sync + 'static {
+        self.header_provider = Some(Box::new(provider));
+        self
+    }
+
+    // For servers that ignore If-Modified-Since on GET but report Last-Modified correctly on HEAD.
+    pub fn with_head_precheck(mut self) -> HttpConfigSource {
+        self.head_precheck = true;
+        self
+    }
+
+    // Server errors, timeouts and failures to connect are retried up to max_retries times before
+    // the fetch fails, waiting initial_backoff before the first retry and doubling it each time, up
+    // to five minutes.
+    pub fn with_retries(mut self, max_retries: u32, initial_backoff: Duration) -> HttpConfigSource {
+        self.max_retries = max_retries;
+        self.retry_backoff = initial_backoff;
+        self
+    }
+
+    // Responses declaring a larger Content-Length fail the fetch before their body is read. Those
+    // without one, such as chunked responses, fail as soon as more than the limit has been read
+    // through streaming() or buffered(). The plain Response is handed over as is.
+    pub fn with_max_size(mut self, bytes: u64) -> HttpConfigSource {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    // Hands the body to the processor as it arrives rather than as a whole, for datasets too
+    // large to comfortably hold twice.
+    pub fn streaming(self) -> HttpStreamingConfigSource {
+        HttpStreamingConfigSource {
+            inner: self,
+        }
+    }
+
+    // Reads the whole body before handing it over, as a BytesPayload processors can parse in
+    // place without copying it again.
+    pub fn buffered(self) -> HttpBufferedConfigSource {
+        HttpBufferedConfigSource {
+            inner: self,
         }
     }
 
+    async fn send(&self, req: RequestBuilder) -> Result<Response> {
+        let mut backoff = self.retry_backoff;
+        for _ in 0..self.max_retries {
+            let Some(attempt) = req.try_clone() else {
+                break;
+            };
+
+            match attempt.send().await {
+                Ok(resp) if !resp.status().is_server_error() => return Ok(resp),
+                Err(err) if !(err.is_connect() || err.is_timeout()) => return Err(err.into()),
+                _ => {}
+            }
+
+            time::sleep(backoff.min(MAX_RETRY_BACKOFF)).await;
+            backoff = backoff.saturating_mul(2);
+        }
+
+        Ok(req.send().await?)
+    }
+
+    fn check_size(&self, resp: &Response) -> Result<()> {
+        match (self.max_size, resp.content_length()) {
+            (Some(max_size), Some(length)) if length > max_size => Err(Error::new(
+                format!("Response of {} bytes exceeds the maximum size of {} bytes", length, max_size).as_str()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn request(&self, method: Method) -> Result<RequestBuilder> {
+        let mut req = self.client.request(method, self.url.as_str());
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+
+        req = match &self.auth {
+            Some(Auth::Bearer(token)) => req.bearer_auth(token),
+            Some(Auth::Basic(username, password)) => req.basic_auth(username, password.as_ref()),
+            None => req,
+        };
+
+        if let Some(provider) = &self.header_provider {
+            for (name, value) in provider()? {
+                req = req.header(name, value);
+            }
+        }
+
+        Ok(req)
+    }
+
     fn get_version(resp: &Response) -> Option<String> {
         let option = resp.headers()
             .get("Last-Modified")
@@ -30,12 +189,71 @@ impl HttpConfigSource {
     }
 }
 
+pub struct HttpConfigSourceBuilder {
+    client: ClientBuilder,
+    url: String,
+}
+
+impl HttpConfigSourceBuilder {
+    // A PEM encoded certificate chain and PKCS#8 private key presented for mutual TLS.
+    pub fn with_identity_pem(mut self, cert_chain: &[u8], key: &[u8]) -> Result<HttpConfigSourceBuilder> {
+        self.client = self.client.identity(Identity::from_pkcs8_pem(cert_chain, key)?);
+        Ok(self)
+    }
+
+    pub fn with_identity_pkcs12(mut self, der: &[u8], password: &str) -> Result<HttpConfigSourceBuilder> {
+        self.client = self.client.identity(Identity::from_pkcs12_der(der, password)?);
+        Ok(self)
+    }
+
+    // Trusted in addition to the system roots unless with_only_custom_roots() is also set.
+    pub fn with_root_certificate_pem(mut self, pem: &[u8]) -> Result<HttpConfigSourceBuilder> {
+        self.client = self.client.add_root_certificate(Certificate::from_pem(pem)?);
+        Ok(self)
+    }
+
+    pub fn with_only_custom_roots(mut self) -> HttpConfigSourceBuilder {
+        self.client = self.client.tls_built_in_root_certs(false);
+        self
+    }
+
+    // Connects to the given address for requests to the hostname, which is still used for SNI and
+    // certificate validation. Useful when the endpoint is reached by IP or through a tunnel.
+    pub fn with_sni_override(mut self, hostname: &str, addr: SocketAddr) -> HttpConfigSourceBuilder {
+        self.client = self.client.resolve(hostname, addr);
+        self
+    }
+
+    // Routes all requests through the proxy, which may be http://, https:// or socks5://, in
+    // place of any set through the environment.
+    pub fn with_proxy(mut self, url: &str, credentials: Option<(&str, &str)>) -> Result<HttpConfigSourceBuilder> {
+        let mut proxy = Proxy::all(url)?;
+        if let Some((username, password)) = credentials {
+            proxy = proxy.basic_auth(username, password);
+        }
+
+        self.client = self.client.proxy(proxy);
+        Ok(self)
+    }
+
+    // Ignores proxies set through the environment.
+    pub fn with_no_proxy(mut self) -> HttpConfigSourceBuilder {
+        self.client = self.client.no_proxy();
+        self
+    }
+
+    pub fn build(self) -> Result<HttpConfigSource> {
+        Ok(HttpConfigSource::new(self.client.build()?, self.url))
+    }
+}
+
 #[async_trait]
 impl ConfigSource<String, Response> for HttpConfigSource {
     async fn fetch(&self) -> Result<(Option<String>, Response)> {
-        let resp = self.client.get(self.url.as_str()).send().await?;
+        let resp = self.send(self.request(Method::GET)?).await?;
 
         if resp.status().is_success() {
+            self.check_size(&resp)?;
             Ok((HttpConfigSource::get_version(&resp), resp))
         } else {
             Err(Error::new(format!("Fetch failed. Status: {}", resp.status().as_str()).as_str()))
@@ -43,11 +261,24 @@ impl ConfigSource<String, Response> for HttpConfigSource {
     }
 
     async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Response)>> {
-        let resp = self.client.get(self.url.as_str())
-            .header("If-Modified-Since", version)
-            .send().await?;
+        if self.head_precheck {
+            let head = self.send(self.request(Method::HEAD)?).await?;
+            if !head.status().is_success() {
+                return Err(Error::new(format!("Precheck failed. Status: {}", head.status().as_str()).as_str()));
+            }
+
+            if HttpConfigSource::get_version(&head).as_ref() == Some(version) {
+                return Ok(None);
+            }
+
+            return self.fetch().await.map(Some);
+        }
+
+        let req = self.request(Method::GET)?.header("If-Modified-Since", version);
+        let resp = self.send(req).await?;
 
         if resp.status().is_success() {
+            self.check_size(&resp)?;
             Ok(Some((HttpConfigSource::get_version(&resp), resp)))
         } else if resp.status() == StatusCode::NOT_MODIFIED {
             Ok(None)
@@ -56,3 +287,135 @@ impl ConfigSource<String, Response> for HttpConfigSource {
         }
     }
 }
+
+pub struct HttpStreamingConfigSource {
+    inner: HttpConfigSource,
+}
+
+#[async_trait]
+impl ConfigSource<String, HttpBody> for HttpStreamingConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, HttpBody)> {
+        let (version, resp) = self.inner.fetch().await?;
+        Ok((version, HttpBody::new(resp, self.inner.max_size)))
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, HttpBody)>> {
+        let fetched = self.inner.fetch_if_newer(version).await?;
+        Ok(fetched.map(|(version, resp)| (version, HttpBody::new(resp, self.inner.max_size))))
+    }
+}
+
+pub struct HttpBufferedConfigSource {
+    inner: HttpConfigSource,
+}
+
+#[async_trait]
+impl ConfigSource<String, BytesPayload> for HttpBufferedConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, BytesPayload)> {
+        let (version, resp) = self.inner.fetch().await?;
+        Ok((version, read_fully(HttpBody::new(resp, self.inner.max_size)).await?))
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, BytesPayload)>> {
+        match self.inner.fetch_if_newer(version).await? {
+            Some((version, resp)) => Ok(Some((version, read_fully(HttpBody::new(resp, self.inner.max_size)).await?))),
+            None => Ok(None),
+        }
+    }
+}
+
+// Reads through an HttpBody, so buffering can't get around the maximum size.
+async fn read_fully(mut body: HttpBody) -> Result<BytesPayload> {
+    let mut bytes = BytesMut::new();
+    while let Some(chunk) = body.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+
+    Ok(BytesPayload::new(bytes.freeze()))
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+
+// A response body read a chunk at a time, as a Stream, an AsyncRead, or a Read for the line
+// processors. Read blocks the calling worker with block_in_place, so it needs the multi-threaded
+// runtime. Reading fails once more than the source's maximum size has arrived.
+pub struct HttpBody {
+    // Only ever accessed through &mut, the Mutex just makes the body Sync.
+    stream: Mutex<ByteStream>,
+    chunk: Bytes,
+    received: u64,
+    max_size: Option<u64>,
+}
+
+impl HttpBody {
+    fn new(resp: Response, max_size: Option<u64>) -> HttpBody {
+        HttpBody {
+            stream: Mutex::new(Box::pin(resp.bytes_stream())),
+            chunk: Bytes::new(),
+            received: 0,
+            max_size,
+        }
+    }
+
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes>>> {
+        let next = ready!(self.stream.get_mut().unwrap_or_else(PoisonError::into_inner).as_mut().poll_next(cx));
+        Poll::Ready(next.map(|chunk| {
+            let chunk = chunk?;
+            self.received += chunk.len() as u64;
+            match self.max_size {
+                Some(max_size) if self.received > max_size => Err(Error::new(
+                    format!("Response exceeds the maximum size of {} bytes", max_size).as_str()
+                )),
+                _ => Ok(chunk),
+            }
+        }))
+    }
+
+    fn take_into(&mut self, buf: &mut [u8]) -> usize {
+        let len = min(buf.len(), self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        len
+    }
+}
+
+impl Stream for HttpBody {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes>>> {
+        if !self.chunk.is_empty() {
+            return Poll::Ready(Some(Ok(mem::take(&mut self.chunk))));
+        }
+
+        self.poll_chunk(cx)
+    }
+}
+
+impl AsyncRead for HttpBody {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        while self.chunk.is_empty() {
+            match ready!(self.poll_chunk(cx)) {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                Some(Err(err)) => return Poll::Ready(Err(io::Error::other(err.to_string()))),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = self.take_into(buf.initialize_unfilled());
+        buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Read for HttpBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match task::block_in_place(|| Handle::current().block_on(poll_fn(|cx| self.poll_chunk(cx)))) {
+                Some(Ok(chunk)) => self.chunk = chunk,
+                Some(Err(err)) => return Err(io::Error::other(err.to_string())),
+                None => return Ok(0),
+            }
+        }
+
+        Ok(self.take_into(buf))
+    }
+}
@@ -0,0 +1,83 @@
+pub use kube::Client;
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::Api;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+pub struct K8sConfigMapSource {
+    api: Api<ConfigMap>,
+    name: String,
+}
+
+impl K8sConfigMapSource {
+    pub fn new<S: Into<String>>(client: Client, namespace: S, name: S) -> Result<K8sConfigMapSource> {
+        Ok(K8sConfigMapSource {
+            api: Api::namespaced(client, namespace.into().as_str()),
+            name: name.into(),
+        })
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, BTreeMap<String, String>> for K8sConfigMapSource {
+    async fn fetch(&self) -> Result<(Option<String>, BTreeMap<String, String>)> {
+        let config_map = self.api.get(self.name.as_str()).await?;
+        Ok((config_map.metadata.resource_version, config_map.data.unwrap_or_default()))
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, BTreeMap<String, String>)>> {
+        let meta = self.api.get_metadata(self.name.as_str()).await?;
+        if meta.metadata.resource_version.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        self.fetch().await.map(Some)
+    }
+}
+
+pub struct K8sConfigMapKeySource {
+    api: Api<ConfigMap>,
+    name: String,
+    key: String,
+}
+
+impl K8sConfigMapKeySource {
+    pub fn new<S: Into<String>>(client: Client, namespace: S, name: S, key: S) -> Result<K8sConfigMapKeySource> {
+        Ok(K8sConfigMapKeySource {
+            api: Api::namespaced(client, namespace.into().as_str()),
+            name: name.into(),
+            key: key.into(),
+        })
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, Cursor<Vec<u8>>> for K8sConfigMapKeySource {
+    async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let config_map = self.api.get(self.name.as_str()).await?;
+        let version = config_map.metadata.resource_version;
+
+        if let Some(value) = config_map.data.and_then(|mut data| data.remove(&self.key)) {
+            Ok((version, Cursor::new(value.into_bytes())))
+        } else if let Some(value) = config_map.binary_data.and_then(|mut data| data.remove(&self.key)) {
+            Ok((version, Cursor::new(value.0)))
+        } else {
+            Err(Error::new(format!("Key '{}' not found in ConfigMap", self.key).as_str()))
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let meta = self.api.get_metadata(self.name.as_str()).await?;
+        if meta.metadata.resource_version.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        self.fetch().await.map(Some)
+    }
+}
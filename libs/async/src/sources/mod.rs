@@ -1,10 +1,78 @@
 pub mod sources;
+pub mod channel;
+pub mod wrappers;
+
+#[cfg(feature = "archive")]
+pub mod archive;
+
+#[cfg(feature = "azure-app-config")]
+pub mod azure_app_config;
+
+#[cfg(feature = "azure-blob")]
+pub mod azure_blob;
+
+#[cfg(feature = "bitbucket")]
+pub mod bitbucket;
+
+#[cfg(feature = "decompress")]
+pub mod decompress;
+
+#[cfg(feature = "encrypted")]
+pub mod encrypted;
+
+#[cfg(feature = "etcd")]
+pub mod etcd;
+
+#[cfg(feature = "exec")]
+pub mod exec;
+
+#[cfg(feature = "firestore")]
+pub mod firestore;
+
+#[cfg(feature = "ftp")]
+pub mod ftp;
+
+#[cfg(feature = "gcs")]
+pub mod gcs;
 
 #[cfg(feature = "github")]
 pub mod github;
 
+#[cfg(feature = "gitlab")]
+pub mod gitlab;
+
+#[cfg(feature = "graphql")]
+pub mod graphql;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+#[cfg(feature = "hash-version")]
+pub mod hash_versioned;
+
 #[cfg(feature = "http")]
 pub mod http;
 
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes;
+
+#[cfg(feature = "mysql")]
+pub mod mysql;
+
 #[cfg(feature = "s3")]
-pub mod s3;
\ No newline at end of file
+pub mod s3;
+
+#[cfg(feature = "s3-sqs")]
+pub mod sqs;
+
+#[cfg(feature = "secrets-manager")]
+pub mod secrets_manager;
+
+#[cfg(feature = "sftp")]
+pub mod sftp;
+
+#[cfg(feature = "sse")]
+pub mod sse;
+
+#[cfg(feature = "watch")]
+pub mod watched_file;
\ No newline at end of file
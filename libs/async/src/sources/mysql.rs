@@ -0,0 +1,63 @@
+pub use sqlx::mysql::{MySqlPool, MySqlRow};
+
+use async_trait::async_trait;
+use sqlx::Row;
+
+use mirror_cache_core::util::Result;
+
+use crate::sources::sources::ConfigSource;
+
+pub struct MySqlConfigSource {
+    pool: MySqlPool,
+    query: String,
+    version_query: Option<String>,
+}
+
+impl MySqlConfigSource {
+    pub fn new<S: Into<String>>(pool: MySqlPool, query: S) -> MySqlConfigSource {
+        MySqlConfigSource {
+            pool,
+            query: query.into(),
+            version_query: None,
+        }
+    }
+
+    // The version query should return a single value, read as a string. Something like
+    // `SELECT CAST(MAX(updated_at) AS CHAR) FROM my_config`. Without one every fetch is
+    // unconditional.
+    pub fn with_version_query<S: Into<String>>(mut self, version_query: S) -> MySqlConfigSource {
+        self.version_query = Some(version_query.into());
+        self
+    }
+
+    async fn current_version(&self) -> Result<Option<String>> {
+        match &self.version_query {
+            Some(q) => {
+                let row = sqlx::query(q.as_str()).fetch_one(&self.pool).await?;
+                Ok(row.try_get::<Option<String>, _>(0)?)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn fetch_rows(&self) -> Result<Vec<MySqlRow>> {
+        Ok(sqlx::query(self.query.as_str()).fetch_all(&self.pool).await?)
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, Vec<MySqlRow>> for MySqlConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, Vec<MySqlRow>)> {
+        let version = self.current_version().await?;
+        Ok((version, self.fetch_rows().await?))
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Vec<MySqlRow>)>> {
+        let current = self.current_version().await?;
+        if current.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        Ok(Some((current, self.fetch_rows().await?)))
+    }
+}
@@ -1,25 +1,102 @@
 pub use aws_sdk_s3::Client;
 
 use async_trait::async_trait;
+use aws_sdk_s3::config;
+use aws_sdk_s3::config::{Credentials, Region};
 use aws_sdk_s3::primitives::{ByteStream, DateTime};
 use aws_smithy_http::result::SdkError;
-use mirror_cache_core::util::Result;
+use tokio::task::JoinHandle;
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
 use crate::sources::sources::ConfigSource;
+#[cfg(feature = "s3-sqs")]
+use crate::sources::sqs::{watch_queue, SqsClient};
 
 pub struct S3ConfigSource {
     client: Client,
     bucket: String,
     path: String,
+    max_size: Option<u64>,
+    trigger: Option<RefreshTrigger>,
+    watcher: Option<JoinHandle<()>>,
 }
 
 impl S3ConfigSource {
+    // Starts from shared AWS config, for example `&aws_config::load_from_env().await`, and
+    // allows pointing at S3-compatible stores such as MinIO, Cloudflare R2 or Ceph RGW.
+    pub fn builder<C: Into<config::Builder>, S: Into<String>>(config: C, bucket: S, path: S) -> S3ConfigSourceBuilder {
+        S3ConfigSourceBuilder {
+            config: config.into(),
+            bucket: bucket.into(),
+            path: path.into(),
+        }
+    }
+
     pub fn new<S: Into<String>>(client: Client, bucket: S, path: S) -> Result<S3ConfigSource> {
         Ok(S3ConfigSource {
             client,
             bucket: bucket.into(),
             path: path.into(),
+            max_size: None,
+            trigger: None,
+            watcher: None,
         })
     }
+
+    // Objects larger than this fail the fetch without their body being read.
+    pub fn with_max_size(mut self, bytes: u64) -> S3ConfigSource {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    // Subscribes to S3 event notifications delivered to an SQS queue, refreshing as soon as the
+    // object changes rather than waiting for the next fetch interval.
+    #[cfg(feature = "s3-sqs")]
+    pub fn with_sqs_notifications<S: Into<String>>(mut self, sqs: SqsClient, queue_url: S) -> S3ConfigSource {
+        let trigger = RefreshTrigger::new();
+        self.watcher = Some(tokio::spawn(
+            watch_queue(sqs, queue_url.into(), self.bucket.clone(), self.path.clone(), trigger.clone())
+        ));
+        self.trigger = Some(trigger);
+        self
+    }
+}
+
+pub struct S3ConfigSourceBuilder {
+    config: config::Builder,
+    bucket: String,
+    path: String,
+}
+
+impl S3ConfigSourceBuilder {
+    pub fn with_endpoint<S: Into<String>>(mut self, endpoint_url: S) -> S3ConfigSourceBuilder {
+        self.config = self.config.endpoint_url(endpoint_url);
+        self
+    }
+
+    // Most S3-compatible stores don't support virtual-hosted-style bucket addressing.
+    pub fn with_path_style(mut self) -> S3ConfigSourceBuilder {
+        self.config = self.config.force_path_style(true);
+        self
+    }
+
+    pub fn with_region<S: Into<String>>(mut self, region: S) -> S3ConfigSourceBuilder {
+        self.config = self.config.region(Region::new(region.into()));
+        self
+    }
+
+    pub fn with_credentials<S: Into<String>>(mut self, access_key_id: S, secret_access_key: S) -> S3ConfigSourceBuilder {
+        let credentials = Credentials::new(access_key_id, secret_access_key, None, None, "mirror-cache");
+        self.config = self.config.credentials_provider(credentials);
+        self
+    }
+
+    pub fn build(self) -> Result<S3ConfigSource> {
+        S3ConfigSource::new(Client::from_conf(self.config.build()), self.bucket, self.path)
+    }
+
+    pub fn build_versioned(self) -> Result<S3VersionedConfigSource> {
+        S3VersionedConfigSource::new(Client::from_conf(self.config.build()), self.bucket, self.path)
+    }
 }
 
 #[async_trait]
@@ -30,10 +107,22 @@ impl ConfigSource<DateTime, ByteStream> for S3ConfigSource {
             .key(self.path.clone())
             .send().await?;
 
+        check_size(self.max_size, resp.content_length())?;
         Ok((resp.last_modified().cloned(), resp.body))
     }
 
     async fn fetch_if_newer(&self, version: &DateTime) -> Result<Option<(Option<DateTime>, ByteStream)>> {
+        // A HEAD is cheap to check first, the conditional GET below still covers the object
+        // changing in between.
+        let head = self.client.head_object()
+            .bucket(self.bucket.clone())
+            .key(self.path.clone())
+            .send().await?;
+
+        if head.last_modified().is_some_and(|modified| modified <= version) {
+            return Ok(None);
+        }
+
         let result = self.client.get_object()
             .bucket(self.bucket.clone())
             .key(self.path.clone())
@@ -41,7 +130,10 @@ impl ConfigSource<DateTime, ByteStream> for S3ConfigSource {
             .send().await;
 
         match result {
-            Ok(resp) => Ok(Some((resp.last_modified().cloned(), resp.body))),
+            Ok(resp) => {
+                check_size(self.max_size, resp.content_length())?;
+                Ok(Some((resp.last_modified().cloned(), resp.body)))
+            },
             Err(SdkError::ServiceError(err)) => {
                 if err.raw().http().status() == 304 {
                     Ok(None)
@@ -52,4 +144,112 @@ impl ConfigSource<DateTime, ByteStream> for S3ConfigSource {
             Err(err) => Err(err.into())
         }
     }
-}
\ No newline at end of file
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.trigger.clone()
+    }
+}
+
+// For buckets with versioning enabled. Uses the object's VersionId rather than its last modified
+// time, which only has second granularity, so uploads in quick succession are never missed.
+pub struct S3VersionedConfigSource {
+    client: Client,
+    bucket: String,
+    path: String,
+    max_size: Option<u64>,
+    trigger: Option<RefreshTrigger>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl S3VersionedConfigSource {
+    pub fn new<S: Into<String>>(client: Client, bucket: S, path: S) -> Result<S3VersionedConfigSource> {
+        Ok(S3VersionedConfigSource {
+            client,
+            bucket: bucket.into(),
+            path: path.into(),
+            max_size: None,
+            trigger: None,
+            watcher: None,
+        })
+    }
+
+    pub fn with_max_size(mut self, bytes: u64) -> S3VersionedConfigSource {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    // Subscribes to S3 event notifications delivered to an SQS queue, refreshing as soon as the
+    // object changes rather than waiting for the next fetch interval.
+    #[cfg(feature = "s3-sqs")]
+    pub fn with_sqs_notifications<S: Into<String>>(mut self, sqs: SqsClient, queue_url: S) -> S3VersionedConfigSource {
+        let trigger = RefreshTrigger::new();
+        self.watcher = Some(tokio::spawn(
+            watch_queue(sqs, queue_url.into(), self.bucket.clone(), self.path.clone(), trigger.clone())
+        ));
+        self.trigger = Some(trigger);
+        self
+    }
+
+    async fn fetch_version(&self, version_id: Option<String>) -> Result<(Option<String>, ByteStream)> {
+        let resp = self.client.get_object()
+            .bucket(self.bucket.clone())
+            .key(self.path.clone())
+            .set_version_id(version_id)
+            .send().await?;
+
+        check_size(self.max_size, resp.content_length())?;
+        match resp.version_id() {
+            Some(version_id) => Ok((Some(String::from(version_id)), resp.body)),
+            None => Err(Error::new("Object has no VersionId, is versioning enabled on the bucket?")),
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, ByteStream> for S3VersionedConfigSource {
+    async fn fetch(&self) -> Result<(Option<String>, ByteStream)> {
+        self.fetch_version(None).await
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, ByteStream)>> {
+        let head = self.client.head_object()
+            .bucket(self.bucket.clone())
+            .key(self.path.clone())
+            .send().await?;
+
+        match head.version_id() {
+            Some(latest) if latest == version => Ok(None),
+            // Fetch exactly the version seen, in case another upload lands in between.
+            latest => self.fetch_version(latest.map(String::from)).await.map(Some),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.trigger.clone()
+    }
+}
+
+fn check_size(max_size: Option<u64>, length: i64) -> Result<()> {
+    match max_size {
+        Some(max_size) if length > 0 && length as u64 > max_size => Err(Error::new(
+            format!("Object of {} bytes exceeds the maximum size of {} bytes", length, max_size).as_str()
+        )),
+        _ => Ok(()),
+    }
+}
+
+impl Drop for S3ConfigSource {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.watcher {
+            handle.abort();
+        }
+    }
+}
+
+impl Drop for S3VersionedConfigSource {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.watcher {
+            handle.abort();
+        }
+    }
+}
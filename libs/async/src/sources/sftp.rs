@@ -0,0 +1,101 @@
+use std::io::{Cursor, Read};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use ssh2::{Session, Sftp};
+use tokio::task;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+#[derive(Clone)]
+pub enum SftpAuth {
+    Password(String),
+    PrivateKey { path: PathBuf, passphrase: Option<String> },
+    Agent,
+}
+
+pub struct SftpConfigSource {
+    target: Arc<SftpTarget>,
+}
+
+impl SftpConfigSource {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(address: S, username: S, auth: SftpAuth, path: P) -> SftpConfigSource {
+        SftpConfigSource {
+            target: Arc::new(SftpTarget {
+                address: address.into(),
+                username: username.into(),
+                auth,
+                path: path.into(),
+            }),
+        }
+    }
+
+    // ssh2 is blocking only, so the work is moved off the runtime's worker threads.
+    async fn fetch_newer_than(&self, version: Option<u64>) -> Result<Option<(Option<u64>, Cursor<Vec<u8>>)>> {
+        let target = self.target.clone();
+        task::spawn_blocking(move || target.fetch_newer_than(version)).await?
+    }
+}
+
+struct SftpTarget {
+    address: String,
+    username: String,
+    auth: SftpAuth,
+    path: PathBuf,
+}
+
+impl SftpTarget {
+    fn connect(&self) -> Result<Sftp> {
+        let mut session = Session::new()?;
+        session.set_tcp_stream(TcpStream::connect(self.address.as_str())?);
+        session.handshake()?;
+
+        match &self.auth {
+            SftpAuth::Password(password) => session.userauth_password(&self.username, password)?,
+            SftpAuth::PrivateKey { path, passphrase } =>
+                session.userauth_pubkey_file(&self.username, None, path, passphrase.as_deref())?,
+            SftpAuth::Agent => session.userauth_agent(&self.username)?,
+        }
+
+        if !session.authenticated() {
+            return Err(Error::new("SFTP authentication failed"));
+        }
+
+        Ok(session.sftp()?)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn fetch_newer_than(&self, version: Option<u64>) -> Result<Option<(Option<u64>, Cursor<Vec<u8>>)>> {
+        let sftp = self.connect()?;
+        let mtime = sftp.stat(&self.path)?.mtime;
+
+        // Servers that don't report mtime get an unconditional fetch.
+        if let (Some(current), Some(previous)) = (mtime, version) {
+            if current <= previous {
+                return Ok(None);
+            }
+        }
+
+        let mut content = vec![];
+        sftp.open(&self.path)?.read_to_end(&mut content)?;
+        Ok(Some((mtime, Cursor::new(content))))
+    }
+}
+
+#[async_trait]
+impl ConfigSource<u64, Cursor<Vec<u8>>> for SftpConfigSource {
+    async fn fetch(&self) -> Result<(Option<u64>, Cursor<Vec<u8>>)> {
+        match self.fetch_newer_than(None).await? {
+            Some(update) => Ok(update),
+            None => Err(Error::new("Unconditional fetch returned no content")),
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &u64) -> Result<Option<(Option<u64>, Cursor<Vec<u8>>)>> {
+        self.fetch_newer_than(Some(*version)).await
+    }
+}
@@ -1,15 +1,22 @@
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::UNIX_EPOCH;
 use async_trait::async_trait;
 
-use mirror_cache_core::util::Result;
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
 
 #[async_trait]
 pub trait ConfigSource<E, S> {
     async fn fetch(&self) -> Result<(Option<E>, S)>;
     async fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>>;
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        None
+    }
 }
 
 pub struct LocalFileConfigSource<P: AsRef<Path> + Send + Sync> {
@@ -59,3 +66,116 @@ impl<P: AsRef<Path> + Send + Sync> ConfigSource<u128, BufReader<File>> for Local
     }
 }
 
+// Kubernetes mounts ConfigMaps as a directory of symlinks through `..data`, which itself links
+// to a timestamped directory and is swapped atomically on update. The link target changes on
+// every update, so it serves as the version.
+const K8S_DATA_LINK: &str = "..data";
+
+pub struct K8sMountedConfigSource<P: AsRef<Path> + Send + Sync> {
+    dir: P,
+    key: String,
+}
+
+impl<P: AsRef<Path> + Send + Sync> K8sMountedConfigSource<P> {
+    pub fn new<S: Into<String>>(dir: P, key: S) -> K8sMountedConfigSource<P> {
+        K8sMountedConfigSource {
+            dir,
+            key: key.into(),
+        }
+    }
+
+    fn current_data_dir(&self) -> Result<PathBuf> {
+        Ok(fs::read_link(self.dir.as_ref().join(K8S_DATA_LINK))?)
+    }
+
+    fn open(&self, data_dir: PathBuf) -> Result<(Option<String>, BufReader<File>)> {
+        let file = File::open(self.dir.as_ref().join(&data_dir).join(&self.key))?;
+        Ok((Some(data_dir.to_string_lossy().into_owned()), BufReader::new(file)))
+    }
+}
+
+#[async_trait]
+impl<P: AsRef<Path> + Send + Sync> ConfigSource<String, BufReader<File>> for K8sMountedConfigSource<P> {
+    async fn fetch(&self) -> Result<(Option<String>, BufReader<File>)> {
+        self.open(self.current_data_dir()?)
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, BufReader<File>)>> {
+        let data_dir = self.current_data_dir()?;
+        if data_dir.to_string_lossy() == version.as_str() {
+            return Ok(None);
+        }
+
+        self.open(data_dir).map(Some)
+    }
+}
+
+// Always serves the same payload, mostly useful in tests or for data that never changes.
+pub struct StaticConfigSource<E, S> {
+    version: Option<E>,
+    payload: S,
+}
+
+impl<E, S> StaticConfigSource<E, S> {
+    pub fn new(version: Option<E>, payload: S) -> StaticConfigSource<E, S> {
+        StaticConfigSource {
+            version,
+            payload,
+        }
+    }
+}
+
+#[async_trait]
+impl<E: Clone + PartialEq + Send + Sync, S: Clone + Send + Sync> ConfigSource<E, S> for StaticConfigSource<E, S> {
+    async fn fetch(&self) -> Result<(Option<E>, S)> {
+        Ok((self.version.clone(), self.payload.clone()))
+    }
+
+    async fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        if self.version.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        self.fetch().await.map(Some)
+    }
+}
+
+// Serves a scripted sequence of results, one per fetch, so processors and callbacks can be tested
+// against updates and failures without a real backend. A conditional fetch whose step carries the
+// version already held reports no change. Once the script runs out there's never anything newer.
+#[allow(clippy::type_complexity)]
+pub struct InMemoryConfigSource<E, S> {
+    steps: Mutex<VecDeque<Result<(Option<E>, S)>>>,
+}
+
+impl<E, S> InMemoryConfigSource<E, S> {
+    pub fn new<I: IntoIterator<Item=Result<(Option<E>, S)>>>(steps: I) -> InMemoryConfigSource<E, S> {
+        InMemoryConfigSource {
+            steps: Mutex::new(steps.into_iter().collect()),
+        }
+    }
+
+    fn next(&self) -> Result<Option<(Option<E>, S)>> {
+        match self.steps.lock()?.pop_front() {
+            Some(step) => step.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<E: PartialEq + Send + Sync, S: Send> ConfigSource<E, S> for InMemoryConfigSource<E, S> {
+    async fn fetch(&self) -> Result<(Option<E>, S)> {
+        match self.next()? {
+            Some(step) => Ok(step),
+            None => Err(Error::new("No more scripted payloads")),
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        match self.next()? {
+            Some((Some(next), _)) if &next == version => Ok(None),
+            next => Ok(next),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+pub use aws_sdk_sqs::Client as SqsClient;
+
+use std::time::Duration;
+use serde::Deserialize;
+use tokio::time;
+use url::form_urlencoded;
+
+use mirror_cache_core::util::RefreshTrigger;
+
+const SQS_WAIT_SECONDS: i32 = 20;
+const SQS_MAX_MESSAGES: i32 = 10;
+const SQS_RETRY: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct S3Event {
+    #[serde(rename = "Records", default)]
+    records: Vec<S3EventRecord>,
+}
+
+#[derive(Deserialize)]
+struct S3EventRecord {
+    s3: S3Entity,
+}
+
+#[derive(Deserialize)]
+struct S3Entity {
+    bucket: S3Bucket,
+    object: S3Object,
+}
+
+#[derive(Deserialize)]
+struct S3Bucket {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct S3Object {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct SnsEnvelope {
+    #[serde(rename = "Message")]
+    message: String,
+}
+
+// Long polls a queue receiving S3 event notifications, firing the trigger whenever one concerns
+// the watched object. Every message received is deleted, so the queue should be dedicated to
+// this cache.
+pub(crate) async fn watch_queue(client: SqsClient, queue_url: String, bucket: String, key: String, trigger: RefreshTrigger) {
+    loop {
+        let resp = client.receive_message()
+            .queue_url(queue_url.as_str())
+            .wait_time_seconds(SQS_WAIT_SECONDS)
+            .max_number_of_messages(SQS_MAX_MESSAGES)
+            .send().await;
+
+        let messages = match resp {
+            Ok(resp) => resp.messages().map(|m| m.to_vec()).unwrap_or_default(),
+            Err(_) => {
+                time::sleep(SQS_RETRY).await;
+                continue;
+            }
+        };
+
+        if messages.iter().any(|m| m.body().is_some_and(|b| concerns(b, &bucket, &key))) {
+            trigger.fire();
+        }
+
+        for message in messages {
+            if let Some(handle) = message.receipt_handle() {
+                // Failed deletes are redelivered and at worst cause a spare fetch.
+                let _ = client.delete_message()
+                    .queue_url(queue_url.as_str())
+                    .receipt_handle(handle)
+                    .send().await;
+            }
+        }
+    }
+}
+
+fn concerns(body: &str, bucket: &str, key: &str) -> bool {
+    // Notifications fanned out through SNS arrive wrapped in an envelope.
+    let body = match serde_json::from_str::<SnsEnvelope>(body) {
+        Ok(envelope) => envelope.message,
+        Err(_) => String::from(body),
+    };
+
+    match serde_json::from_str::<S3Event>(body.as_str()) {
+        Ok(event) => event.records.iter()
+            .any(|r| r.s3.bucket.name == bucket && decode_key(r.s3.object.key.as_str()) == key),
+        Err(_) => false,
+    }
+}
+
+// Keys in S3 events are form encoded, spaces become '+'.
+fn decode_key(key: &str) -> String {
+    form_urlencoded::parse(key.as_bytes())
+        .map(|(k, _)| k.into_owned())
+        .next()
+        .unwrap_or_default()
+}
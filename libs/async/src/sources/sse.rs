@@ -0,0 +1,234 @@
+pub use reqwest::Client;
+
+use std::io::Cursor;
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::header::ACCEPT;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const STREAM_RETRY: Duration = Duration::from_secs(1);
+const FIRST_EVENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Latest = Option<std::result::Result<(String, Vec<u8>), String>>;
+
+// Wraps another source, fetching from it as soon as an event arrives on the stream. Polling
+// carries on at the fetch interval regardless, so nothing is missed while the stream is down.
+pub struct SseConfigSource<C> {
+    inner: C,
+    trigger: RefreshTrigger,
+    stream: JoinHandle<()>,
+}
+
+impl<C> SseConfigSource<C> {
+    pub fn new<S: Into<String>>(client: Client, url: S, inner: C) -> SseConfigSource<C> {
+        let trigger = RefreshTrigger::new();
+        let on_event = trigger.clone();
+
+        SseConfigSource {
+            inner,
+            trigger,
+            stream: tokio::spawn(listen(client, url.into(), move |_| on_event.fire(), |_| {})),
+        }
+    }
+}
+
+impl<C> Drop for SseConfigSource<C> {
+    fn drop(&mut self) {
+        self.stream.abort();
+    }
+}
+
+#[async_trait]
+impl<E, S, C> ConfigSource<E, S> for SseConfigSource<C>
+    where
+        E: Sync,
+        C: ConfigSource<E, S> + Send + Sync {
+    async fn fetch(&self) -> Result<(Option<E>, S)> {
+        self.inner.fetch().await
+    }
+
+    async fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        self.inner.fetch_if_newer(version).await
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+// Uses the data of each event as the full dataset. The event id is used as the version where the
+// server sends one, otherwise events are numbered as they arrive.
+pub struct SsePayloadSource {
+    latest: watch::Receiver<Latest>,
+    trigger: RefreshTrigger,
+    stream: JoinHandle<()>,
+    first_event_timeout: Duration,
+}
+
+impl SsePayloadSource {
+    pub fn new<S: Into<String>>(client: Client, url: S) -> SsePayloadSource {
+        let (tx, latest) = watch::channel(None);
+        let failures = tx.clone();
+        let trigger = RefreshTrigger::new();
+        let on_event = trigger.clone();
+
+        let mut received: u64 = 0;
+        let stream = tokio::spawn(listen(client, url.into(), move |event| {
+            received += 1;
+            let version = event.id.unwrap_or_else(|| received.to_string());
+            tx.send_replace(Some(Ok((version, event.data.into_bytes()))));
+            on_event.fire();
+        }, move |err| record_failure(&failures, err)));
+
+        SsePayloadSource {
+            latest,
+            trigger,
+            stream,
+            first_event_timeout: FIRST_EVENT_TIMEOUT,
+        }
+    }
+
+    // How long a fetch waits for the first event before failing, so build() can't hang on a
+    // stream that connects but never delivers. Thirty seconds by default.
+    pub fn with_first_event_timeout(mut self, timeout: Duration) -> SsePayloadSource {
+        self.first_event_timeout = timeout;
+        self
+    }
+}
+
+impl Drop for SsePayloadSource {
+    fn drop(&mut self) {
+        self.stream.abort();
+    }
+}
+
+#[async_trait]
+impl ConfigSource<String, Cursor<Vec<u8>>> for SsePayloadSource {
+    async fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        // Nothing can be returned until the first event, or failure, comes in.
+        let mut latest = self.latest.clone();
+        let Ok(current) = time::timeout(self.first_event_timeout, latest.wait_for(|l| l.is_some())).await else {
+            return Err(Error::new(format!("No event arrived on the stream within {:?}", self.first_event_timeout).as_str()));
+        };
+        let current = current?;
+
+        match current.as_ref() {
+            Some(Ok((version, body))) => Ok((Some(version.clone()), Cursor::new(body.clone()))),
+            Some(Err(msg)) => Err(Error::new(msg.as_str())),
+            None => Err(Error::new("Stream closed before receiving data")),
+        }
+    }
+
+    async fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let unchanged = matches!(self.latest.borrow().as_ref(), Some(Ok((latest, _))) if latest == version);
+        if unchanged {
+            return Ok(None);
+        }
+
+        self.fetch().await.map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+// Once data has arrived it keeps being served while the stream is re-established, failures are
+// only surfaced if there's nothing else to return.
+fn record_failure(tx: &watch::Sender<Latest>, err: String) {
+    tx.send_if_modified(|latest| match latest {
+        Some(Ok(_)) => false,
+        _ => {
+            *latest = Some(Err(err));
+            true
+        }
+    });
+}
+
+struct Event {
+    id: Option<String>,
+    data: String,
+}
+
+// Follows the field rules from the HTML spec, event names and retry hints are ignored.
+#[derive(Default)]
+struct EventParser {
+    last_id: Option<String>,
+    data: Vec<String>,
+}
+
+impl EventParser {
+    fn line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            if self.data.is_empty() {
+                return None;
+            }
+
+            return Some(Event {
+                id: self.last_id.clone(),
+                data: self.data.drain(..).collect::<Vec<String>>().join("\n"),
+            });
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "data" => self.data.push(String::from(value)),
+            "id" => self.last_id = Some(String::from(value)),
+            _ => {}
+        }
+
+        None
+    }
+}
+
+async fn listen<F, G>(client: Client, url: String, mut on_event: F, on_failure: G)
+    where
+        F: FnMut(Event) + Send,
+        G: Fn(String) + Send {
+    let mut parser = EventParser::default();
+    loop {
+        let mut req = client.get(url.as_str()).header(ACCEPT, "text/event-stream");
+        if let Some(id) = &parser.last_id {
+            req = req.header("Last-Event-ID", id);
+        }
+
+        match req.send().await.and_then(|r| r.error_for_status()) {
+            Ok(mut resp) => {
+                let mut buf = Vec::new();
+                loop {
+                    match resp.chunk().await {
+                        Ok(Some(chunk)) => buf.extend_from_slice(&chunk),
+                        Ok(None) => break,
+                        Err(err) => {
+                            on_failure(err.to_string());
+                            break;
+                        }
+                    }
+
+                    while let Some(end) = buf.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = buf.drain(..=end).collect();
+                        let line = String::from_utf8_lossy(&line);
+                        if let Some(event) = parser.line(line.trim_end_matches(['\r', '\n'])) {
+                            on_event(event);
+                        }
+                    }
+                }
+            }
+            Err(err) => on_failure(err.to_string()),
+        }
+
+        // Any partial event from the dropped stream is discarded.
+        parser.data.clear();
+        time::sleep(STREAM_RETRY).await;
+    }
+}
@@ -1,18 +1,124 @@
-use std::collections::{HashMap, HashSet};
-use std::hash::Hash;
-use std::sync::Arc;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::{Deref, RangeBounds};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use arc_swap::{ArcSwap, Guard};
 use crate::util::Holder;
 
+#[cfg(feature = "rand")]
+use rand::Rng;
+#[cfg(feature = "regex")]
+pub use regex::RegexSet;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+#[cfg(feature = "rand")]
+use crate::util::{Error, Result};
+
 const NON_RUNNING: &str = "Attempt to read collection from non-running update service";
 
+// Called by the cache each time a refresh swaps in a new dataset, so collections can keep the
+// previous generation and react to updates themselves rather than just reading the holder.
+pub trait UpdateHook<E, T> {
+    #[allow(clippy::type_complexity)]
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, T)>>, current: &(Option<E>, T));
+}
+
+// Lets the cache report the version being served without knowing which collection it holds.
+pub trait Versioned<E> {
+    fn current_version(&self) -> Option<E>;
+}
+
+fn empty_holder<E, T>() -> Holder<E, T> {
+    Arc::new(ArcSwap::new(Arc::new(None)))
+}
+
+// Stops the watch it was returned from when dropped.
+pub struct Subscription {
+    cancel: Option<Box<dyn FnOnce() + Send + Sync>>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            cancel()
+        }
+    }
+}
+
+// A collection as it was when the snapshot was taken, unaffected by later updates, so all of its
+// entries can be scanned consistently. Dereferences to the collection itself.
+pub struct Snapshot<E, T> {
+    snapshot: Arc<Option<(Option<E>, T)>>,
+}
+
+impl<E, T> Snapshot<E, T> {
+    fn new(snapshot: Arc<Option<(Option<E>, T)>>) -> Snapshot<E, T> {
+        if snapshot.is_none() {
+            panic!("{}", NON_RUNNING);
+        }
+
+        Snapshot {
+            snapshot
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn previous(snapshot: Arc<Option<(Option<E>, T)>>) -> Option<Snapshot<E, T>> {
+        snapshot.is_some().then_some(Snapshot {
+            snapshot
+        })
+    }
+
+    pub fn version(&self) -> Option<&E> {
+        match self.snapshot.as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.as_ref()
+        }
+    }
+}
+
+impl<E, T> Deref for Snapshot<E, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.snapshot.as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, t)) => t
+        }
+    }
+}
+
+// Serializes just the collection, so admin endpoints can dump the live config. The version is
+// available separately from version().
+#[cfg(feature = "serde")]
+impl<E, T: Serialize> Serialize for Snapshot<E, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
+impl<E, T> Clone for Snapshot<E, T> {
+    fn clone(&self) -> Self {
+        Snapshot {
+            snapshot: self.snapshot.clone(),
+        }
+    }
+}
+
 pub struct UpdatingObject<E, T> {
-    backing: Holder<E, Arc<T>>
+    backing: Holder<E, Arc<T>>,
+    previous: Holder<E, Arc<T>>,
 }
 
 impl<E, T> UpdatingObject<E, T> {
     pub fn new(backing: Holder<E, Arc<T>>) -> UpdatingObject<E, T> {
         UpdatingObject {
-            backing
+            backing,
+            previous: empty_holder(),
         }
     }
 
@@ -22,16 +128,91 @@ impl<E, T> UpdatingObject<E, T> {
             Some((_, a)) => a.clone()
         }
     }
+
+    // A guard dereferencing to the current value. Cheaper than get_current() as it usually avoids
+    // touching the reference count, but guards are meant to be short lived, hold an Arc from
+    // get_current() for anything longer.
+    pub fn load(&self) -> ObjectGuard<E, T> {
+        let guard = self.backing.load();
+        if guard.is_none() {
+            panic!("{}", NON_RUNNING);
+        }
+
+        ObjectGuard {
+            guard
+        }
+    }
+
+    // Reads part of the current value, such as a single field, without holding on to all of it.
+    pub fn map<R, F: FnOnce(&T) -> R>(&self, f: F) -> R {
+        f(&self.load())
+    }
+
+    pub fn snapshot(&self) -> Snapshot<E, Arc<T>> {
+        Snapshot::new(self.backing.load_full())
+    }
+
+    pub fn get_versioned(&self) -> (Option<E>, Arc<T>) where E: Clone {
+        match self.backing.load_full().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, a)) => (v.clone(), a.clone())
+        }
+    }
+
+    // The version of the dataset currently served, None if the source doesn't version its data or
+    // the fallback is being served.
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.backing.load_full().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    // The dataset this one replaced, kept until the next update so old and new values can be
+    // compared. None until the first refresh after startup.
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, Arc<T>>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+}
+
+impl<E, T> UpdateHook<E, Arc<T>> for UpdatingObject<E, T> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, Arc<T>)>>, _current: &(Option<E>, Arc<T>)) {
+        self.previous.store(previous.clone());
+    }
+}
+
+impl<E: Clone, T> Versioned<E> for UpdatingObject<E, T> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingObject::current_version(self)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub struct ObjectGuard<E, T> {
+    guard: Guard<Arc<Option<(Option<E>, Arc<T>)>>>,
+}
+
+impl<E, T> Deref for ObjectGuard<E, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.guard.as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, a)) => a
+        }
+    }
 }
 
 pub struct UpdatingSet<E, T: Eq + Hash + Send + Sync> {
-    backing: Holder<E, HashSet<T>>
+    backing: Holder<E, HashSet<T>>,
+    previous: Holder<E, HashSet<T>>,
 }
 
 impl<E, T: Eq + Hash + Send + Sync> UpdatingSet<E, T> {
     pub fn new(backing: Holder<E, HashSet<T>>) -> UpdatingSet<E, T> {
         UpdatingSet {
-            backing
+            backing,
+            previous: empty_holder(),
         }
     }
 
@@ -56,23 +237,245 @@ impl<E, T: Eq + Hash + Send + Sync> UpdatingSet<E, T> {
         }
     }
 
+    pub fn snapshot(&self) -> Snapshot<E, HashSet<T>> {
+        Snapshot::new(self.get_collection())
+    }
+
+    // The values as of the call, collected so the iterator can outlive the snapshot.
+    pub fn iter(&self) -> std::vec::IntoIter<T> where T: Clone {
+        self.snapshot().iter().cloned().collect::<Vec<T>>().into_iter()
+    }
+
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, HashSet<T>>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+
     fn get_collection(&self) -> Arc<Option<(Option<E>, HashSet<T>)>> {
         self.backing.load_full().clone()
     }
 }
 
+impl<E, T: Eq + Hash + Send + Sync> UpdateHook<E, HashSet<T>> for UpdatingSet<E, T> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, HashSet<T>)>>, _current: &(Option<E>, HashSet<T>)) {
+        self.previous.store(previous.clone());
+    }
+}
+
+impl<E: Clone, T: Eq + Hash + Send + Sync> Versioned<E> for UpdatingSet<E, T> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingSet::current_version(self)
+    }
+}
+
+// How NormalizedKey normalizes strings.
+pub trait KeyNormalizer {
+    fn normalize(key: &str) -> String;
+}
+
+pub struct AsciiLowercase {}
+
+impl KeyNormalizer for AsciiLowercase {
+    fn normalize(key: &str) -> String {
+        key.to_ascii_lowercase()
+    }
+}
+
+// A string key normalized whenever one is constructed, so keys built by processors and keys built
+// for lookups can't mismatch on case, for maps keyed by header names or hostnames.
+pub struct NormalizedKey<N: KeyNormalizer = AsciiLowercase> {
+    key: String,
+    _phantom_n: PhantomData<fn() -> N>,
+}
+
+impl<N: KeyNormalizer> NormalizedKey<N> {
+    pub fn new<S: AsRef<str>>(key: S) -> NormalizedKey<N> {
+        NormalizedKey {
+            key: N::normalize(key.as_ref()),
+            _phantom_n: PhantomData,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.key
+    }
+}
+
+impl<N: KeyNormalizer> From<&str> for NormalizedKey<N> {
+    fn from(key: &str) -> Self {
+        NormalizedKey::new(key)
+    }
+}
+
+impl<N: KeyNormalizer> From<String> for NormalizedKey<N> {
+    fn from(key: String) -> Self {
+        NormalizedKey::new(key)
+    }
+}
+
+impl<N: KeyNormalizer> Clone for NormalizedKey<N> {
+    fn clone(&self) -> Self {
+        NormalizedKey {
+            key: self.key.clone(),
+            _phantom_n: PhantomData,
+        }
+    }
+}
+
+impl<N: KeyNormalizer> PartialEq for NormalizedKey<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<N: KeyNormalizer> Eq for NormalizedKey<N> {}
+
+impl<N: KeyNormalizer> Hash for NormalizedKey<N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
+    }
+}
+
+impl<N: KeyNormalizer> PartialOrd for NormalizedKey<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N: KeyNormalizer> Ord for NormalizedKey<N> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<N: KeyNormalizer> Debug for NormalizedKey<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.key, f)
+    }
+}
+
+impl<N: KeyNormalizer> Display for NormalizedKey<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.key)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<N: KeyNormalizer> Serialize for NormalizedKey<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.key)
+    }
+}
+
+type KeyWatcher<V> = Box<dyn Fn(Option<&Arc<V>>, Option<&Arc<V>>) + Send + Sync>;
+
+#[allow(clippy::type_complexity)]
 pub struct UpdatingMap<E, K: Eq + Hash, V> {
-    backing: Holder<E, HashMap<K, Arc<V>>>
+    backing: Holder<E, HashMap<K, Arc<V>>>,
+    previous: Holder<E, HashMap<K, Arc<V>>>,
+    watchers: Arc<Mutex<HashMap<K, Vec<(u64, KeyWatcher<V>)>>>>,
+    next_watcher: AtomicU64,
 }
 
 impl<E, K: Eq + Hash, V> UpdatingMap<E, K, V> {
     pub fn new(backing: Holder<E, HashMap<K, Arc<V>>>) -> UpdatingMap<E, K, V> {
         UpdatingMap {
-            backing
+            backing,
+            previous: empty_holder(),
+            watchers: Arc::new(Mutex::new(HashMap::new())),
+            next_watcher: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<E, K: Eq + Hash + Clone + Send + Sync + 'static, V: PartialEq + 'static> UpdatingMap<E, K, V> {
+    // Calls on_change with the key's new value, None if it was removed, whenever a refresh changes
+    // it. Called on the refresh thread or task, so it should be quick and mustn't watch more keys.
+    // The watch lasts until the returned Subscription is dropped.
+    pub fn watch<F>(&self, key: K, on_change: F) -> Subscription
+        where F: Fn(Option<Arc<V>>) + Send + Sync + 'static {
+        let id = self.next_watcher.fetch_add(1, Ordering::Relaxed);
+        let watcher: KeyWatcher<V> = Box::new(move |old, new| {
+            let changed = match (old, new) {
+                (Some(old), Some(new)) => !Arc::ptr_eq(old, new) && old != new,
+                (None, None) => false,
+                _ => true,
+            };
+
+            if changed {
+                on_change(new.cloned())
+            }
+        });
+
+        self.watchers.lock().unwrap_or_else(PoisonError::into_inner)
+            .entry(key.clone())
+            .or_default()
+            .push((id, watcher));
+
+        let watchers = Arc::downgrade(&self.watchers);
+        Subscription {
+            cancel: Some(Box::new(move || unwatch(watchers, &key, id))),
         }
     }
 }
 
+impl<E, N: KeyNormalizer, V: Send + Sync> UpdatingMap<E, NormalizedKey<N>, V> {
+    // Looks up a raw key, normalizing it first.
+    pub fn get_normalized(&self, key: &str) -> Option<Arc<V>> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, h)) => h.get(&NormalizedKey::new(key)).cloned()
+        }
+    }
+
+    pub fn contains_normalized(&self, key: &str) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, h)) => h.contains_key(&NormalizedKey::new(key))
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn unwatch<K: Eq + Hash, V>(watchers: Weak<Mutex<HashMap<K, Vec<(u64, KeyWatcher<V>)>>>>, key: &K, id: u64) {
+    if let Some(watchers) = watchers.upgrade() {
+        let mut watchers = watchers.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(for_key) = watchers.get_mut(key) {
+            for_key.retain(|(i, _)| *i != id);
+            if for_key.is_empty() {
+                watchers.remove(key);
+            }
+        }
+    }
+}
+
+impl<E, K: Eq + Hash, V> UpdateHook<E, HashMap<K, Arc<V>>> for UpdatingMap<E, K, V> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, HashMap<K, Arc<V>>)>>, current: &(Option<E>, HashMap<K, Arc<V>>)) {
+        self.previous.store(previous.clone());
+
+        let watchers = self.watchers.lock().unwrap_or_else(PoisonError::into_inner);
+        for (key, for_key) in watchers.iter() {
+            let old = previous.as_ref().as_ref().and_then(|(_, h)| h.get(key));
+            let new = current.1.get(key);
+            for (_, watcher) in for_key {
+                watcher(old, new)
+            }
+        }
+    }
+}
+
+impl<E: Clone, K: Eq + Hash + Send + Sync, V: Send + Sync> Versioned<E> for UpdatingMap<E, K, V> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingMap::current_version(self)
+    }
+}
+
 impl<E, K: Eq + Hash + Send + Sync, V: Send + Sync> UpdatingMap<E, K, V> {
     pub fn get(&self, key: &K) -> Option<Arc<V>> {
         match self.get_collection().as_ref() {
@@ -81,6 +484,43 @@ impl<E, K: Eq + Hash + Send + Sync, V: Send + Sync> UpdatingMap<E, K, V> {
         }
     }
 
+    // The value along with the version of the dataset it was read from.
+    pub fn get_versioned(&self, key: &K) -> Option<(Option<E>, Arc<V>)> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, h)) => h.get(key).map(|val| (v.clone(), val.clone()))
+        }
+    }
+
+    pub fn get_or(&self, key: &K, default: Arc<V>) -> Arc<V> {
+        self.get(key).unwrap_or(default)
+    }
+
+    // The default is only computed if the key is missing.
+    pub fn get_or_else<F: FnOnce() -> Arc<V>>(&self, key: &K, f: F) -> Arc<V> {
+        self.get(key).unwrap_or_else(f)
+    }
+
+    pub fn get_or_default(&self, key: &K) -> Arc<V> where V: Default {
+        self.get(key).unwrap_or_default()
+    }
+
+    // Looks all the keys up in the same snapshot, so the results are consistent with each other
+    // even if an update lands meanwhile.
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, h)) => keys.iter().map(|k| h.get(k).cloned()).collect()
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, h)) => h.contains_key(key)
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self.get_collection().as_ref() {
             None => panic!("{}", NON_RUNNING),
@@ -95,8 +535,573 @@ impl<E, K: Eq + Hash + Send + Sync, V: Send + Sync> UpdatingMap<E, K, V> {
         }
     }
 
+    pub fn snapshot(&self) -> Snapshot<E, HashMap<K, Arc<V>>> {
+        Snapshot::new(self.get_collection())
+    }
+
+    // The entries as of the call, collected so the iterator can outlive the snapshot.
+    pub fn iter(&self) -> std::vec::IntoIter<(K, Arc<V>)> where K: Clone {
+        self.snapshot().iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>().into_iter()
+    }
+
+    // The keys as of the call.
+    pub fn keys(&self) -> std::vec::IntoIter<K> where K: Clone {
+        self.snapshot().keys().cloned().collect::<Vec<K>>().into_iter()
+    }
+
+    // The values as of the call.
+    pub fn values(&self) -> std::vec::IntoIter<Arc<V>> {
+        self.snapshot().values().cloned().collect::<Vec<Arc<V>>>().into_iter()
+    }
+
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, HashMap<K, Arc<V>>>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+
     #[allow(clippy::type_complexity)]
     fn get_collection(&self) -> Arc<Option<(Option<E>, HashMap<K, Arc<V>>)>> {
         self.backing.load_full().clone()
     }
-}
\ No newline at end of file
+}
+
+pub struct UpdatingBTreeMap<E, K: Ord, V> {
+    backing: Holder<E, BTreeMap<K, Arc<V>>>,
+    previous: Holder<E, BTreeMap<K, Arc<V>>>,
+}
+
+impl<E, K: Ord, V> UpdatingBTreeMap<E, K, V> {
+    pub fn new(backing: Holder<E, BTreeMap<K, Arc<V>>>) -> UpdatingBTreeMap<E, K, V> {
+        UpdatingBTreeMap {
+            backing,
+            previous: empty_holder(),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, b)) => b.get(key).cloned()
+        }
+    }
+
+    // The value along with the version of the dataset it was read from.
+    pub fn get_versioned(&self, key: &K) -> Option<(Option<E>, Arc<V>)> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, b)) => b.get(key).map(|val| (v.clone(), val.clone()))
+        }
+    }
+
+    // The entries with keys in the range, in key order. For tiers and thresholds, the entry
+    // covering a value is the last one in ..=value.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<(K, Arc<V>)> where K: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, b)) => b.range(range).map(|(k, v)| (k.clone(), v.clone())).collect()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, b)) => b.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, b)) => b.is_empty(),
+        }
+    }
+
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, BTreeMap<K, Arc<V>>>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_collection(&self) -> Arc<Option<(Option<E>, BTreeMap<K, Arc<V>>)>> {
+        self.backing.load_full().clone()
+    }
+}
+
+impl<E, K: Ord, V> UpdateHook<E, BTreeMap<K, Arc<V>>> for UpdatingBTreeMap<E, K, V> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, BTreeMap<K, Arc<V>>)>>, _current: &(Option<E>, BTreeMap<K, Arc<V>>)) {
+        self.previous.store(previous.clone());
+    }
+}
+
+impl<E: Clone, K: Ord, V> Versioned<E> for UpdatingBTreeMap<E, K, V> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingBTreeMap::current_version(self)
+    }
+}
+
+pub struct UpdatingVec<E, V> {
+    backing: Holder<E, Vec<Arc<V>>>,
+    previous: Holder<E, Vec<Arc<V>>>,
+}
+
+impl<E, V> UpdatingVec<E, V> {
+    pub fn new(backing: Holder<E, Vec<Arc<V>>>) -> UpdatingVec<E, V> {
+        UpdatingVec {
+            backing,
+            previous: empty_holder(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Arc<V>> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, v)) => v.get(index).cloned()
+        }
+    }
+
+    // Iterates over the elements as they were when called, unaffected by updates in the meantime,
+    // so precedence is evaluated against a consistent list.
+    pub fn iter_snapshot(&self) -> VecSnapshot<E, V> {
+        let snapshot = self.get_collection();
+        if snapshot.is_none() {
+            panic!("{}", NON_RUNNING);
+        }
+
+        VecSnapshot {
+            snapshot,
+            next: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, v)) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, v)) => v.is_empty(),
+        }
+    }
+
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, Vec<Arc<V>>>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_collection(&self) -> Arc<Option<(Option<E>, Vec<Arc<V>>)>> {
+        self.backing.load_full().clone()
+    }
+}
+
+impl<E, V> UpdateHook<E, Vec<Arc<V>>> for UpdatingVec<E, V> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, Vec<Arc<V>>)>>, _current: &(Option<E>, Vec<Arc<V>>)) {
+        self.previous.store(previous.clone());
+    }
+}
+
+impl<E: Clone, V> Versioned<E> for UpdatingVec<E, V> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingVec::current_version(self)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub struct VecSnapshot<E, V> {
+    snapshot: Arc<Option<(Option<E>, Vec<Arc<V>>)>>,
+    next: usize,
+}
+
+impl<E, V> Iterator for VecSnapshot<E, V> {
+    type Item = Arc<V>;
+
+    fn next(&mut self) -> Option<Arc<V>> {
+        let (_, v) = self.snapshot.as_ref().as_ref()?;
+        let item = v.get(self.next)?.clone();
+        self.next += 1;
+        Some(item)
+    }
+}
+
+// A trie over the bytes of string keys, for prefix lookups such as routing tables.
+pub struct Trie<V> {
+    root: TrieNode<V>,
+    len: usize,
+}
+
+struct TrieNode<V> {
+    value: Option<Arc<V>>,
+    children: HashMap<u8, TrieNode<V>>,
+}
+
+impl<V> TrieNode<V> {
+    fn new() -> TrieNode<V> {
+        TrieNode {
+            value: None,
+            children: HashMap::new(),
+        }
+    }
+}
+
+impl<V> Trie<V> {
+    pub fn new() -> Trie<V> {
+        Trie {
+            root: TrieNode::new(),
+            len: 0,
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, value: V) -> Option<Arc<V>> {
+        let mut node = &mut self.root;
+        for b in key.bytes() {
+            node = node.children.entry(b).or_insert_with(TrieNode::new);
+        }
+
+        let previous = node.value.replace(Arc::new(value));
+        if previous.is_none() {
+            self.len += 1;
+        }
+
+        previous
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Arc<V>> {
+        let mut node = &self.root;
+        for b in key.bytes() {
+            node = node.children.get(&b)?;
+        }
+
+        node.value.as_ref()
+    }
+
+    // The value of the longest key that's a prefix of the given one, if any.
+    pub fn longest_prefix_match(&self, key: &str) -> Option<&Arc<V>> {
+        let mut node = &self.root;
+        let mut longest = node.value.as_ref();
+        for b in key.bytes() {
+            match node.children.get(&b) {
+                Some(child) => node = child,
+                None => break,
+            }
+
+            if node.value.is_some() {
+                longest = node.value.as_ref();
+            }
+        }
+
+        longest
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<V> Default for Trie<V> {
+    fn default() -> Self {
+        Trie::new()
+    }
+}
+
+pub struct UpdatingTrie<E, V> {
+    backing: Holder<E, Trie<V>>,
+    previous: Holder<E, Trie<V>>,
+}
+
+impl<E, V> UpdatingTrie<E, V> {
+    pub fn new(backing: Holder<E, Trie<V>>) -> UpdatingTrie<E, V> {
+        UpdatingTrie {
+            backing,
+            previous: empty_holder(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Arc<V>> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, t)) => t.get(key).cloned()
+        }
+    }
+
+    pub fn longest_prefix_match(&self, key: &str) -> Option<Arc<V>> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, t)) => t.longest_prefix_match(key).cloned()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, t)) => t.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, t)) => t.is_empty(),
+        }
+    }
+
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, Trie<V>>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+
+    fn get_collection(&self) -> Arc<Option<(Option<E>, Trie<V>)>> {
+        self.backing.load_full().clone()
+    }
+}
+
+impl<E, V> UpdateHook<E, Trie<V>> for UpdatingTrie<E, V> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, Trie<V>)>>, _current: &(Option<E>, Trie<V>)) {
+        self.previous.store(previous.clone());
+    }
+}
+
+impl<E: Clone, V> Versioned<E> for UpdatingTrie<E, V> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingTrie::current_version(self)
+    }
+}
+
+#[cfg(feature = "regex")]
+pub struct UpdatingRegexSet<E> {
+    backing: Holder<E, RegexSet>,
+    previous: Holder<E, RegexSet>,
+}
+
+#[cfg(feature = "regex")]
+impl<E> UpdatingRegexSet<E> {
+    pub fn new(backing: Holder<E, RegexSet>) -> UpdatingRegexSet<E> {
+        UpdatingRegexSet {
+            backing,
+            previous: empty_holder(),
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, r)) => r.is_match(text)
+        }
+    }
+
+    // The patterns matching the text. Their positions shift as the set is updated, so the
+    // patterns themselves are returned rather than indices.
+    pub fn matches(&self, text: &str) -> Vec<String> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, r)) => r.matches(text).iter().map(|i| r.patterns()[i].clone()).collect()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, r)) => r.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, r)) => r.is_empty(),
+        }
+    }
+
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, RegexSet>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+
+    fn get_collection(&self) -> Arc<Option<(Option<E>, RegexSet)>> {
+        self.backing.load_full().clone()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<E> UpdateHook<E, RegexSet> for UpdatingRegexSet<E> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, RegexSet)>>, _current: &(Option<E>, RegexSet)) {
+        self.previous.store(previous.clone());
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<E: Clone> Versioned<E> for UpdatingRegexSet<E> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingRegexSet::current_version(self)
+    }
+}
+
+// Values with weights, sampled in proportion to them, such as traffic splits or canary weights.
+#[cfg(feature = "rand")]
+pub struct WeightedSet<V> {
+    values: Vec<Arc<V>>,
+    // Running totals of the weights, so sampling is a binary search.
+    cumulative: Vec<f64>,
+}
+
+#[cfg(feature = "rand")]
+impl<V> WeightedSet<V> {
+    pub fn new() -> WeightedSet<V> {
+        WeightedSet {
+            values: vec![],
+            cumulative: vec![],
+        }
+    }
+
+    // Weights must be finite and not negative. Values weighted zero are kept but never sampled.
+    pub fn insert(&mut self, value: V, weight: f64) -> Result<()> {
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(Error::new(format!("Invalid weight {}", weight).as_str()));
+        }
+
+        self.cumulative.push(self.total_weight() + weight);
+        self.values.push(Arc::new(value));
+        Ok(())
+    }
+
+    // None if there are no values or they're all weighted zero.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<&Arc<V>> {
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let point = rng.gen_range(0.0..total);
+        let i = self.cumulative.partition_point(|c| *c <= point);
+        self.values.get(i.min(self.values.len() - 1))
+    }
+
+    pub fn total_weight(&self) -> f64 {
+        self.cumulative.last().copied().unwrap_or(0.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<V> Default for WeightedSet<V> {
+    fn default() -> Self {
+        WeightedSet::new()
+    }
+}
+
+#[cfg(feature = "rand")]
+pub struct UpdatingWeightedSet<E, V> {
+    backing: Holder<E, WeightedSet<V>>,
+    previous: Holder<E, WeightedSet<V>>,
+}
+
+#[cfg(feature = "rand")]
+impl<E, V> UpdatingWeightedSet<E, V> {
+    pub fn new(backing: Holder<E, WeightedSet<V>>) -> UpdatingWeightedSet<E, V> {
+        UpdatingWeightedSet {
+            backing,
+            previous: empty_holder(),
+        }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Arc<V>> {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, w)) => w.sample(rng).cloned()
+        }
+    }
+
+    pub fn total_weight(&self) -> f64 {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, w)) => w.total_weight(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, w)) => w.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((_, w)) => w.is_empty(),
+        }
+    }
+
+    pub fn current_version(&self) -> Option<E> where E: Clone {
+        match self.get_collection().as_ref() {
+            None => panic!("{}", NON_RUNNING),
+            Some((v, _)) => v.clone()
+        }
+    }
+
+    pub fn previous_snapshot(&self) -> Option<Snapshot<E, WeightedSet<V>>> {
+        Snapshot::previous(self.previous.load_full())
+    }
+
+    fn get_collection(&self) -> Arc<Option<(Option<E>, WeightedSet<V>)>> {
+        self.backing.load_full().clone()
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<E, V> UpdateHook<E, WeightedSet<V>> for UpdatingWeightedSet<E, V> {
+    fn swapped(&self, previous: &Arc<Option<(Option<E>, WeightedSet<V>)>>, _current: &(Option<E>, WeightedSet<V>)) {
+        self.previous.store(previous.clone());
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<E: Clone, V> Versioned<E> for UpdatingWeightedSet<E, V> {
+    fn current_version(&self) -> Option<E> {
+        UpdatingWeightedSet::current_version(self)
+    }
+}
@@ -2,3 +2,6 @@ pub mod processors;
 pub mod collections;
 pub mod metrics;
 pub mod util;
+
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
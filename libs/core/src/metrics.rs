@@ -10,4 +10,7 @@ pub trait Metrics<E> {
     fn fallback_invoked(&self);
     fn fetch_error(&self, err: &Error);
     fn process_error(&self, err: &Error);
+    // Called once each time the cache goes longer than its max staleness without a successful
+    // check. Not every implementation cares, so it does nothing by default.
+    fn stale(&self, _last_successful_check: &Option<DateTime<Utc>>) {}
 }
\ No newline at end of file
@@ -1,15 +1,166 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
+use std::io;
 use std::io::{BufRead, BufReader, Read};
-use std::sync::Arc;
-use crate::util::Result;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use arc_swap::ArcSwapOption;
+#[cfg(feature = "regex")]
+use crate::collections::RegexSet;
+use crate::collections::Trie;
+#[cfg(feature = "rand")]
+use crate::collections::WeightedSet;
+use crate::util::{Diffable, Error, Result, ServedDataset};
+
+#[cfg(feature = "bincode")]
+pub mod bincode;
+
+#[cfg(feature = "bytes")]
+pub mod bytes;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "json-schema")]
+pub mod schema;
 
 pub trait RawConfigProcessor<S, T> {
     fn process(&self, raw: S) -> Result<T>;
+
+    // Handed the dataset being served when the cache is built. Wrappers pass it on to the
+    // processor they wrap.
+    fn bind_served(&self, _served: ServedDataset<T>) {}
+}
+
+// With the gzip feature enabled, gzipped payloads are recognized by their magic bytes and
+// decompressed as they're read, so pre-compressed files can be fed straight to line processors.
+#[cfg(feature = "gzip")]
+fn line_reader<'a, B: BufRead + 'a>(mut raw: B) -> io::Result<Box<dyn BufRead + 'a>> {
+    if raw.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(raw))))
+    } else {
+        Ok(Box::new(raw))
+    }
+}
+
+#[cfg(not(feature = "gzip"))]
+fn line_reader<'a, B: BufRead + 'a>(raw: B) -> io::Result<Box<dyn BufRead + 'a>> {
+    Ok(Box::new(raw))
+}
+
+type SkippedLines = Box<dyn Fn(&[(usize, Error)]) + Send + Sync>;
+
+// How line processors treat lines their parse function rejects.
+enum BadLines {
+    FailFast,
+    Collect,
+    Skip(SkippedLines),
+}
+
+fn for_each_line<B: BufRead, F: FnMut(String) -> Result<()>>(raw: B, bad_lines: &BadLines, mut each: F) -> Result<()> {
+    let mut errors = vec![];
+    let mut skipped = vec![];
+    for (i, line) in line_reader(raw)?.lines().enumerate() {
+        match (each(line?), bad_lines) {
+            (Ok(()), _) => {}
+            (Err(err), BadLines::FailFast) => return Err(err),
+            (Err(err), BadLines::Collect) => errors.push(format!("line {}: {}", i + 1, err)),
+            (Err(err), BadLines::Skip(_)) => skipped.push((i + 1, err)),
+        }
+    }
+
+    if let BadLines::Skip(report) = bad_lines {
+        if !skipped.is_empty() {
+            report(&skipped);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::new(format!("{} lines failed to parse: {}", errors.len(), errors.join("; ")).as_str()))
+    }
+}
+
+// Shares one allocation between equal values, for datasets where many entries carry the same
+// value. Unsized values such as str may be interned from references, so repeated strings inside
+// larger values can be pooled by parse functions as Arc<str>. Values only the pool still holds are
+// dropped by prune(), which processors owning a pool call after each refresh.
+pub struct ValuePool<V: ?Sized> {
+    pool: Mutex<HashSet<Arc<V>>>,
+}
+
+impl<V: Eq + Hash + ?Sized> ValuePool<V> {
+    pub fn new() -> ValuePool<V> {
+        ValuePool {
+            pool: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn intern(&self, val: V) -> Arc<V> where V: Sized {
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        match pool.get(&val) {
+            Some(pooled) => pooled.clone(),
+            None => {
+                let pooled = Arc::new(val);
+                pool.insert(pooled.clone());
+                pooled
+            }
+        }
+    }
+
+    pub fn intern_ref(&self, val: &V) -> Arc<V> where for<'a> Arc<V>: From<&'a V> {
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        match pool.get(val) {
+            Some(pooled) => pooled.clone(),
+            None => {
+                let pooled = Arc::from(val);
+                pool.insert(pooled.clone());
+                pooled
+            }
+        }
+    }
+
+    pub fn prune(&self) {
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        pool.retain(|pooled| Arc::strong_count(pooled) > 1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<V: Eq + Hash + ?Sized> Default for ValuePool<V> {
+    fn default() -> Self {
+        ValuePool::new()
+    }
+}
+
+// Lets map processors pool values without requiring every value type to be hashable.
+trait Intern<V>: Send + Sync {
+    fn intern(&self, val: V) -> Arc<V>;
+
+    fn prune(&self);
+}
+
+impl<V: Eq + Hash + Send + Sync> Intern<V> for ValuePool<V> {
+    fn intern(&self, val: V) -> Arc<V> {
+        ValuePool::intern(self, val)
+    }
+
+    fn prune(&self) {
+        ValuePool::prune(self)
+    }
 }
 
 pub struct RawLineSetProcessor<V: Eq + Hash + Sync + Send, P: Fn(String) -> Result<Option<V>>> {
     parse: P,
+    bad_lines: BadLines,
 }
 
 impl<
@@ -18,35 +169,63 @@ impl<
 > RawLineSetProcessor<V, P> {
     pub fn new(parse: P) -> RawLineSetProcessor<V, P> {
         RawLineSetProcessor {
-            parse
+            parse,
+            bad_lines: BadLines::FailFast,
         }
     }
+
+    // Reports every line that fails to parse in one error, rather than stopping at the first.
+    pub fn with_all_errors(mut self) -> RawLineSetProcessor<V, P> {
+        self.bad_lines = BadLines::Collect;
+        self
+    }
+
+    // Skips lines that fail to parse instead of failing the refresh, for feeds where a few bad
+    // rows are expected. If any were skipped, the callback is passed their line numbers and
+    // errors once the whole payload has been processed.
+    pub fn with_lenient<F>(mut self, on_skipped: F) -> RawLineSetProcessor<V, P>
+        where F: Fn(&[(usize, Error)]) + Send + Sync + 'static {
+        self.bad_lines = BadLines::Skip(Box::new(on_skipped));
+        self
+    }
 }
 
 impl<
-    R: Read,
     V: Eq + Hash + Send + Sync + 'static,
     P: Fn(String) -> Result<Option<V>> + 'static
-> RawConfigProcessor<R, HashSet<V>> for RawLineSetProcessor<V, P> {
-    fn process(&self, raw: R) -> Result<HashSet<V>> {
+> RawLineSetProcessor<V, P> {
+    fn process_lines<B: BufRead>(&self, lines: B) -> Result<HashSet<V>> {
         let mut set: HashSet<V> = HashSet::new();
-        let lines = BufReader::new(raw).lines();
-        for line in lines {
-            if let Some(v) = (self.parse)(line?)? {
+        for_each_line(lines, &self.bad_lines, |line| {
+            if let Some(v) = (self.parse)(line)? {
                 set.insert(v);
             }
-        }
+
+            Ok(())
+        })?;
 
         Ok(set)
     }
 }
 
+impl<
+    R: Read,
+    V: Eq + Hash + Send + Sync + 'static,
+    P: Fn(String) -> Result<Option<V>> + 'static
+> RawConfigProcessor<R, HashSet<V>> for RawLineSetProcessor<V, P> {
+    fn process(&self, raw: R) -> Result<HashSet<V>> {
+        self.process_lines(BufReader::new(raw))
+    }
+}
+
 pub struct RawLineMapProcessor<
     K: Eq + Hash + Sync + Send + 'static,
     V: Sync + Send + 'static,
     P: Fn(String) -> Result<Option<(K, V)>> + 'static
 > {
     parse: P,
+    bad_lines: BadLines,
+    values: Option<Box<dyn Intern<V>>>,
 }
 
 impl<
@@ -56,8 +235,65 @@ impl<
 > RawLineMapProcessor<K, V, P> {
     pub fn new(parse: P) -> RawLineMapProcessor<K, V, P> {
         RawLineMapProcessor {
-            parse
+            parse,
+            bad_lines: BadLines::FailFast,
+            values: None,
+        }
+    }
+
+    // Reports every line that fails to parse in one error, rather than stopping at the first.
+    pub fn with_all_errors(mut self) -> RawLineMapProcessor<K, V, P> {
+        self.bad_lines = BadLines::Collect;
+        self
+    }
+
+    // Skips lines that fail to parse instead of failing the refresh, for feeds where a few bad
+    // rows are expected. If any were skipped, the callback is passed their line numbers and
+    // errors once the whole payload has been processed.
+    pub fn with_lenient<F>(mut self, on_skipped: F) -> RawLineMapProcessor<K, V, P>
+        where F: Fn(&[(usize, Error)]) + Send + Sync + 'static {
+        self.bad_lines = BadLines::Skip(Box::new(on_skipped));
+        self
+    }
+}
+
+impl<
+    K: Eq + Hash + Sync + Send + 'static,
+    V: Eq + Hash + Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawLineMapProcessor<K, V, P> {
+    // Equal values share a single allocation, including with the previous dataset, which can cut
+    // resident memory considerably for large maps with many repeated values.
+    pub fn with_interning(mut self) -> RawLineMapProcessor<K, V, P> {
+        self.values = Some(Box::new(ValuePool::new()));
+        self
+    }
+}
+
+impl<
+    K: Eq + Hash + Sync + Send + 'static,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawLineMapProcessor<K, V, P> {
+    fn process_lines<B: BufRead>(&self, lines: B) -> Result<HashMap<K, Arc<V>>> {
+        let mut map: HashMap<K, Arc<V>> = HashMap::new();
+        for_each_line(lines, &self.bad_lines, |line| {
+            if let Some((k, v)) = (self.parse)(line)? {
+                let v = match &self.values {
+                    Some(values) => values.intern(v),
+                    None => Arc::new(v),
+                };
+                map.insert(k, v);
+            }
+
+            Ok(())
+        })?;
+
+        if let Some(values) = &self.values {
+            values.prune();
         }
+
+        Ok(map)
     }
 }
 
@@ -68,14 +304,731 @@ impl<
     P: Fn(String) -> Result<Option<(K, V)>> + 'static
 > RawConfigProcessor<R, HashMap<K, Arc<V>>> for RawLineMapProcessor<K, V, P> {
     fn process(&self, raw: R) -> Result<HashMap<K, Arc<V>>> {
-        let mut map: HashMap<K, Arc<V>> = HashMap::new();
-        let lines = BufReader::new(raw).lines();
+        self.process_lines(BufReader::new(raw))
+    }
+}
+
+pub struct RawLineVecProcessor<V: Sync + Send + 'static, P: Fn(String) -> Result<Option<V>> + 'static> {
+    parse: P,
+    bad_lines: BadLines,
+}
+
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<V>> + 'static
+> RawLineVecProcessor<V, P> {
+    pub fn new(parse: P) -> RawLineVecProcessor<V, P> {
+        RawLineVecProcessor {
+            parse,
+            bad_lines: BadLines::FailFast,
+        }
+    }
+
+    // Reports every line that fails to parse in one error, rather than stopping at the first.
+    pub fn with_all_errors(mut self) -> RawLineVecProcessor<V, P> {
+        self.bad_lines = BadLines::Collect;
+        self
+    }
+
+    // Skips lines that fail to parse instead of failing the refresh, for feeds where a few bad
+    // rows are expected. If any were skipped, the callback is passed their line numbers and
+    // errors once the whole payload has been processed.
+    pub fn with_lenient<F>(mut self, on_skipped: F) -> RawLineVecProcessor<V, P>
+        where F: Fn(&[(usize, Error)]) + Send + Sync + 'static {
+        self.bad_lines = BadLines::Skip(Box::new(on_skipped));
+        self
+    }
+}
+
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<V>> + 'static
+> RawLineVecProcessor<V, P> {
+    fn process_lines<B: BufRead>(&self, lines: B) -> Result<Vec<Arc<V>>> {
+        let mut vec: Vec<Arc<V>> = Vec::new();
+        for_each_line(lines, &self.bad_lines, |line| {
+            if let Some(v) = (self.parse)(line)? {
+                vec.push(Arc::new(v));
+            }
+
+            Ok(())
+        })?;
+
+        Ok(vec)
+    }
+}
+
+impl<
+    R: Read,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<V>> + 'static
+> RawConfigProcessor<R, Vec<Arc<V>>> for RawLineVecProcessor<V, P> {
+    fn process(&self, raw: R) -> Result<Vec<Arc<V>>> {
+        self.process_lines(BufReader::new(raw))
+    }
+}
+
+pub struct RawLineBTreeMapProcessor<
+    K: Ord + Sync + Send + 'static,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> {
+    parse: P,
+    bad_lines: BadLines,
+    values: Option<Box<dyn Intern<V>>>,
+}
+
+impl<
+    K: Ord + Sync + Send + 'static,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawLineBTreeMapProcessor<K, V, P> {
+    pub fn new(parse: P) -> RawLineBTreeMapProcessor<K, V, P> {
+        RawLineBTreeMapProcessor {
+            parse,
+            bad_lines: BadLines::FailFast,
+            values: None,
+        }
+    }
+
+    // Reports every line that fails to parse in one error, rather than stopping at the first.
+    pub fn with_all_errors(mut self) -> RawLineBTreeMapProcessor<K, V, P> {
+        self.bad_lines = BadLines::Collect;
+        self
+    }
+
+    // Skips lines that fail to parse instead of failing the refresh, for feeds where a few bad
+    // rows are expected. If any were skipped, the callback is passed their line numbers and
+    // errors once the whole payload has been processed.
+    pub fn with_lenient<F>(mut self, on_skipped: F) -> RawLineBTreeMapProcessor<K, V, P>
+        where F: Fn(&[(usize, Error)]) + Send + Sync + 'static {
+        self.bad_lines = BadLines::Skip(Box::new(on_skipped));
+        self
+    }
+}
+
+impl<
+    K: Ord + Sync + Send + 'static,
+    V: Eq + Hash + Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawLineBTreeMapProcessor<K, V, P> {
+    // Equal values share a single allocation, including with the previous dataset, which can cut
+    // resident memory considerably for large maps with many repeated values.
+    pub fn with_interning(mut self) -> RawLineBTreeMapProcessor<K, V, P> {
+        self.values = Some(Box::new(ValuePool::new()));
+        self
+    }
+}
+
+impl<
+    K: Ord + Sync + Send + 'static,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawLineBTreeMapProcessor<K, V, P> {
+    fn process_lines<B: BufRead>(&self, lines: B) -> Result<BTreeMap<K, Arc<V>>> {
+        let mut map: BTreeMap<K, Arc<V>> = BTreeMap::new();
+        for_each_line(lines, &self.bad_lines, |line| {
+            if let Some((k, v)) = (self.parse)(line)? {
+                let v = match &self.values {
+                    Some(values) => values.intern(v),
+                    None => Arc::new(v),
+                };
+                map.insert(k, v);
+            }
+
+            Ok(())
+        })?;
+
+        if let Some(values) = &self.values {
+            values.prune();
+        }
+
+        Ok(map)
+    }
+}
+
+impl<
+    R: Read,
+    K: Ord + Sync + Send + 'static,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawConfigProcessor<R, BTreeMap<K, Arc<V>>> for RawLineBTreeMapProcessor<K, V, P> {
+    fn process(&self, raw: R) -> Result<BTreeMap<K, Arc<V>>> {
+        self.process_lines(BufReader::new(raw))
+    }
+}
+
+pub struct RawLineTrieProcessor<V: Sync + Send + 'static, P: Fn(String) -> Result<Option<(String, V)>> + 'static> {
+    parse: P,
+    bad_lines: BadLines,
+}
+
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(String, V)>> + 'static
+> RawLineTrieProcessor<V, P> {
+    pub fn new(parse: P) -> RawLineTrieProcessor<V, P> {
+        RawLineTrieProcessor {
+            parse,
+            bad_lines: BadLines::FailFast,
+        }
+    }
+
+    // Reports every line that fails to parse in one error, rather than stopping at the first.
+    pub fn with_all_errors(mut self) -> RawLineTrieProcessor<V, P> {
+        self.bad_lines = BadLines::Collect;
+        self
+    }
+
+    // Skips lines that fail to parse instead of failing the refresh, for feeds where a few bad
+    // rows are expected. If any were skipped, the callback is passed their line numbers and
+    // errors once the whole payload has been processed.
+    pub fn with_lenient<F>(mut self, on_skipped: F) -> RawLineTrieProcessor<V, P>
+        where F: Fn(&[(usize, Error)]) + Send + Sync + 'static {
+        self.bad_lines = BadLines::Skip(Box::new(on_skipped));
+        self
+    }
+}
+
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(String, V)>> + 'static
+> RawLineTrieProcessor<V, P> {
+    fn process_lines<B: BufRead>(&self, lines: B) -> Result<Trie<V>> {
+        let mut trie: Trie<V> = Trie::new();
+        for_each_line(lines, &self.bad_lines, |line| {
+            if let Some((k, v)) = (self.parse)(line)? {
+                trie.insert(&k, v);
+            }
+
+            Ok(())
+        })?;
+
+        Ok(trie)
+    }
+}
+
+impl<
+    R: Read,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(String, V)>> + 'static
+> RawConfigProcessor<R, Trie<V>> for RawLineTrieProcessor<V, P> {
+    fn process(&self, raw: R) -> Result<Trie<V>> {
+        self.process_lines(BufReader::new(raw))
+    }
+}
+
+// Builds a WeightedSet from lines parsed into values and their weights.
+#[cfg(feature = "rand")]
+pub struct RawLineWeightedSetProcessor<V: Sync + Send + 'static, P: Fn(String) -> Result<Option<(V, f64)>> + 'static> {
+    parse: P,
+    bad_lines: BadLines,
+}
+
+#[cfg(feature = "rand")]
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(V, f64)>> + 'static
+> RawLineWeightedSetProcessor<V, P> {
+    pub fn new(parse: P) -> RawLineWeightedSetProcessor<V, P> {
+        RawLineWeightedSetProcessor {
+            parse,
+            bad_lines: BadLines::FailFast,
+        }
+    }
+
+    // Reports every line that fails to parse in one error, rather than stopping at the first.
+    pub fn with_all_errors(mut self) -> RawLineWeightedSetProcessor<V, P> {
+        self.bad_lines = BadLines::Collect;
+        self
+    }
+
+    // Skips lines that fail to parse instead of failing the refresh, for feeds where a few bad
+    // rows are expected. If any were skipped, the callback is passed their line numbers and
+    // errors once the whole payload has been processed.
+    pub fn with_lenient<F>(mut self, on_skipped: F) -> RawLineWeightedSetProcessor<V, P>
+        where F: Fn(&[(usize, Error)]) + Send + Sync + 'static {
+        self.bad_lines = BadLines::Skip(Box::new(on_skipped));
+        self
+    }
+
+    fn process_lines<B: BufRead>(&self, lines: B) -> Result<WeightedSet<V>> {
+        let mut set: WeightedSet<V> = WeightedSet::new();
+        for_each_line(lines, &self.bad_lines, |line| {
+            if let Some((v, weight)) = (self.parse)(line)? {
+                set.insert(v, weight)?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(set)
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<
+    R: Read,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(V, f64)>> + 'static
+> RawConfigProcessor<R, WeightedSet<V>> for RawLineWeightedSetProcessor<V, P> {
+    fn process(&self, raw: R) -> Result<WeightedSet<V>> {
+        self.process_lines(BufReader::new(raw))
+    }
+}
+
+// Compiles one regex per line into a RegexSet. Blank lines and lines starting with '#' are skipped.
+#[cfg(feature = "regex")]
+pub struct RawLineRegexSetProcessor {}
+
+#[cfg(feature = "regex")]
+impl RawLineRegexSetProcessor {
+    pub fn new() -> RawLineRegexSetProcessor {
+        RawLineRegexSetProcessor {}
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Default for RawLineRegexSetProcessor {
+    fn default() -> Self {
+        RawLineRegexSetProcessor::new()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl RawLineRegexSetProcessor {
+    fn process_lines<B: BufRead>(&self, lines: B) -> Result<RegexSet> {
+        let mut patterns = vec![];
+        let lines = line_reader(lines)?.lines();
         for line in lines {
-            if let Some((k, v)) = (self.parse)(line?)? {
-                map.insert(k, Arc::new(v));
+            let line = line?;
+            if !line.trim().is_empty() && !line.starts_with('#') {
+                patterns.push(line);
             }
         }
 
+        Ok(RegexSet::new(patterns)?)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<R: Read> RawConfigProcessor<R, RegexSet> for RawLineRegexSetProcessor {
+    fn process(&self, raw: R) -> Result<RegexSet> {
+        self.process_lines(BufReader::new(raw))
+    }
+}
+
+// Caps how much of the payload another processor may read. Line processors consume their input
+// incrementally, so this bounds the memory one oversized upstream file can take up, failing
+// processing cleanly instead once more than max_bytes have been read.
+pub struct SizeLimitedProcessor<P> {
+    inner: P,
+    max_bytes: u64,
+}
+
+impl<P> SizeLimitedProcessor<P> {
+    pub fn new(inner: P, max_bytes: u64) -> SizeLimitedProcessor<P> {
+        SizeLimitedProcessor {
+            inner,
+            max_bytes,
+        }
+    }
+}
+
+impl<
+    R: Read,
+    T,
+    P: RawConfigProcessor<LimitedReader<R>, T>
+> RawConfigProcessor<R, T> for SizeLimitedProcessor<P> {
+    fn process(&self, raw: R) -> Result<T> {
+        self.inner.process(LimitedReader::new(raw, self.max_bytes))
+    }
+
+    fn bind_served(&self, served: ServedDataset<T>) {
+        self.inner.bind_served(served);
+    }
+}
+
+pub struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+    max_bytes: u64,
+}
+
+impl<R> LimitedReader<R> {
+    pub fn new(inner: R, max_bytes: u64) -> LimitedReader<R> {
+        LimitedReader {
+            inner,
+            remaining: max_bytes,
+            max_bytes,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Read one byte past the limit, so a payload of exactly max_bytes isn't rejected.
+        let allowed = buf.len().min(self.remaining.saturating_add(1).try_into().unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..allowed])?;
+        if read as u64 > self.remaining {
+            return Err(io::Error::other(format!("Payload exceeds the limit of {} bytes", self.max_bytes)));
+        }
+
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+// Checks the output of another processor before it's swapped in, for example that a map isn't
+// empty and holds the keys it must. Errors returned by the check fail processing, so obviously
+// broken configs never replace working ones.
+pub struct ValidatedProcessor<P, F> {
+    inner: P,
+    validate: F,
+}
+
+impl<P, F> ValidatedProcessor<P, F> {
+    pub fn new(inner: P, validate: F) -> ValidatedProcessor<P, F> {
+        ValidatedProcessor {
+            inner,
+            validate,
+        }
+    }
+}
+
+impl<
+    S,
+    T,
+    P: RawConfigProcessor<S, T>,
+    F: Fn(&T) -> Result<()>
+> RawConfigProcessor<S, T> for ValidatedProcessor<P, F> {
+    fn process(&self, raw: S) -> Result<T> {
+        let processed = self.inner.process(raw)?;
+        (self.validate)(&processed)?;
+        Ok(processed)
+    }
+
+    fn bind_served(&self, served: ServedDataset<T>) {
+        self.inner.bind_served(served);
+    }
+}
+
+// Rejects datasets that differ too much from the one being served, so a truncated upstream file
+// can't wipe out a critical allowlist. That includes one restored from a snapshot at startup, or
+// rolled back to. Rejections fail processing, leaving the current dataset in place, and since the
+// version isn't advanced the same update is fetched and rejected again on each poll until it's
+// fixed upstream or confirmed with the handle from confirmation().
+pub struct ChangeGuardProcessor<P, T> {
+    inner: P,
+    max_changed: Option<f64>,
+    min_entries: Option<usize>,
+    served: Mutex<Option<ServedDataset<T>>>,
+    confirmed: Arc<AtomicBool>,
+}
+
+impl<P, T> ChangeGuardProcessor<P, T> {
+    pub fn new(inner: P) -> ChangeGuardProcessor<P, T> {
+        ChangeGuardProcessor {
+            inner,
+            max_changed: None,
+            min_entries: None,
+            served: Mutex::new(None),
+            confirmed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Rejects updates adding, removing or changing more than this fraction of the keys in the
+    // dataset being served, 0.2 for example allowing a fifth of them to change at once. Nothing
+    // is served before the first real dataset, so only with_min_entries() guards that one.
+    pub fn with_max_changed(mut self, fraction: f64) -> ChangeGuardProcessor<P, T> {
+        self.max_changed = Some(fraction);
+        self
+    }
+
+    // Rejects any dataset with fewer keys than this, including the first.
+    pub fn with_min_entries(mut self, min_entries: usize) -> ChangeGuardProcessor<P, T> {
+        self.min_entries = Some(min_entries);
+        self
+    }
+
+    pub fn confirmation(&self) -> ChangeConfirmation {
+        ChangeConfirmation {
+            confirmed: self.confirmed.clone(),
+        }
+    }
+
+    fn check(&self, processed: &T) -> Result<()> where T: Diffable {
+        if let Some(min_entries) = self.min_entries {
+            if processed.entries() < min_entries {
+                return Err(Error::new(format!(
+                    "Update has {} entries, fewer than the minimum of {}", processed.entries(), min_entries
+                ).as_str()));
+            }
+        }
+
+        let Some(max_changed) = self.max_changed else {
+            return Ok(());
+        };
+        let Some(served) = self.served.lock().unwrap_or_else(PoisonError::into_inner).clone() else {
+            return Ok(());
+        };
+
+        // Nothing is lost replacing an empty dataset, so any change to one is let through.
+        let changed = served.with(|current| match current.entries() {
+            0 => 0.0,
+            entries => {
+                let diff = processed.diff(Some(current));
+                (diff.added.len() + diff.removed.len() + diff.changed.len()) as f64 / entries as f64
+            }
+        });
+        match changed {
+            Some(changed) if changed > max_changed => Err(Error::new(format!(
+                "Update changes {:.0}% of entries, more than the allowed {:.0}%", changed * 100.0, max_changed * 100.0
+            ).as_str())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<
+    S,
+    T: Diffable,
+    P: RawConfigProcessor<S, T>
+> RawConfigProcessor<S, T> for ChangeGuardProcessor<P, T> {
+    fn process(&self, raw: S) -> Result<T> {
+        let processed = self.inner.process(raw)?;
+        // A confirmation is used up by the next dataset processed, whether or not it needed it.
+        if !self.confirmed.swap(false, Ordering::AcqRel) {
+            self.check(&processed)?;
+        }
+
+        Ok(processed)
+    }
+
+    fn bind_served(&self, served: ServedDataset<T>) {
+        *self.served.lock().unwrap_or_else(PoisonError::into_inner) = Some(served.clone());
+        self.inner.bind_served(served);
+    }
+}
+
+// Lets the next dataset through a ChangeGuardProcessor however much it changes, for when a large
+// change is intended. Pair with refresh_now() to apply it straight away.
+#[derive(Clone)]
+pub struct ChangeConfirmation {
+    confirmed: Arc<AtomicBool>,
+}
+
+impl ChangeConfirmation {
+    pub fn confirm(&self) {
+        self.confirmed.store(true, Ordering::Release);
+    }
+}
+
+// Processors that produce metadata alongside the dataset, such as header comments, a generation
+// timestamp or row counts.
+pub trait MetadataProcessor<S, T, D> {
+    fn process_with_metadata(&self, raw: S) -> Result<(T, D)>;
+}
+
+// Adapts a MetadataProcessor for use with the cache, which holds only the dataset. The metadata
+// from the latest successful processing is published to the sidecar before the dataset is swapped
+// in, so update callbacks and metrics holding a clone of it see the metadata of the new dataset.
+pub struct SidecarProcessor<P, D> {
+    inner: P,
+    sidecar: MetadataSidecar<D>,
+}
+
+impl<P, D> SidecarProcessor<P, D> {
+    pub fn new(inner: P) -> SidecarProcessor<P, D> {
+        SidecarProcessor {
+            inner,
+            sidecar: MetadataSidecar {
+                latest: Arc::new(ArcSwapOption::empty()),
+            },
+        }
+    }
+
+    pub fn sidecar(&self) -> MetadataSidecar<D> {
+        self.sidecar.clone()
+    }
+}
+
+impl<
+    S,
+    T,
+    D,
+    P: MetadataProcessor<S, T, D>
+> RawConfigProcessor<S, T> for SidecarProcessor<P, D> {
+    fn process(&self, raw: S) -> Result<T> {
+        let (processed, metadata) = self.inner.process_with_metadata(raw)?;
+        self.sidecar.latest.store(Some(Arc::new(metadata)));
+        Ok(processed)
+    }
+}
+
+pub struct MetadataSidecar<D> {
+    latest: Arc<ArcSwapOption<D>>,
+}
+
+impl<D> MetadataSidecar<D> {
+    // None until the first successful processing.
+    pub fn get(&self) -> Option<Arc<D>> {
+        self.latest.load_full()
+    }
+}
+
+impl<D> Clone for MetadataSidecar<D> {
+    fn clone(&self) -> Self {
+        MetadataSidecar {
+            latest: self.latest.clone(),
+        }
+    }
+}
+
+// Parses INI files into sections of key value pairs, keys before the first section header land
+// in the section "". Both '=' and ':' separate keys from values, and lines starting with ';' or
+// '#' are comments.
+pub struct IniProcessor {}
+
+impl IniProcessor {
+    pub fn new() -> IniProcessor {
+        IniProcessor {}
+    }
+}
+
+impl Default for IniProcessor {
+    fn default() -> Self {
+        IniProcessor::new()
+    }
+}
+
+impl<R: Read> RawConfigProcessor<R, HashMap<String, HashMap<String, String>>> for IniProcessor {
+    fn process(&self, raw: R) -> Result<HashMap<String, HashMap<String, String>>> {
+        let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+        parse_ini(raw, |section, key, value| {
+            sections.entry(String::from(section)).or_default().insert(key, value);
+        })?;
+
+        Ok(sections)
+    }
+}
+
+// Parses INI files like IniProcessor, but flattens them into a map keyed by "section.key", or just
+// "key" before the first section header.
+pub struct IniMapProcessor {}
+
+impl IniMapProcessor {
+    pub fn new() -> IniMapProcessor {
+        IniMapProcessor {}
+    }
+}
+
+impl Default for IniMapProcessor {
+    fn default() -> Self {
+        IniMapProcessor::new()
+    }
+}
+
+impl<R: Read> RawConfigProcessor<R, HashMap<String, Arc<String>>> for IniMapProcessor {
+    fn process(&self, raw: R) -> Result<HashMap<String, Arc<String>>> {
+        let mut map: HashMap<String, Arc<String>> = HashMap::new();
+        parse_ini(raw, |section, key, value| {
+            let key = if section.is_empty() { key } else { format!("{}.{}", section, key) };
+            map.insert(key, Arc::new(value));
+        })?;
+
         Ok(map)
     }
-}
\ No newline at end of file
+}
+
+fn parse_ini<R: Read, F: FnMut(&str, String, String)>(raw: R, mut entry: F) -> Result<()> {
+    let mut section = String::new();
+    for (i, line) in line_reader(BufReader::new(raw))?.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = String::from(name.trim());
+        } else if let Some((key, value)) = line.split_once(['=', ':']) {
+            entry(&section, String::from(key.trim()), String::from(value.trim()));
+        } else {
+            return Err(Error::new(format!("Unparseable INI line {}: {}", i + 1, line).as_str()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+    use arc_swap::ArcSwap;
+
+    use crate::processors::{ChangeGuardProcessor, RawConfigProcessor, RawLineSetProcessor};
+    use crate::util::{RefreshStatus, Result, ServedDataset};
+
+    type LineSet = RawLineSetProcessor<String, fn(String) -> Result<Option<String>>>;
+
+    fn lines(range: std::ops::Range<usize>) -> Cursor<String> {
+        Cursor::new(range.map(|i| format!("{}\n", i)).collect())
+    }
+
+    fn guard() -> ChangeGuardProcessor<LineSet, HashSet<String>> {
+        ChangeGuardProcessor::new(RawLineSetProcessor::new(|line| Ok(Some(line))))
+    }
+
+    fn serve(processor: &ChangeGuardProcessor<LineSet, HashSet<String>>, dataset: HashSet<String>, loaded: bool) {
+        let mut status = RefreshStatus::new();
+        if loaded {
+            status.swapped();
+        }
+
+        let holder = Arc::new(ArcSwap::new(Arc::new(Some((Some(1), dataset)))));
+        let served = ServedDataset::new(holder, Arc::new(Mutex::new(status)));
+        RawConfigProcessor::<Cursor<String>, _>::bind_served(processor, served);
+    }
+
+    #[test]
+    fn change_guard_measures_against_served() {
+        let processor = guard().with_max_changed(0.2);
+        serve(&processor, (0..10).map(|i| i.to_string()).collect(), true);
+
+        assert!(processor.process(lines(0..5)).is_err());
+        assert_eq!(processor.process(lines(0..11)).unwrap().len(), 11);
+    }
+
+    #[test]
+    fn change_guard_confirmation_lets_one_through() {
+        let processor = guard().with_max_changed(0.2);
+        serve(&processor, (0..10).map(|i| i.to_string()).collect(), true);
+
+        processor.confirmation().confirm();
+        assert!(processor.process(lines(0..2)).is_ok());
+        assert!(processor.process(lines(0..2)).is_err());
+    }
+
+    #[test]
+    fn change_guard_ignores_unloaded_dataset() {
+        let processor = guard().with_max_changed(0.2);
+        serve(&processor, (0..10).map(|i| i.to_string()).collect(), false);
+
+        assert!(processor.process(lines(0..2)).is_ok());
+    }
+
+    #[test]
+    fn change_guard_min_entries() {
+        let processor = guard().with_min_entries(3);
+
+        assert!(processor.process(lines(0..2)).is_err());
+        assert!(processor.process(lines(0..3)).is_ok());
+    }
+}
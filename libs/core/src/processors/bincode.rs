@@ -0,0 +1,43 @@
+use std::io::{BufReader, Read};
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "bytes")]
+use crate::processors::bytes::BytesPayload;
+use crate::processors::RawConfigProcessor;
+use crate::util::Result;
+
+// Deserializes a payload written with bincode's default options, for snapshots produced
+// internally where both ends are under our control. Much faster than text formats for very large
+// maps, but the writer and reader have to agree exactly on the type.
+pub struct BincodeProcessor<T> {
+    _phantom_t: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> BincodeProcessor<T> {
+    pub fn new() -> BincodeProcessor<T> {
+        BincodeProcessor {
+            _phantom_t: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Default for BincodeProcessor<T> {
+    fn default() -> Self {
+        BincodeProcessor::new()
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> RawConfigProcessor<R, T> for BincodeProcessor<T> {
+    fn process(&self, raw: R) -> Result<T> {
+        Ok(::bincode::deserialize_from(BufReader::new(raw))?)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<T: DeserializeOwned> RawConfigProcessor<BytesPayload, T> for BincodeProcessor<T> {
+    fn process(&self, raw: BytesPayload) -> Result<T> {
+        Ok(::bincode::deserialize(&raw)?)
+    }
+}
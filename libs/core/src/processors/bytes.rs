@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::ops::Deref;
+use std::sync::Arc;
+
+pub use ::bytes::Bytes;
+
+#[cfg(feature = "regex")]
+use crate::collections::RegexSet;
+use crate::collections::Trie;
+#[cfg(feature = "rand")]
+use crate::collections::WeightedSet;
+#[cfg(feature = "regex")]
+use crate::processors::RawLineRegexSetProcessor;
+#[cfg(feature = "rand")]
+use crate::processors::RawLineWeightedSetProcessor;
+use crate::processors::{
+    RawConfigProcessor, RawLineBTreeMapProcessor, RawLineMapProcessor, RawLineSetProcessor,
+    RawLineTrieProcessor, RawLineVecProcessor,
+};
+use crate::util::Result;
+
+// A payload already held in memory in full, such as a response body handed over by an HTTP client.
+// Processors parse it in place instead of reading it through a BufReader, so the bytes are never
+// copied between fetch and parse. It deliberately isn't Read, processors that only accept readers
+// can be given a std::io::Cursor over it.
+pub struct BytesPayload {
+    bytes: Bytes,
+}
+
+impl BytesPayload {
+    pub fn new(bytes: Bytes) -> BytesPayload {
+        BytesPayload {
+            bytes
+        }
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+}
+
+impl From<Bytes> for BytesPayload {
+    fn from(bytes: Bytes) -> Self {
+        BytesPayload::new(bytes)
+    }
+}
+
+impl From<Vec<u8>> for BytesPayload {
+    fn from(bytes: Vec<u8>) -> Self {
+        BytesPayload::new(Bytes::from(bytes))
+    }
+}
+
+impl Deref for BytesPayload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for BytesPayload {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<
+    V: Eq + Hash + Send + Sync + 'static,
+    P: Fn(String) -> Result<Option<V>> + 'static
+> RawConfigProcessor<BytesPayload, HashSet<V>> for RawLineSetProcessor<V, P> {
+    fn process(&self, raw: BytesPayload) -> Result<HashSet<V>> {
+        self.process_lines(&raw[..])
+    }
+}
+
+impl<
+    K: Eq + Hash + Sync + Send + 'static,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawConfigProcessor<BytesPayload, HashMap<K, Arc<V>>> for RawLineMapProcessor<K, V, P> {
+    fn process(&self, raw: BytesPayload) -> Result<HashMap<K, Arc<V>>> {
+        self.process_lines(&raw[..])
+    }
+}
+
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<V>> + 'static
+> RawConfigProcessor<BytesPayload, Vec<Arc<V>>> for RawLineVecProcessor<V, P> {
+    fn process(&self, raw: BytesPayload) -> Result<Vec<Arc<V>>> {
+        self.process_lines(&raw[..])
+    }
+}
+
+impl<
+    K: Ord + Sync + Send + 'static,
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(K, V)>> + 'static
+> RawConfigProcessor<BytesPayload, BTreeMap<K, Arc<V>>> for RawLineBTreeMapProcessor<K, V, P> {
+    fn process(&self, raw: BytesPayload) -> Result<BTreeMap<K, Arc<V>>> {
+        self.process_lines(&raw[..])
+    }
+}
+
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(String, V)>> + 'static
+> RawConfigProcessor<BytesPayload, Trie<V>> for RawLineTrieProcessor<V, P> {
+    fn process(&self, raw: BytesPayload) -> Result<Trie<V>> {
+        self.process_lines(&raw[..])
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<
+    V: Sync + Send + 'static,
+    P: Fn(String) -> Result<Option<(V, f64)>> + 'static
+> RawConfigProcessor<BytesPayload, WeightedSet<V>> for RawLineWeightedSetProcessor<V, P> {
+    fn process(&self, raw: BytesPayload) -> Result<WeightedSet<V>> {
+        self.process_lines(&raw[..])
+    }
+}
+
+#[cfg(feature = "regex")]
+impl RawConfigProcessor<BytesPayload, RegexSet> for RawLineRegexSetProcessor {
+    fn process(&self, raw: BytesPayload) -> Result<RegexSet> {
+        self.process_lines(&raw[..])
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::io::{BufReader, Read};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "bytes")]
+use crate::processors::bytes::BytesPayload;
+use crate::processors::RawConfigProcessor;
+use crate::util::Result;
+
+// Deserializes the whole payload as a single JSON document, for use with object_builder().
+pub struct JsonProcessor<T> {
+    _phantom_t: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> JsonProcessor<T> {
+    pub fn new() -> JsonProcessor<T> {
+        JsonProcessor {
+            _phantom_t: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Default for JsonProcessor<T> {
+    fn default() -> Self {
+        JsonProcessor::new()
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> RawConfigProcessor<R, T> for JsonProcessor<T> {
+    fn process(&self, raw: R) -> Result<T> {
+        Ok(serde_json::from_reader(BufReader::new(raw))?)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<T: DeserializeOwned> RawConfigProcessor<BytesPayload, T> for JsonProcessor<T> {
+    fn process(&self, raw: BytesPayload) -> Result<T> {
+        Ok(serde_json::from_slice(&raw)?)
+    }
+}
+
+// Deserializes a JSON object into a map, for use with map_builder(). Keys are deserialized from the
+// object's field names, so they can be anything deserializable from a string.
+pub struct JsonMapProcessor<K, V> {
+    _phantom_k: PhantomData<fn() -> K>,
+    _phantom_v: PhantomData<fn() -> V>,
+}
+
+impl<K: DeserializeOwned + Eq + Hash, V: DeserializeOwned> JsonMapProcessor<K, V> {
+    pub fn new() -> JsonMapProcessor<K, V> {
+        JsonMapProcessor {
+            _phantom_k: PhantomData,
+            _phantom_v: PhantomData,
+        }
+    }
+}
+
+impl<K: DeserializeOwned + Eq + Hash, V: DeserializeOwned> Default for JsonMapProcessor<K, V> {
+    fn default() -> Self {
+        JsonMapProcessor::new()
+    }
+}
+
+impl<
+    R: Read,
+    K: DeserializeOwned + Eq + Hash,
+    V: DeserializeOwned
+> RawConfigProcessor<R, HashMap<K, Arc<V>>> for JsonMapProcessor<K, V> {
+    fn process(&self, raw: R) -> Result<HashMap<K, Arc<V>>> {
+        let map: HashMap<K, V> = serde_json::from_reader(BufReader::new(raw))?;
+        Ok(map.into_iter().map(|(k, v)| (k, Arc::new(v))).collect())
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<
+    K: DeserializeOwned + Eq + Hash,
+    V: DeserializeOwned
+> RawConfigProcessor<BytesPayload, HashMap<K, Arc<V>>> for JsonMapProcessor<K, V> {
+    fn process(&self, raw: BytesPayload) -> Result<HashMap<K, Arc<V>>> {
+        let map: HashMap<K, V> = serde_json::from_slice(&raw)?;
+        Ok(map.into_iter().map(|(k, v)| (k, Arc::new(v))).collect())
+    }
+}
+
+// Deserializes a JSON array into a set, for use with set_builder().
+pub struct JsonSetProcessor<V> {
+    _phantom_v: PhantomData<fn() -> V>,
+}
+
+impl<V: DeserializeOwned + Eq + Hash> JsonSetProcessor<V> {
+    pub fn new() -> JsonSetProcessor<V> {
+        JsonSetProcessor {
+            _phantom_v: PhantomData,
+        }
+    }
+}
+
+impl<V: DeserializeOwned + Eq + Hash> Default for JsonSetProcessor<V> {
+    fn default() -> Self {
+        JsonSetProcessor::new()
+    }
+}
+
+impl<R: Read, V: DeserializeOwned + Eq + Hash> RawConfigProcessor<R, HashSet<V>> for JsonSetProcessor<V> {
+    fn process(&self, raw: R) -> Result<HashSet<V>> {
+        Ok(serde_json::from_reader(BufReader::new(raw))?)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<V: DeserializeOwned + Eq + Hash> RawConfigProcessor<BytesPayload, HashSet<V>> for JsonSetProcessor<V> {
+    fn process(&self, raw: BytesPayload) -> Result<HashSet<V>> {
+        Ok(serde_json::from_slice(&raw)?)
+    }
+}
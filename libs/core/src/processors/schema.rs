@@ -0,0 +1,47 @@
+use std::io::{Cursor, Read};
+
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+use crate::processors::RawConfigProcessor;
+use crate::util::{Error, Result, ServedDataset};
+
+// Validates a JSON payload against a JSON Schema before handing it on to another processor, so a
+// malformed push fails processing with a description of what's wrong and the old data is kept.
+pub struct SchemaValidatedProcessor<P> {
+    schema: JSONSchema,
+    inner: P,
+}
+
+impl<P> SchemaValidatedProcessor<P> {
+    pub fn new(schema: &Value, inner: P) -> Result<SchemaValidatedProcessor<P>> {
+        let schema = JSONSchema::compile(schema)
+            .map_err(|err| Error::new(format!("Invalid JSON Schema: {}", err).as_str()))?;
+
+        Ok(SchemaValidatedProcessor {
+            schema,
+            inner,
+        })
+    }
+}
+
+impl<R: Read, T, P: RawConfigProcessor<Cursor<Vec<u8>>, T>> RawConfigProcessor<R, T> for SchemaValidatedProcessor<P> {
+    fn process(&self, mut raw: R) -> Result<T> {
+        let mut payload = Vec::new();
+        raw.read_to_end(&mut payload)?;
+
+        let instance: Value = serde_json::from_slice(&payload)?;
+        if let Err(errors) = self.schema.validate(&instance) {
+            let errors: Vec<String> = errors
+                .map(|err| format!("{}: {}", err.instance_path, err))
+                .collect();
+            return Err(Error::new(format!("Schema validation failed: {}", errors.join("; ")).as_str()));
+        }
+
+        self.inner.process(Cursor::new(payload))
+    }
+
+    fn bind_served(&self, served: ServedDataset<T>) {
+        self.inner.bind_served(served);
+    }
+}
@@ -0,0 +1,32 @@
+use std::fs;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::util::Result;
+
+// A dataset and its version, serialized with bincode's default options. Like BincodeProcessor,
+// the writer and reader have to agree exactly on the types.
+pub fn to_bytes<E: Serialize, T: Serialize>(version: &Option<E>, dataset: &T) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(&(version, dataset))?)
+}
+
+pub fn from_bytes<E: DeserializeOwned, T: DeserializeOwned>(bytes: &[u8]) -> Result<(Option<E>, T)> {
+    Ok(bincode::deserialize(bytes)?)
+}
+
+// Written to a temporary file first and renamed over the old copy so a crash can't leave it torn.
+pub fn save<E: Serialize, T: Serialize>(path: &Path, version: &Option<E>, dataset: &T) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = BufWriter::new(fs::File::create(&tmp)?);
+    bincode::serialize_into(&mut file, &(version, dataset))?;
+    file.into_inner()?.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load<E: DeserializeOwned, T: DeserializeOwned>(path: &Path) -> Result<(Option<E>, T)> {
+    Ok(bincode::deserialize_from(BufReader::new(fs::File::open(path)?))?)
+}
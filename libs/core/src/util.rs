@@ -1,7 +1,12 @@
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::{Display, Formatter};
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
 use std::result;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
 use std::time::Duration;
 
 use arc_swap::ArcSwap;
@@ -9,9 +14,12 @@ use chrono::{DateTime, Utc};
 
 use crate::metrics::Metrics;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Error {
     pub msg: String,
+    // Set by sources that know the backend won't serve them again before a given time, such as
+    // when rate limited. The cache skips polling until then rather than failing repeatedly.
+    retry_at: Option<DateTime<Utc>>,
 }
 
 impl Display for Error {
@@ -23,9 +31,19 @@ impl Display for Error {
 impl Error {
     pub fn new(msg: &str) -> Error {
         Error {
-            msg: String::from(msg)
+            msg: String::from(msg),
+            retry_at: None,
         }
     }
+
+    pub fn with_retry_at(mut self, retry_at: DateTime<Utc>) -> Error {
+        self.retry_at = Some(retry_at);
+        self
+    }
+
+    pub fn retry_at(&self) -> Option<DateTime<Utc>> {
+        self.retry_at
+    }
 }
 
 impl<E: std::error::Error> From<E> for Error {
@@ -141,6 +159,118 @@ impl<Config: Send + Sync, Version> UpdateFn<Config, Version> for FieldUpdateFn<C
     }
 }
 
+// The keys that differ between two versions of a map or set. Sets never have changed keys.
+#[derive(Debug)]
+pub struct UpdateDiff<K> {
+    pub added: Vec<K>,
+    pub removed: Vec<K>,
+    pub changed: Vec<K>,
+}
+
+impl<K> UpdateDiff<K> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+// Datasets that can be compared against their previous version. Values are compared by pointer
+// before equality, so unchanged entries carried over between versions are cheap to skip.
+pub trait Diffable {
+    type Key;
+
+    fn diff(&self, previous: Option<&Self>) -> UpdateDiff<Self::Key>;
+
+    // The number of keys, which changes are measured against.
+    fn entries(&self) -> usize;
+}
+
+impl<K: Eq + Hash + Clone, V: PartialEq> Diffable for HashMap<K, Arc<V>> {
+    type Key = K;
+
+    fn diff(&self, previous: Option<&Self>) -> UpdateDiff<K> {
+        let empty = HashMap::new();
+        let previous = previous.unwrap_or(&empty);
+        UpdateDiff {
+            added: self.keys().filter(|k| !previous.contains_key(*k)).cloned().collect(),
+            removed: previous.keys().filter(|k| !self.contains_key(*k)).cloned().collect(),
+            changed: self.iter()
+                .filter(|(k, v)| previous.get(*k).is_some_and(|old| !Arc::ptr_eq(old, v) && old != *v))
+                .map(|(k, _)| k.clone())
+                .collect(),
+        }
+    }
+
+    fn entries(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K: Ord + Clone, V: PartialEq> Diffable for BTreeMap<K, Arc<V>> {
+    type Key = K;
+
+    fn diff(&self, previous: Option<&Self>) -> UpdateDiff<K> {
+        let empty = BTreeMap::new();
+        let previous = previous.unwrap_or(&empty);
+        UpdateDiff {
+            added: self.keys().filter(|k| !previous.contains_key(*k)).cloned().collect(),
+            removed: previous.keys().filter(|k| !self.contains_key(*k)).cloned().collect(),
+            changed: self.iter()
+                .filter(|(k, v)| previous.get(*k).is_some_and(|old| !Arc::ptr_eq(old, v) && old != *v))
+                .map(|(k, _)| k.clone())
+                .collect(),
+        }
+    }
+
+    fn entries(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<V: Eq + Hash + Clone> Diffable for HashSet<V> {
+    type Key = V;
+
+    fn diff(&self, previous: Option<&Self>) -> UpdateDiff<V> {
+        let empty = HashSet::new();
+        let previous = previous.unwrap_or(&empty);
+        UpdateDiff {
+            added: self.difference(previous).cloned().collect(),
+            removed: previous.difference(self).cloned().collect(),
+            changed: vec![],
+        }
+    }
+
+    fn entries(&self) -> usize {
+        self.len()
+    }
+}
+
+// An update callback for map and set collections that's handed what changed rather than the
+// whole dataset, along with the new version. Updates changing nothing aren't passed on.
+pub struct OnDiff<E, T: Diffable, F: Fn(&UpdateDiff<T::Key>, &Option<E>)> {
+    f: F,
+    _phantom_t: PhantomData<T>,
+    _phantom_e: PhantomData<E>,
+}
+
+impl<E, T: Diffable, F: Fn(&UpdateDiff<T::Key>, &Option<E>)> UpdateFn<T, E> for OnDiff<E, T, F> {
+    fn updated(&self, previous: &Option<(Option<E>, T)>, new_version: &Option<E>, new_dataset: &T) {
+        let diff = new_dataset.diff(previous.as_ref().map(|(_, t)| t));
+        if !diff.is_empty() {
+            (self.f)(&diff, new_version)
+        }
+    }
+}
+
+impl<E, T: Diffable, F: Fn(&UpdateDiff<T::Key>, &Option<E>)> OnDiff<E, T, F> {
+    pub fn with_fn(f: F) -> OnDiff<E, T, F> {
+        OnDiff {
+            f,
+            _phantom_t: PhantomData,
+            _phantom_e: PhantomData,
+        }
+    }
+}
+
 pub trait FailureFn<E> {
     fn failed(&self, err: &Error, last_version_and_ts: Option<(Option<E>, DateTime<Utc>)>);
 }
@@ -165,8 +295,332 @@ impl<E, F: Fn(&Error, Option<(Option<E>, DateTime<Utc>)>)> OnFailure<E, F> {
     }
 }
 
+// A random duration of up to max. Hashers are randomly seeded, which is plenty for spreading out
+// polling without pulling in an RNG.
+pub fn random_jitter(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_nanos(random % max.as_nanos().min(u64::MAX as u128) as u64)
+}
+
+// Runs user code, such as a processor or callback, turning a panic into an error so that it fails
+// one refresh instead of killing the refresh loop and freezing the cache.
+pub fn catch_panic<T, F: FnOnce() -> Result<T>>(what: &str, f: F) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(panic_error(what, payload)))
+}
+
+// Describes a caught panic, whose payload is almost always the message passed to panic!().
+pub fn panic_error(what: &str, payload: Box<dyn Any + Send>) -> Error {
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map(String::as_str).unwrap_or("unknown cause"),
+    };
+    Error::new(format!("{} panicked: {}", what, message).as_str())
+}
+
+// Hands an error to the failure callback. There's nowhere left to route a panic in the callback
+// itself, so that only reaches the metrics.
+pub fn report_failure<E, F: FailureFn<E>, M: Metrics<E>>(
+    err: &Error, last_version_and_ts: Option<(Option<E>, DateTime<Utc>)>, on_failure: Option<&F>, metrics: Option<&M>,
+) {
+    let Some(failure_callback) = on_failure else {
+        return;
+    };
+
+    let outcome = catch_panic("Failure callback", || {
+        failure_callback.failed(err, last_version_and_ts);
+        Ok(())
+    });
+    if let (Err(e), Some(m)) = (outcome, metrics) {
+        m.process_error(&e);
+    }
+}
+
+// A point in time view of how the cache's refreshes have been going, suitable for readiness and
+// liveness checks.
+#[derive(Debug, Clone)]
+pub struct Health<E> {
+    pub last_successful_check: Option<DateTime<Utc>>,
+    pub last_successful_update: Option<DateTime<Utc>>,
+    pub current_version: Option<E>,
+    pub consecutive_failures: u64,
+    pub last_error: Option<String>,
+    pub stale: bool,
+}
+
+// What happened on each poll, as delivered to subscribers.
+#[derive(Debug, Clone)]
+pub enum CacheEvent<E> {
+    Updated { version: Option<E> },
+    CheckNoChange,
+    Failed { error: Error },
+    FallbackUsed,
+    RolledBack { version: Option<E> },
+}
+
+// What a refresh requested through refresh_and_wait() came to, failures aside. NoChange covers
+// anything newer being held back by a pin, since what's served didn't change.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefreshOutcome<E> {
+    Updated { version: Option<E> },
+    NoChange,
+}
+
+// How the cache's refreshes have been going, kept up to date by its polling loop.
+pub struct RefreshStatus {
+    started: DateTime<Utc>,
+    last_successful_check: Option<DateTime<Utc>>,
+    last_successful_update: Option<DateTime<Utc>>,
+    consecutive_failures: u64,
+    last_error: Option<(Error, DateTime<Utc>)>,
+    stale: bool,
+}
+
+impl RefreshStatus {
+    pub fn new() -> RefreshStatus {
+        RefreshStatus {
+            started: Utc::now(),
+            last_successful_check: None,
+            last_successful_update: None,
+            consecutive_failures: 0,
+            last_error: None,
+            stale: false,
+        }
+    }
+
+    // Records a fetch that succeeded, whether or not it found anything new.
+    pub fn checked(&mut self) {
+        self.last_successful_check = Some(Utc::now());
+        self.consecutive_failures = 0;
+        self.stale = false;
+    }
+
+    // Records a fetch that swapped in a new dataset.
+    pub fn updated(&mut self) {
+        self.checked();
+        self.swapped();
+    }
+
+    // Records a new dataset being swapped in, which may have been fetched earlier.
+    pub fn swapped(&mut self) {
+        self.last_successful_update = Some(Utc::now());
+    }
+
+    pub fn failed(&mut self, err: &Error) {
+        self.consecutive_failures += 1;
+        self.last_error = Some((err.clone(), Utc::now()));
+    }
+
+    // True only when called for the first time after the last successful check, or startup if
+    // there was none, fell more than max_staleness behind, so alerts fire once per stale episode.
+    pub fn became_stale(&mut self, max_staleness: Duration) -> bool {
+        let since = self.last_successful_check.unwrap_or(self.started);
+        let max_staleness = chrono::Duration::from_std(max_staleness).unwrap_or(chrono::Duration::MAX);
+        if self.stale || Utc::now() - since <= max_staleness {
+            return false;
+        }
+
+        self.stale = true;
+        true
+    }
+
+    // Whether a real dataset, rather than a fallback, has ever been swapped in.
+    pub fn is_loaded(&self) -> bool {
+        self.last_successful_update.is_some()
+    }
+
+    pub fn last_successful_check(&self) -> Option<DateTime<Utc>> {
+        self.last_successful_check
+    }
+
+    pub fn consecutive_failures(&self) -> u64 {
+        self.consecutive_failures
+    }
+
+    // The most recent fetch or processing error and when it happened. It's kept after later
+    // successes, consecutive failures in health() says whether it's still relevant.
+    pub fn last_error(&self) -> Option<(Error, DateTime<Utc>)> {
+        self.last_error.clone()
+    }
+
+    // When the dataset being served was swapped in. A fallback is installed at startup, so that's
+    // its time until the first successful update.
+    pub fn last_updated(&self) -> DateTime<Utc> {
+        self.last_successful_update.unwrap_or(self.started)
+    }
+
+    pub fn health<E>(&self, current_version: Option<E>) -> Health<E> {
+        Health {
+            last_successful_check: self.last_successful_check,
+            last_successful_update: self.last_successful_update,
+            current_version,
+            consecutive_failures: self.consecutive_failures,
+            last_error: self.last_error.as_ref().map(|(e, _)| e.msg.clone()),
+            stale: self.stale,
+        }
+    }
+}
+
+impl Default for RefreshStatus {
+    fn default() -> Self {
+        RefreshStatus::new()
+    }
+}
+
 pub type Holder<E, T> = Arc<ArcSwap<Option<(Option<E>, T)>>>;
 
+// The dataset a cache is serving, readable without knowing its version type, for processors that
+// compare what they produce against it. A fallback doesn't count, there's nothing to compare
+// against until a real dataset has been swapped in.
+pub struct ServedDataset<T> {
+    #[allow(clippy::type_complexity)]
+    visit: Arc<dyn Fn(&mut dyn FnMut(&T)) + Send + Sync>,
+}
+
+impl<T: Send + Sync + 'static> ServedDataset<T> {
+    pub fn new<E: Send + Sync + 'static>(holder: Holder<E, T>, status: Arc<Mutex<RefreshStatus>>) -> ServedDataset<T> {
+        ServedDataset {
+            visit: Arc::new(move |f| {
+                if !status.lock().unwrap_or_else(PoisonError::into_inner).is_loaded() {
+                    return;
+                }
+                if let Some((_, dataset)) = holder.load().as_ref() {
+                    f(dataset)
+                }
+            }),
+        }
+    }
+}
+
+impl<T> ServedDataset<T> {
+    // Runs f over the dataset being served, if there is one.
+    pub fn with<R, F: FnOnce(&T) -> R>(&self, f: F) -> Option<R> {
+        let mut f = Some(f);
+        let mut result = None;
+        (self.visit)(&mut |dataset| result = f.take().map(|f| f(dataset)));
+        result
+    }
+}
+
+impl<T> Clone for ServedDataset<T> {
+    fn clone(&self) -> Self {
+        ServedDataset {
+            visit: self.visit.clone(),
+        }
+    }
+}
+
+// Whether the served dataset is pinned, and whether the latest one found should be swapped back in.
+#[derive(Default)]
+pub struct Pin {
+    pinned: bool,
+    released: bool,
+}
+
+impl Pin {
+    pub fn pin(&mut self) {
+        self.pinned = true;
+    }
+
+    // Lifts any pin, and has the next poll swap the latest dataset found back in if an older one
+    // is being served.
+    pub fn release(&mut self) {
+        self.pinned = false;
+        self.released = true;
+    }
+
+    pub fn is_released(&self) -> bool {
+        self.released
+    }
+
+    // True once after release(), for the poll that swaps the latest dataset back in.
+    pub fn take_released(&mut self) -> bool {
+        std::mem::take(&mut self.released)
+    }
+
+    // Stores the dataset unless pinned, in which case it's held back until released.
+    #[allow(clippy::type_complexity)]
+    pub fn swap_in<E, T>(&mut self, holder: &Holder<E, T>, dataset: Arc<Option<(Option<E>, T)>>) -> bool {
+        if self.pinned {
+            return false;
+        }
+
+        holder.store(dataset);
+        true
+    }
+}
+
+// The last few datasets swapped in, oldest first, for rolling back to. Entries are shared with
+// the holder, so retaining them costs only what outlives its time being served.
+#[allow(clippy::type_complexity)]
+pub struct History<E, T> {
+    capacity: usize,
+    entries: Mutex<VecDeque<Arc<Option<(Option<E>, T)>>>>,
+}
+
+impl<E, T> History<E, T> {
+    pub fn new(capacity: usize) -> History<E, T> {
+        History {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, entry: Arc<Option<(Option<E>, T)>>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        // Swapping the latest dataset back in after a pin or rollback doesn't make it newer.
+        if entries.back().is_some_and(|last| Arc::ptr_eq(last, &entry)) {
+            return;
+        }
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    // The newest retained dataset whose version matches.
+    #[allow(clippy::type_complexity)]
+    pub fn find<F: Fn(&E) -> bool>(&self, matches: F) -> Option<Arc<Option<(Option<E>, T)>>> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.iter().rev()
+            .find(|entry| matches!(entry.as_ref(), Some((Some(v), _)) if matches(v)))
+            .cloned()
+    }
+}
+
+#[derive(Clone, Default)]
+#[allow(clippy::type_complexity)]
+pub struct RefreshTrigger {
+    target: Arc<Mutex<Option<Box<dyn Fn() + Send + Sync>>>>,
+}
+
+impl RefreshTrigger {
+    pub fn new() -> RefreshTrigger {
+        RefreshTrigger::default()
+    }
+
+    pub fn bind<F: Fn() + Send + Sync + 'static>(&self, target: F) {
+        if let Ok(mut guard) = self.target.lock() {
+            *guard = Some(Box::new(target));
+        }
+    }
+
+    pub fn fire(&self) {
+        if let Ok(guard) = self.target.lock() {
+            if let Some(target) = guard.as_ref() {
+                target()
+            }
+        }
+    }
+}
+
 pub struct Absent {}
 
 impl<E, T> UpdateFn<T, E> for Absent {
@@ -215,4 +669,8 @@ impl<E> Metrics<E> for Absent {
     fn process_error(&self, _err: &Error) {
         panic!("Should never be called");
     }
+
+    fn stale(&self, _last_successful_check: &Option<DateTime<Utc>>) {
+        panic!("Should never be called");
+    }
 }
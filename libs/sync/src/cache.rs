@@ -1,25 +1,57 @@
+use std::any::Any;
 use std::borrow::Borrow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
 use std::marker::PhantomData;
-use std::sync::Arc;
+#[cfg(feature = "snapshot")]
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, TryLockError};
+use std::sync::mpsc::{self, channel, Receiver, Sender, TryRecvError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use std::time::{Duration, Instant, SystemTime};
 
 use arc_swap::ArcSwap;
-use chrono::DateTime;
-use mirror_cache_core::collections::{UpdatingMap, UpdatingObject, UpdatingSet};
+use chrono::{DateTime, Utc};
+use mirror_cache_core::collections::{Trie, UpdateHook, UpdatingBTreeMap, UpdatingMap, UpdatingObject, UpdatingSet, UpdatingTrie, UpdatingVec, Versioned};
+#[cfg(feature = "rand")]
+use mirror_cache_core::collections::{UpdatingWeightedSet, WeightedSet};
+#[cfg(feature = "regex")]
+use mirror_cache_core::collections::{RegexSet, UpdatingRegexSet};
 use mirror_cache_core::metrics::Metrics;
 use mirror_cache_core::processors::RawConfigProcessor;
-use mirror_cache_core::util::{Absent, Error, FailureFn, FallbackFn, Holder, Result, UpdateFn};
-use scheduled_thread_pool::ScheduledThreadPool;
+#[cfg(feature = "snapshot")]
+use mirror_cache_core::snapshot;
+use mirror_cache_core::util::{catch_panic, random_jitter, report_failure, Absent, CacheEvent, Error, FailureFn, FallbackFn, Health, History, Holder, Pin, RefreshOutcome, RefreshStatus, Result, ServedDataset, UpdateFn};
+#[cfg(feature = "snapshot")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "snapshot")]
+use serde::Serialize;
+use scheduled_thread_pool::{JobHandle, ScheduledThreadPool};
 
 use crate::sources::sources::ConfigSource;
 
+type Poll = Arc<Mutex<dyn FnMut() -> Polled + Send>>;
+
 pub struct MirrorCache<O> {
     cache: Arc<O>,
+    poll: Poll,
+    last_poll: Arc<Mutex<Polled>>,
+    // Cleared when a poll panics somewhere other than a processor or callback, until restart().
+    running: Arc<AtomicBool>,
+    status: Arc<Mutex<RefreshStatus>>,
+    // Paired with status, signalled whenever a real dataset is swapped in.
+    loaded: Arc<Condvar>,
+    // A Subscribers<E>, type erased since the cache isn't generic over the version.
+    events: Arc<dyn Any + Send + Sync>,
+    restore: Restore,
+    pins: Arc<Mutex<Pin>>,
+    // The Holder<E, T> being served, type erased since the cache isn't generic over either.
+    #[cfg_attr(not(feature = "snapshot"), allow(dead_code))]
+    served: Arc<dyn Any + Send + Sync>,
 
-    #[allow(dead_code)]
-    scheduler: ScheduledThreadPool,
+    scheduler: Arc<ScheduledThreadPool>,
+    poll_job: JobHandle,
 }
 
 impl<O: 'static> MirrorCache<O> {
@@ -35,22 +67,76 @@ impl<O: 'static> MirrorCache<O> {
         A: FallbackFn<T> + 'static,
         M: Metrics<E> + Send + Sync + 'static
     >(
-        name: Option<String>, source: C, processor: P, interval: Duration,
-        on_update: Option<U>, on_failure: Option<F>, mut metrics: Option<M>,
+        name: Option<String>, source: C, processor: P, interval: Duration, settings: Settings,
+        snapshots: Option<Snapshots<E, T>>, on_update: Option<U>, on_failure: Option<F>, metrics: Option<M>,
         fallback: Option<A>, constructor: fn(Holder<E, T>) -> O,
-    ) -> Result<MirrorCache<O>> {
+    ) -> Result<MirrorCache<O>> where O: UpdateHook<E, T> + Send + Sync {
+        // What's being served, and what was fetched most recently. They only differ while pinned
+        // or after a rollback.
         let holder: Holder<E, T> = Arc::new(ArcSwap::new(Arc::new(None)));
+        let latest: Holder<E, T> = Arc::new(ArcSwap::new(Arc::new(None)));
+        let trigger = source.refresh_trigger();
+        let events = Arc::new(Subscribers::<E>::new());
+        let metrics = metrics.map(Arc::new);
+        let status = Arc::new(Mutex::new(RefreshStatus::new()));
+        processor.bind_served(ServedDataset::new(holder.clone(), status.clone()));
         let update_fn =
-            MirrorCache::<O>::get_update_fn(holder.clone(), source, processor);
-        let initial_fetch = update_fn(metrics.as_mut());
+            Arc::new(MirrorCache::<O>::get_update_fn(latest.clone(), source, processor));
+
+        // Fetches can't be interrupted, so with a timeout the initial one runs on a thread of its
+        // own. If it times out it's left running, and the first poll after it lands uses it.
+        let mut initial = None;
+        // A snapshot saved by an earlier run is restored first, so the initial fetch only has to find
+        // anything newer and it's served if the source can't be reached. A missing or unreadable
+        // snapshot just means starting cold.
+        let restored = match snapshots.as_ref().map(|snapshots| (snapshots.load)()) {
+            Some(Ok(dataset)) => {
+                let dataset = Arc::new(Some(dataset));
+                latest.store(dataset.clone());
+                holder.store(dataset);
+                status.lock().unwrap_or_else(PoisonError::into_inner).swapped();
+                true
+            }
+            _ => false,
+        };
+
+        let initial_fetch = match settings.initial_fetch_timeout {
+            _ if settings.lazy_start => Ok(Arc::new(None)),
+            None => update_fn(metrics.as_deref()),
+            Some(timeout) => {
+                let (tx, rx) = mpsc::channel();
+                let (update_fn, metrics) = (update_fn.clone(), metrics.clone());
+                thread::spawn(move || {
+                    let _ = tx.send(update_fn(metrics.as_deref()));
+                });
+
+                rx.recv_timeout(timeout).unwrap_or_else(|_| {
+                    initial = Some(rx);
+                    Err(Error::new(format!("Initial fetch timed out after {:?}", timeout).as_str()))
+                })
+            }
+        };
+        holder.store(latest.load_full());
+        {
+            let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+            match initial_fetch.as_ref() {
+                _ if settings.lazy_start => {}
+                Ok(init) if init.is_some() => status.updated(),
+                Ok(_) => status.checked(),
+                Err(e) => status.failed(e),
+            }
+        }
 
         match initial_fetch.as_ref() {
+            Err(e) if restored => settings.check_escalation(&status, e, metrics.as_deref()),
             Err(e) => {
                 match fallback {
                     Some(fallback_fun) => {
                         let fallback_state = Arc::new(Some((None, fallback_fun.get_fallback())));
                         holder.as_ref().store(fallback_state);
-                        if let Some(m) = metrics.as_mut() {
+                        events.publish(CacheEvent::FallbackUsed);
+                        settings.check_escalation(&status, e, metrics.as_deref());
+                        if let Some(m) = metrics.as_deref() {
                             m.fallback_invoked();
                         }
                     }
@@ -59,12 +145,21 @@ impl<O: 'static> MirrorCache<O> {
             }
             Ok(init) => {
                 match init.as_ref() {
+                    None if restored => {
+                        // The source still serves the restored version, so the callback won't
+                        // otherwise hear of it until something changes.
+                        let dataset = holder.load_full();
+                        if let (Some((v, s)), Some(update_callback)) = (dataset.as_ref(), on_update.borrow()) {
+                            update_callback.updated(&None, v, s);
+                        }
+                    }
                     None => {
                         match fallback {
                             Some(fallback_fun) => {
                                 let fallback_state = Arc::new(Some((None, fallback_fun.get_fallback())));
                                 holder.as_ref().store(fallback_state);
-                                if let Some(m) = metrics.as_mut() {
+                                events.publish(CacheEvent::FallbackUsed);
+                                if let Some(m) = metrics.as_deref() {
                                     m.fallback_invoked();
                                 }
                             }
@@ -72,6 +167,10 @@ impl<O: 'static> MirrorCache<O> {
                         }
                     }
                     Some((v, s)) => {
+                        events.publish(CacheEvent::Updated { version: v.clone() });
+                        if let Some(snapshots) = &snapshots {
+                            let _ = (snapshots.save)(v, s);
+                        }
                         if let Some(update_callback) = on_update.borrow() {
                             update_callback.updated(&None, v, s);
                         }
@@ -80,38 +179,225 @@ impl<O: 'static> MirrorCache<O> {
             }
         };
 
+        let history = Arc::new(History::new(settings.history));
+        history.record(holder.load_full());
+
         let mut last_success = DateTime::from(SystemTime::now());
+        let mut retry_at: Option<DateTime<Utc>> = None;
         let cache = Arc::new(constructor(holder.clone()));
-        let scheduler = match name {
+        let served: Arc<dyn Any + Send + Sync> = Arc::new(holder.clone());
+        let scheduler = Arc::new(match name {
             Some(n) => ScheduledThreadPool::builder()
                 .num_threads(1)
                 .thread_name_pattern(n.as_str())
                 .build(),
             None => ScheduledThreadPool::new(1),
+        });
+
+        let pins = Arc::new(Mutex::new(Pin::default()));
+        let restore: Restore = {
+            let (holder, collection, history, events, pins, status) =
+                (holder.clone(), cache.clone(), history.clone(), events.clone(), pins.clone(), status.clone());
+            Box::new(move |matches, pin| {
+                let Some(matches) = matches.downcast_ref::<VersionMatcher<E>>() else {
+                    return Err(Error::new("Version type didn't match the collection's version type"));
+                };
+
+                let current = holder.load_full();
+                let target = match current.as_ref() {
+                    Some((Some(v), _)) if matches(v) => current.clone(),
+                    _ => history.find(|v| matches(v))
+                        .ok_or_else(|| Error::new("Version isn't in the retained history"))?,
+                };
+
+                let previous = {
+                    let mut pins = pins.lock().unwrap_or_else(PoisonError::into_inner);
+                    if pin {
+                        pins.pin();
+                    }
+                    if Arc::ptr_eq(&target, &current) {
+                        return Ok(());
+                    }
+                    holder.swap(target.clone())
+                };
+
+                status.lock().unwrap_or_else(PoisonError::into_inner).swapped();
+                if let Some(current) = target.as_ref() {
+                    collection.swapped(&previous, current);
+                    events.publish(CacheEvent::RolledBack { version: current.0.clone() });
+                }
+                Ok(())
+            })
         };
 
-        scheduler.execute_at_fixed_rate(interval, interval, move || {
+        // Lazily started caches have fetched nothing yet, so poll straight away.
+        let first_poll = match settings.lazy_start {
+            true => random_jitter(settings.fetch_jitter),
+            false => interval + random_jitter(settings.fetch_jitter),
+        };
+        let poll_status = status.clone();
+        let loaded = Arc::new(Condvar::new());
+        let poll_loaded = loaded.clone();
+        let poll_events = events.clone();
+        let poll_pins = pins.clone();
+        let collection = cache.clone();
+        let last_poll = Arc::new(Mutex::new(Polled::NoChange));
+        let polled = last_poll.clone();
+        let running = Arc::new(AtomicBool::new(true));
+        let poll_running = running.clone();
+        let on_failure = on_failure.map(Arc::new);
+        let (death_holder, death_status, death_events, death_failure, death_metrics) =
+            (holder.clone(), status.clone(), events.clone(), on_failure.clone(), metrics.clone());
+        let mut poll_once = move || {
             let previous = holder.load_full().clone();
 
-            match update_fn(metrics.as_mut()) {
-                Ok(a) => if let Some((v, t)) = a.as_ref() {
-                    last_success = DateTime::from(SystemTime::now());
-                    if let Some(update_callback) = &on_update {
-                        update_callback.updated(&previous, v, t)
+            // After a pin is released the first poll swaps the latest dataset found back in, if an
+            // older one is being served.
+            let mut fetched = None;
+            if poll_pins.lock().unwrap_or_else(PoisonError::into_inner).take_released() {
+                fetched = Some(latest.load_full()).filter(|latest| !Arc::ptr_eq(latest, &previous));
+            }
+
+            // Polls wait on a timed out initial fetch rather than starting another.
+            let outcome = match initial.take() {
+                Some(rx) => match rx.try_recv() {
+                    Ok(outcome) => Some(outcome),
+                    Err(TryRecvError::Empty) => {
+                        initial = Some(rx);
+                        None
                     }
+                    Err(TryRecvError::Disconnected) => None,
                 },
-                Err(e) => {
-                    if let Some(failure_callback) = &on_failure {
-                        let last = previous.as_ref().as_ref().map(|(v, _)| (v.clone(), last_success));
-                        failure_callback.failed(&e, last)
+                None if retry_at.is_none_or(|at| Utc::now() >= at) => Some(update_fn(metrics.as_deref())),
+                None => None,
+            };
+
+            let failure = match outcome {
+                Some(Ok(a)) => {
+                    poll_status.lock().unwrap_or_else(PoisonError::into_inner).checked();
+                    if a.is_some() {
+                        fetched = Some(a);
+                    } else {
+                        poll_events.publish(CacheEvent::CheckNoChange);
                     }
+                    None
+                }
+                Some(Err(e)) => {
+                    poll_status.lock().unwrap_or_else(PoisonError::into_inner).failed(&e);
+                    poll_events.publish(CacheEvent::Failed { error: e.clone() });
+                    retry_at = e.retry_at();
+                    let last = previous.as_ref().as_ref().map(|(v, _)| (v.clone(), last_success));
+                    report_failure(&e, last, on_failure.as_deref(), metrics.as_deref());
+                    settings.check_escalation(&poll_status, &e, metrics.as_deref());
+                    Some(e)
                 }
+                // Callers waiting on the outcome are told why nothing was fetched.
+                None => Some(match (&initial, retry_at) {
+                    (Some(_), _) => Error::new("The initial fetch is still in flight"),
+                    (None, Some(at)) => Error::new(format!("Polling is held off until {}", at).as_str()).with_retry_at(at),
+                    (None, None) => Error::new("The initial fetch ended without a result"),
+                }),
+            };
+
+            let swapped_in = fetched
+                .filter(|a| a.is_some())
+                .filter(|a| poll_pins.lock().unwrap_or_else(PoisonError::into_inner).swap_in(&holder, a.clone()));
+
+            let was_updated = match swapped_in.as_ref().and_then(|a| a.as_ref().as_ref().map(|current| (a, current))) {
+                Some((dataset, current)) => {
+                    poll_status.lock().unwrap_or_else(PoisonError::into_inner).swapped();
+                    poll_loaded.notify_all();
+                    history.record(dataset.clone());
+                    // Failing to save a snapshot shouldn't fail the update, the next one will try
+                    // again.
+                    if let Some(snapshots) = &snapshots {
+                        let _ = (snapshots.save)(&current.0, &current.1);
+                    }
+                    poll_events.publish(CacheEvent::Updated { version: current.0.clone() });
+                    last_success = DateTime::from(SystemTime::now());
+                    collection.swapped(&previous, current);
+                    if let Some(update_callback) = &on_update {
+                        let outcome = catch_panic("Update callback", || {
+                            update_callback.updated(&previous, &current.0, &current.1);
+                            Ok(())
+                        });
+                        if let Err(e) = outcome {
+                            if let Some(m) = metrics.as_deref() {
+                                m.process_error(&e);
+                            }
+                            report_failure(&e, Some((current.0.clone(), last_success)), on_failure.as_deref(), metrics.as_deref());
+                        }
+                    }
+                    true
+                }
+                None => false,
+            };
+
+            settings.check_staleness(&poll_status, metrics.as_deref());
+            match (was_updated, failure) {
+                (true, _) => Polled::Updated,
+                (false, Some(e)) => Polled::Failed(e),
+                (false, None) => Polled::NoChange,
+            }
+        };
+
+        // A panic anywhere else in a poll, such as in the source, leaves its state unknown, so the
+        // loop stops polling and reports it until restart() is called.
+        let poll: Poll = Arc::new(Mutex::new(move || {
+            if !poll_running.load(Ordering::Acquire) {
+                return Polled::Failed(Error::new("The refresh loop isn't running"));
+            }
+
+            let outcome = catch_panic("Refresh loop", || Ok(poll_once())).unwrap_or_else(|e| {
+                poll_running.store(false, Ordering::Release);
+                let last_updated = {
+                    let mut status = death_status.lock().unwrap_or_else(PoisonError::into_inner);
+                    status.failed(&e);
+                    status.last_updated()
+                };
+                death_events.publish(CacheEvent::Failed { error: e.clone() });
+                let last = death_holder.load().as_ref().as_ref().map(|(v, _)| (v.clone(), last_updated));
+                report_failure(&e, last, death_failure.as_deref(), death_metrics.as_deref());
+                Polled::Failed(e)
+            });
+            *polled.lock().unwrap_or_else(PoisonError::into_inner) = outcome.clone();
+            outcome
+        }));
+
+        let scheduled = poll.clone();
+        let poll_job = scheduler.execute_at_fixed_rate(first_poll, interval, move || {
+            if let Ok(mut poll) = scheduled.lock() {
+                (*poll)();
             }
         });
 
+        if let Some(t) = trigger {
+            let weak_scheduler = Arc::downgrade(&scheduler);
+            let weak_poll = Arc::downgrade(&poll);
+            t.bind(move || {
+                if let (Some(scheduler), Some(poll)) = (weak_scheduler.upgrade(), weak_poll.upgrade()) {
+                    scheduler.execute(move || {
+                        if let Ok(mut poll) = poll.lock() {
+                            (*poll)();
+                        }
+                    });
+                }
+            });
+        }
+
         Ok(MirrorCache {
             cache,
+            poll,
+            last_poll,
+            running,
+            status,
+            loaded,
+            events,
+            restore,
+            pins,
+            served,
             scheduler,
+            poll_job,
         })
     }
 
@@ -119,6 +405,169 @@ impl<O: 'static> MirrorCache<O> {
         self.cache.clone()
     }
 
+    pub fn health<E>(&self) -> Health<E> where O: Versioned<E> {
+        let status = self.status.lock().unwrap_or_else(PoisonError::into_inner);
+        status.health(self.cache.current_version())
+    }
+
+    // Why updates last failed, without needing a failure callback to record it.
+    pub fn last_error(&self) -> Option<(Error, DateTime<Utc>)> {
+        self.status.lock().unwrap_or_else(PoisonError::into_inner).last_error()
+    }
+
+    // Each subscriber gets every event from then on, until its receiver is dropped. The initial
+    // fetch happens during build(), before anyone can subscribe.
+    pub fn subscribe<E: Clone + Send + 'static>(&self) -> Receiver<CacheEvent<E>> where O: Versioned<E> {
+        let (sender, receiver) = channel();
+        match self.events.downcast_ref::<Subscribers<E>>() {
+            Some(subscribers) => subscribers.add(sender),
+            None => panic!("Event type didn't match the collection's version type"),
+        }
+
+        receiver
+    }
+
+    // Atomically swaps the newest retained dataset with the given version back in, for example to
+    // recover quickly from a bad push. Only datasets kept by with_history() can be restored.
+    // Polling carries on, and the next new version found replaces it unless pinned, or
+    // roll_forward() swaps the latest one found back in without waiting for a new version.
+    pub fn rollback_to<E: PartialEq + Clone + 'static>(&self, version: &E) -> Result<()> where O: Versioned<E> {
+        let wanted = version.clone();
+        let matches: VersionMatcher<E> = Box::new(move |v| *v == wanted);
+        (self.restore)(&matches, false)
+    }
+
+    // Freezes the served dataset at the given version, which must be the one being served or one
+    // retained by with_history(). Polling carries on in the background, and anything newer it
+    // finds is held back until unpin().
+    pub fn pin<E: PartialEq + Clone + 'static>(&self, version: &E) -> Result<()> where O: Versioned<E> {
+        let wanted = version.clone();
+        let matches: VersionMatcher<E> = Box::new(move |v| *v == wanted);
+        (self.restore)(&matches, true)
+    }
+
+    // Lifts a pin, swapping the latest dataset found back in if it isn't the one being served, and
+    // polling immediately. Returns whether a dataset was swapped in.
+    pub fn unpin(&self) -> bool {
+        self.release()
+    }
+
+    // Undoes rollback_to(), swapping the latest dataset found back in if it isn't the one being
+    // served, and polling immediately. Returns whether a dataset was swapped in.
+    pub fn roll_forward(&self) -> bool {
+        self.release()
+    }
+
+    // Blocks until a real dataset rather than a fallback has been loaded, for work that needs live
+    // config after a lazy start or a failed initial fetch. Returns whether one was in time.
+    pub fn wait_ready(&self, timeout: Duration) -> bool {
+        let status = self.status.lock().unwrap_or_else(PoisonError::into_inner);
+        let (status, _) = self.loaded.wait_timeout_while(status, timeout, |status| !status.is_loaded())
+            .unwrap_or_else(PoisonError::into_inner);
+        status.is_loaded()
+    }
+
+    pub fn last_updated(&self) -> DateTime<Utc> {
+        self.status.lock().unwrap_or_else(PoisonError::into_inner).last_updated()
+    }
+
+    // How long the dataset being served has been in place, for deciding whether it's too old to
+    // act on.
+    pub fn data_age(&self) -> Duration {
+        (Utc::now() - self.last_updated()).to_std().unwrap_or(Duration::ZERO)
+    }
+
+    // Serializes the dataset being served and its version, in the format used by
+    // with_snapshot_persistence(), so tooling can capture exactly what an instance was serving.
+    #[cfg(feature = "snapshot")]
+    pub fn export_snapshot<E, T>(&self) -> Result<Vec<u8>>
+        where O: UpdateHook<E, T>, E: Serialize + 'static, T: Serialize + 'static {
+        let served = self.served_holder::<E, T>()?.load_full();
+        match served.as_ref() {
+            Some((version, dataset)) => snapshot::to_bytes(version, dataset),
+            None => Err(Error::new("Nothing is being served")),
+        }
+    }
+
+    // Writes the export to a file, which with_snapshot_persistence() on another instance can
+    // start from.
+    #[cfg(feature = "snapshot")]
+    pub fn export_snapshot_to<E, T, PP: AsRef<Path>>(&self, path: PP) -> Result<()>
+        where O: UpdateHook<E, T>, E: Serialize + 'static, T: Serialize + 'static {
+        let served = self.served_holder::<E, T>()?.load_full();
+        match served.as_ref() {
+            Some((version, dataset)) => snapshot::save(path.as_ref(), version, dataset),
+            None => Err(Error::new("Nothing is being served")),
+        }
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn served_holder<E: 'static, T: 'static>(&self) -> Result<&Holder<E, T>> {
+        self.served.downcast_ref::<Holder<E, T>>()
+            .ok_or_else(|| Error::new("Dataset type didn't match the collection's dataset type"))
+    }
+
+    // False once the refresh loop has died, after which the dataset being served won't change
+    // until restart().
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    // Brings a dead refresh loop back, polling straight away from the dataset currently held.
+    // Returns false if it was already running.
+    pub fn restart(&self) -> bool {
+        if self.running.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+
+        let poll = self.poll.clone();
+        self.scheduler.execute(move || {
+            if let Ok(mut poll) = poll.lock() {
+                (*poll)();
+            }
+        });
+        true
+    }
+
+    // Fetches and processes immediately on the calling thread rather than waiting out the
+    // interval, returning whether a new dataset was swapped in. If a poll is already in flight,
+    // waits for it and returns its outcome instead of fetching again.
+    pub fn refresh_now(&self) -> bool {
+        matches!(self.poll_now(), Polled::Updated)
+    }
+
+    // Like refresh_now(), but says which version was swapped in or why the refresh failed, for
+    // admin endpoints and deployment hooks confirming a push took effect.
+    pub fn refresh_and_wait<E>(&self) -> Result<RefreshOutcome<E>> where O: Versioned<E> {
+        match self.poll_now() {
+            Polled::Updated => Ok(RefreshOutcome::Updated { version: self.cache.current_version() }),
+            Polled::NoChange => Ok(RefreshOutcome::NoChange),
+            Polled::Failed(e) => Err(e),
+        }
+    }
+
+    fn release(&self) -> bool {
+        self.pins.lock().unwrap_or_else(PoisonError::into_inner).release();
+        let updated = self.refresh_now();
+        // A poll already in flight may have checked before the release, the next one acts on it.
+        if self.pins.lock().unwrap_or_else(PoisonError::into_inner).is_released() {
+            return self.refresh_now() || updated;
+        }
+        updated
+    }
+
+    fn poll_now(&self) -> Polled {
+        let not_running = || Polled::Failed(Error::new("The refresh loop isn't running"));
+        match self.poll.try_lock() {
+            Ok(mut poll) => (*poll)(),
+            Err(TryLockError::Poisoned(_)) => not_running(),
+            Err(TryLockError::WouldBlock) => match self.poll.lock() {
+                Ok(_) => self.last_poll.lock().unwrap_or_else(PoisonError::into_inner).clone(),
+                Err(_) => not_running(),
+            },
+        }
+    }
+
     fn get_update_fn<
         S,
         T,
@@ -128,7 +577,7 @@ impl<O: 'static> MirrorCache<O> {
         M: Metrics<E> + Send + Sync + 'static,
     >(
         holder: Holder<E, T>, source: C, processor: P,
-    ) -> impl Fn(Option<&mut M>) -> Result<Arc<Option<(Option<E>, T)>>> {
+    ) -> impl Fn(Option<&M>) -> Result<Arc<Option<(Option<E>, T)>>> {
         move |metrics| {
             let version =
                 holder.load_full().as_ref().as_ref().map(|(v, _)| v.clone());
@@ -143,7 +592,7 @@ impl<O: 'static> MirrorCache<O> {
             let process_start = Instant::now();
             let update = match raw_update {
                 Ok(None) => None,
-                Ok(Some((v, s))) => Some((v, processor.process(s))),
+                Ok(Some((v, s))) => Some((v, catch_panic("Processor", || processor.process(s)))),
                 Err(e) => {
                     if let Some(m) = metrics {
                         m.fetch_error(&e)
@@ -197,6 +646,18 @@ impl<O: 'static> MirrorCache<O> {
         builder(UpdatingMap::new)
     }
 
+    pub fn btree_map_builder<
+        K: Ord + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, BTreeMap<K, Arc<V>>> + Send + Sync + 'static,
+        D: Into<Duration>,
+    >() -> Builder<UpdatingBTreeMap<E, K, V>, BTreeMap<K, Arc<V>>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingBTreeMap::new)
+    }
+
     pub fn set_builder<
         V: Eq + Hash + Send + Sync + 'static,
         S: 'static,
@@ -208,6 +669,17 @@ impl<O: 'static> MirrorCache<O> {
         builder(UpdatingSet::new)
     }
 
+    pub fn vec_builder<
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, Vec<Arc<V>>> + Send + Sync + 'static,
+        D: Into<Duration>,
+    >() -> Builder<UpdatingVec<E, V>, Vec<Arc<V>>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingVec::new)
+    }
+
     pub fn object_builder<
         V: Send + Sync + 'static,
         S: 'static,
@@ -218,6 +690,168 @@ impl<O: 'static> MirrorCache<O> {
     >() -> Builder<UpdatingObject<E, V>, Arc<V>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
         builder(UpdatingObject::new)
     }
+
+    pub fn trie_builder<
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, Trie<V>> + Send + Sync + 'static,
+        D: Into<Duration>
+    >() -> Builder<UpdatingTrie<E, V>, Trie<V>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingTrie::new)
+    }
+
+    #[cfg(feature = "regex")]
+    pub fn regex_set_builder<
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, RegexSet> + Send + Sync + 'static,
+        D: Into<Duration>
+    >() -> Builder<UpdatingRegexSet<E>, RegexSet, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingRegexSet::new)
+    }
+
+    #[cfg(feature = "rand")]
+    pub fn weighted_set_builder<
+        V: Send + Sync + 'static,
+        S: 'static,
+        E: Sync + Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        P: RawConfigProcessor<S, WeightedSet<V>> + Send + Sync + 'static,
+        D: Into<Duration>
+    >() -> Builder<UpdatingWeightedSet<E, V>, WeightedSet<V>, S, E, C, P, D, Absent, Absent, Absent, Absent> {
+        builder(UpdatingWeightedSet::new)
+    }
+}
+
+// Stops polling once the cache is dropped. Collections handed out by cache() keep serving the last
+// dataset.
+impl<O> Drop for MirrorCache<O> {
+    fn drop(&mut self) {
+        self.poll_job.cancel();
+    }
+}
+
+struct Subscribers<E> {
+    senders: Mutex<Vec<Sender<CacheEvent<E>>>>,
+}
+
+impl<E: Clone> Subscribers<E> {
+    fn new() -> Subscribers<E> {
+        Subscribers {
+            senders: Mutex::new(vec![]),
+        }
+    }
+
+    fn add(&self, sender: Sender<CacheEvent<E>>) {
+        self.senders.lock().unwrap_or_else(PoisonError::into_inner).push(sender);
+    }
+
+    // Subscribers whose receiver has been dropped are forgotten on the next event.
+    fn publish(&self, event: CacheEvent<E>) {
+        let mut senders = self.senders.lock().unwrap_or_else(PoisonError::into_inner);
+        senders.retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+// What a poll came to. The version swapped in is read from the collection, so this needn't be
+// generic over it.
+#[derive(Clone)]
+enum Polled {
+    Updated,
+    NoChange,
+    Failed(Error),
+}
+
+// Matches a version being restored, boxed so it can be passed through a Restore.
+type VersionMatcher<E> = Box<dyn Fn(&E) -> bool>;
+
+// Swaps the dataset being served or one from history back in, optionally pinning it. Takes a
+// VersionMatcher<E>, type erased since the cache isn't generic over the version.
+type Restore = Box<dyn Fn(&dyn Any, bool) -> Result<()> + Send + Sync>;
+
+type StaleFn = Box<dyn Fn(Option<DateTime<Utc>>) + Send + Sync>;
+
+type EscalationFn = Box<dyn Fn(&Error, Option<DateTime<Utc>>) + Send + Sync>;
+
+type SaveFn<E, T> = Box<dyn Fn(&Option<E>, &T) -> Result<()> + Send + Sync>;
+
+type LoadFn<E, T> = Box<dyn Fn() -> Result<(Option<E>, T)> + Send + Sync>;
+
+// Saves and restores datasets for with_snapshot_persistence(), boxed so that only caches using it
+// need serializable datasets.
+#[cfg_attr(not(feature = "snapshot"), allow(dead_code))]
+struct Snapshots<E, T> {
+    save: SaveFn<E, T>,
+    load: LoadFn<E, T>,
+}
+
+// Optional tuning with sensible defaults, carried through the builder and into polling as one.
+#[derive(Default)]
+struct Settings {
+    fetch_jitter: Duration,
+    max_staleness: Option<Duration>,
+    on_stale: Option<StaleFn>,
+    // How many refreshes in a row must fail before escalating, and what to call when they have.
+    escalation: Option<(u64, EscalationFn)>,
+    history: usize,
+    initial_fetch_timeout: Option<Duration>,
+    lazy_start: bool,
+}
+
+impl Settings {
+    // Checks after a failure whether the run of failures has reached the escalation threshold.
+    // Runs are ended by any successful check, so this fires once per run.
+    fn check_escalation<E, M: Metrics<E>>(&self, status: &Mutex<RefreshStatus>, err: &Error, metrics: Option<&M>) {
+        let Some((threshold, on_escalation)) = &self.escalation else {
+            return;
+        };
+
+        let (failures, last_successful_check) = {
+            let status = status.lock().unwrap_or_else(PoisonError::into_inner);
+            (status.consecutive_failures(), status.last_successful_check())
+        };
+        if failures != *threshold {
+            return;
+        }
+
+        let outcome = catch_panic("Escalation callback", || {
+            on_escalation(err, last_successful_check);
+            Ok(())
+        });
+        if let (Err(e), Some(m)) = (outcome, metrics) {
+            m.process_error(&e);
+        }
+    }
+
+    // Checks for staleness after a poll, notifying once per stale episode.
+    fn check_staleness<E, M: Metrics<E>>(&self, status: &Mutex<RefreshStatus>, metrics: Option<&M>) {
+        let Some(max_staleness) = self.max_staleness else {
+            return;
+        };
+
+        let (became_stale, last_successful_check) = {
+            let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+            (status.became_stale(max_staleness), status.last_successful_check())
+        };
+
+        if became_stale {
+            if let Some(m) = metrics {
+                m.stale(&last_successful_check);
+            }
+            if let Some(on_stale) = &self.on_stale {
+                let outcome = catch_panic("Stale callback", || {
+                    on_stale(last_successful_check);
+                    Ok(())
+                });
+                if let (Err(e), Some(m)) = (outcome, metrics) {
+                    m.process_error(&e);
+                }
+            }
+        }
+    }
 }
 
 pub struct Builder<
@@ -236,6 +870,8 @@ pub struct Builder<
     constructor: fn(Holder<E, T>) -> O,
     name: Option<String>,
     fetch_interval: Option<D>,
+    settings: Settings,
+    snapshots: Option<Snapshots<E, T>>,
     config_source: Option<C>,
     config_processor: Option<P>,
     failure_callback: Option<F>,
@@ -246,7 +882,7 @@ pub struct Builder<
 }
 
 impl<
-    O: Send + Sync + 'static,
+    O: UpdateHook<E, T> + Send + Sync + 'static,
     T: Send + Sync + 'static,
     S: 'static,
     E: Send + Sync + Clone + 'static,
@@ -278,11 +914,65 @@ impl<
         self
     }
 
+    // Offsets polling by a random delay of up to the given duration, so a fleet of instances
+    // started together doesn't poll the upstream in lockstep.
+    pub fn with_fetch_jitter(mut self, max_jitter: Duration) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.fetch_jitter = max_jitter;
+        self
+    }
+
+    // Fires the stale callback and the stale() metric once the cache has gone longer than this
+    // without a successful check, whether from failed fetches or processing. Fires again only
+    // after a successful check brings it back.
+    pub fn with_max_staleness(mut self, max_staleness: Duration) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.max_staleness = Some(max_staleness);
+        self
+    }
+
+    // Stops build() waiting on a hanging upstream. A timed out initial fetch is treated like a
+    // failed one, using the fallback if there is one and failing otherwise.
+    pub fn with_initial_fetch_timeout(mut self, timeout: Duration) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.initial_fetch_timeout = Some(timeout);
+        self
+    }
+
+    // Has build() return straight away serving the fallback, which is required, and leaves the
+    // first fetch to the background. For services that must be up before touching the network.
+    pub fn with_lazy_start(mut self) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.lazy_start = true;
+        self
+    }
+
+    // Retains the last n datasets swapped in, including the one being served, so rollback_to()
+    // can bring one back.
+    pub fn with_history(mut self, n: usize) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        self.settings.history = n;
+        self
+    }
+
+    // Called with the time of the last successful check, if there ever was one.
+    pub fn with_stale_callback<SF>(mut self, on_stale: SF) -> Builder<O, T, S, E, C, P, D, U, F, A, M>
+        where SF: Fn(Option<DateTime<Utc>>) + Send + Sync + 'static {
+        self.settings.on_stale = Some(Box::new(on_stale));
+        self
+    }
+
+    // Called once n refreshes in a row have failed, with the latest error and the time of the
+    // last successful check, if there ever was one. It isn't called again until a refresh has
+    // succeeded, so it can tell a blip apart from an upstream that's down.
+    pub fn with_failure_escalation<EF>(mut self, n: u64, on_escalation: EF) -> Builder<O, T, S, E, C, P, D, U, F, A, M>
+        where EF: Fn(&Error, Option<DateTime<Utc>>) + Send + Sync + 'static {
+        self.settings.escalation = Some((n, Box::new(on_escalation)));
+        self
+    }
+
     pub fn with_update_callback<UU: UpdateFn<T, E>>(self, callback: UU) -> Builder<O, T, S, E, C, P, D, UU, F, A, M> {
         Builder {
             constructor: self.constructor,
             name: self.name,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: self.failure_callback,
@@ -298,6 +988,8 @@ impl<
             constructor: self.constructor,
             name: self.name,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: Some(callback),
@@ -313,6 +1005,8 @@ impl<
             constructor: self.constructor,
             name: self.name,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: self.failure_callback,
@@ -328,6 +1022,8 @@ impl<
             constructor: self.constructor,
             name: self.name,
             fetch_interval: self.fetch_interval,
+            settings: self.settings,
+            snapshots: self.snapshots,
             config_source: self.config_source,
             config_processor: self.config_processor,
             failure_callback: self.failure_callback,
@@ -351,11 +1047,17 @@ impl<
             return Err(Error::new("No  fetch interval specified"));
         }
 
+        if self.settings.lazy_start && self.fallback.is_none() {
+            return Err(Error::new("Lazy start requires a fallback"));
+        }
+
         MirrorCache::construct_and_start(
             self.name,
             self.config_source.unwrap(),
             self.config_processor.unwrap(),
             self.fetch_interval.unwrap().into(),
+            self.settings,
+            self.snapshots,
             self.update_callback,
             self.failure_callback,
             self.metrics,
@@ -365,6 +1067,23 @@ impl<
     }
 }
 
+#[cfg(feature = "snapshot")]
+impl<O, T, S, E, C, P, D, U, F, A, M> Builder<O, T, S, E, C, P, D, U, F, A, M>
+    where T: Serialize + DeserializeOwned + 'static, E: Serialize + DeserializeOwned + 'static {
+    // Saves each dataset swapped in, with its version, to the given file, and restores it at
+    // startup ahead of the initial fetch. A cold start then serves recent real config rather than
+    // the fallback while the source is unreachable, and only fetches if there's anything newer.
+    pub fn with_snapshot_persistence<PP: Into<PathBuf>>(mut self, path: PP) -> Builder<O, T, S, E, C, P, D, U, F, A, M> {
+        let path = path.into();
+        let load_path = path.clone();
+        self.snapshots = Some(Snapshots {
+            save: Box::new(move |version, dataset| snapshot::save(&path, version, dataset)),
+            load: Box::new(move || snapshot::load(&load_path)),
+        });
+        self
+    }
+}
+
 fn builder<
     O: Sync + Send + 'static,
     T: Send + Sync + 'static,
@@ -378,6 +1097,8 @@ fn builder<
         constructor,
         name: None,
         fetch_interval: None,
+        settings: Settings::default(),
+        snapshots: None,
         config_source: None,
         config_processor: None,
         failure_callback: None,
@@ -387,3 +1108,104 @@ fn builder<
         phantom: PhantomData::default(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use mirror_cache_core::collections::UpdatingObject;
+    use mirror_cache_core::processors::RawConfigProcessor;
+    use mirror_cache_core::util::Result;
+
+    use crate::cache::MirrorCache;
+    use crate::sources::sources::InMemoryConfigSource;
+
+    struct Identity;
+
+    impl RawConfigProcessor<&'static str, Arc<String>> for Identity {
+        fn process(&self, raw: &'static str) -> Result<Arc<String>> {
+            Ok(Arc::new(String::from(raw)))
+        }
+    }
+
+    // Serves "one" after the build, then "two" after a refresh, with "three" still to come.
+    fn cache() -> MirrorCache<UpdatingObject<u32, String>> {
+        let source = InMemoryConfigSource::new(vec![
+            Ok((Some(1), "one")),
+            Ok((Some(2), "two")),
+            Ok((Some(3), "three")),
+        ]);
+
+        let cache = MirrorCache::<UpdatingObject<u32, String>>::object_builder()
+            .with_source(source)
+            .with_processor(Identity)
+            .with_fetch_interval(Duration::from_secs(3600))
+            .with_history(3)
+            .build()
+            .unwrap();
+        assert!(cache.refresh_now());
+        cache
+    }
+
+    #[test]
+    fn rollback_to_retained_version() {
+        let cache = cache();
+
+        cache.rollback_to(&1).unwrap();
+        assert_eq!(cache.cache().current_version(), Some(1));
+        assert_eq!(cache.cache().get_current().as_str(), "one");
+
+        assert!(cache.rollback_to(&7).is_err());
+        assert_eq!(cache.cache().current_version(), Some(1));
+
+        // Not pinned, so polling moves on.
+        assert!(cache.refresh_now());
+        assert_eq!(cache.cache().get_current().as_str(), "three");
+    }
+
+    #[test]
+    fn roll_forward_after_rollback() {
+        let cache = cache();
+        assert!(cache.refresh_now());
+
+        cache.rollback_to(&2).unwrap();
+        assert_eq!(cache.cache().get_current().as_str(), "two");
+
+        // The source has nothing newer than "three".
+        assert!(cache.roll_forward());
+        assert_eq!(cache.cache().current_version(), Some(3));
+        assert_eq!(cache.cache().get_current().as_str(), "three");
+    }
+
+    #[test]
+    fn pin_holds_back_updates() {
+        let cache = cache();
+
+        cache.pin(&1).unwrap();
+        assert_eq!(cache.cache().get_current().as_str(), "one");
+
+        cache.refresh_now();
+        assert_eq!(cache.cache().get_current().as_str(), "one");
+
+        assert!(cache.unpin());
+        assert_eq!(cache.cache().current_version(), Some(3));
+        assert_eq!(cache.cache().get_current().as_str(), "three");
+    }
+
+    #[test]
+    fn unpin_restores_latest_without_new_version() {
+        let cache = cache();
+        assert!(cache.refresh_now());
+
+        cache.pin(&2).unwrap();
+        assert_eq!(cache.cache().get_current().as_str(), "two");
+
+        // The source has nothing newer than "three".
+        assert!(cache.unpin());
+        assert_eq!(cache.cache().current_version(), Some(3));
+        assert_eq!(cache.cache().get_current().as_str(), "three");
+
+        assert!(!cache.refresh_now());
+        assert_eq!(cache.cache().get_current().as_str(), "three");
+    }
+}
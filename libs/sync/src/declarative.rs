@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use mirror_cache_core::collections::UpdatingMap;
+use mirror_cache_core::processors::json::JsonMapProcessor;
+use mirror_cache_core::processors::{RawConfigProcessor, RawLineMapProcessor};
+use mirror_cache_core::util::{Absent, Error, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::cache::{Builder, MirrorCache};
+#[cfg(feature = "github")]
+use crate::sources::github::{GitHubConfigSource, Octocrab};
+#[cfg(feature = "http")]
+use crate::sources::http::HttpConfigSource;
+use crate::sources::sources::{ConfigSource, LocalFileConfigSource};
+
+pub type Payload = Box<dyn Read + Send>;
+
+pub type DeclaredBuilder<V> = Builder<
+    UpdatingMap<String, String, V>,
+    HashMap<String, Arc<V>>,
+    Payload,
+    String,
+    DeclaredSource,
+    DeclaredProcessor<V>,
+    Duration,
+    Absent,
+    Absent,
+    Absent,
+    Absent,
+>;
+
+// Describes a map cache, so which backend it polls and how often can be changed without a
+// rebuild. For example:
+//
+//   name = "feature-flags"
+//   interval_secs = 30
+//   format = { kind = "lines", separator = "=" }
+//
+//   [source]
+//   kind = "github"
+//   owner = "acme"
+//   repo = "config"
+//   branch = "main"
+//   path = "flags.conf"
+//   token_env = "GITHUB_TOKEN"
+//
+// Credentials are never written into the description itself, only the name of the environment
+// variable to read them from when the cache is built.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CacheSpec {
+    pub name: Option<String>,
+    pub interval_secs: u64,
+    pub source: SourceSpec,
+    pub format: FormatSpec,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum SourceSpec {
+    File {
+        path: PathBuf,
+    },
+    #[cfg(feature = "http")]
+    Http {
+        url: String,
+        token_env: Option<String>,
+    },
+    #[cfg(feature = "github")]
+    Github {
+        owner: String,
+        repo: String,
+        branch: String,
+        path: String,
+        token_env: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum FormatSpec {
+    // A JSON object of keys to values.
+    Json,
+    // One key and value per line, split on the first separator. Blank lines and lines starting
+    // with '#' are skipped.
+    Lines {
+        #[serde(default = "default_separator")]
+        separator: String,
+    },
+}
+
+fn default_separator() -> String {
+    String::from("=")
+}
+
+impl CacheSpec {
+    pub fn from_toml(spec: &str) -> Result<CacheSpec> {
+        Ok(toml::from_str(spec)?)
+    }
+
+    pub fn from_toml_file<P: Into<PathBuf>>(path: P) -> Result<CacheSpec> {
+        CacheSpec::from_toml(&std::fs::read_to_string(path.into())?)
+    }
+
+    // A builder with the source, processor, interval and name filled in, for adding callbacks,
+    // metrics or a fallback before building.
+    pub fn builder<V>(&self) -> Result<DeclaredBuilder<V>>
+        where V: DeserializeOwned + FromStr + Send + Sync + 'static, V::Err: Display {
+        let builder = MirrorCache::<UpdatingMap<String, String, V>>::map_builder()
+            .with_source(DeclaredSource::new(&self.source)?)
+            .with_processor(DeclaredProcessor::new(self.format.clone()))
+            .with_fetch_interval(Duration::from_secs(self.interval_secs));
+
+        Ok(match &self.name {
+            Some(name) => builder.with_name(name),
+            None => builder,
+        })
+    }
+
+    pub fn build<V>(&self) -> Result<MirrorCache<UpdatingMap<String, String, V>>>
+        where V: DeserializeOwned + FromStr + Send + Sync + 'static, V::Err: Display {
+        self.builder()?.build()
+    }
+}
+
+// Whichever source the spec named. Versions are carried as strings so they all fit one type.
+pub struct DeclaredSource {
+    inner: Inner,
+}
+
+enum Inner {
+    File(LocalFileConfigSource<PathBuf>),
+    #[cfg(feature = "http")]
+    Http(HttpConfigSource),
+    #[cfg(feature = "github")]
+    Github(Box<GitHubConfigSource>),
+}
+
+impl DeclaredSource {
+    pub fn new(spec: &SourceSpec) -> Result<DeclaredSource> {
+        let inner = match spec {
+            SourceSpec::File { path } => Inner::File(LocalFileConfigSource::new(path.clone())),
+            #[cfg(feature = "http")]
+            SourceSpec::Http { url, token_env } => {
+                let source = HttpConfigSource::builder(url.as_str()).build()?;
+                match token_env {
+                    Some(var) => Inner::Http(source.with_bearer_token(credential(var)?)),
+                    None => Inner::Http(source),
+                }
+            }
+            #[cfg(feature = "github")]
+            SourceSpec::Github { owner, repo, branch, path, token_env } => {
+                let client = match token_env {
+                    Some(var) => Octocrab::builder().personal_token(credential(var)?).build()?,
+                    None => Octocrab::builder().build()?,
+                };
+                Inner::Github(Box::new(GitHubConfigSource::new(client, owner.clone(), repo.clone(), branch.clone(), path.clone())?))
+            }
+        };
+
+        Ok(DeclaredSource { inner })
+    }
+}
+
+#[cfg(any(feature = "http", feature = "github"))]
+fn credential(var: &str) -> Result<String> {
+    std::env::var(var)
+        .map_err(|e| Error::new(format!("Couldn't read credential from {}: {}", var, e).as_str()))
+}
+
+fn boxed<E: ToString, R: Read + Send + 'static>((version, raw): (Option<E>, R)) -> (Option<String>, Payload) {
+    (version.map(|v| v.to_string()), Box::new(raw))
+}
+
+impl ConfigSource<String, Payload> for DeclaredSource {
+    fn fetch(&self) -> Result<(Option<String>, Payload)> {
+        match &self.inner {
+            Inner::File(source) => source.fetch().map(boxed),
+            #[cfg(feature = "http")]
+            Inner::Http(source) => source.fetch().map(boxed),
+            #[cfg(feature = "github")]
+            Inner::Github(source) => source.fetch().map(boxed),
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Payload)>> {
+        match &self.inner {
+            Inner::File(source) => {
+                let mtime: u128 = version.parse()?;
+                source.fetch_if_newer(&mtime).map(|f| f.map(boxed))
+            }
+            #[cfg(feature = "http")]
+            Inner::Http(source) => source.fetch_if_newer(version).map(|f| f.map(boxed)),
+            #[cfg(feature = "github")]
+            Inner::Github(source) => source.fetch_if_newer(version).map(|f| f.map(boxed)),
+        }
+    }
+}
+
+pub struct DeclaredProcessor<V> {
+    format: FormatSpec,
+    _phantom: PhantomData<fn() -> V>,
+}
+
+impl<V> DeclaredProcessor<V> {
+    pub fn new(format: FormatSpec) -> DeclaredProcessor<V> {
+        DeclaredProcessor {
+            format,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<V> RawConfigProcessor<Payload, HashMap<String, Arc<V>>> for DeclaredProcessor<V>
+    where V: DeserializeOwned + FromStr + Send + Sync + 'static, V::Err: Display {
+    fn process(&self, raw: Payload) -> Result<HashMap<String, Arc<V>>> {
+        match &self.format {
+            FormatSpec::Json => JsonMapProcessor::new().process(raw),
+            FormatSpec::Lines { separator } => {
+                let separator = separator.clone();
+                RawLineMapProcessor::new(move |line: String| parse_line(&line, &separator)).process(raw)
+            }
+        }
+    }
+}
+
+fn parse_line<V: FromStr>(line: &str, separator: &str) -> Result<Option<(String, V)>> where V::Err: Display {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let Some((key, value)) = line.split_once(separator) else {
+        return Err(Error::new(format!("No '{}' in line: {}", separator, line).as_str()));
+    };
+
+    match value.trim().parse() {
+        Ok(value) => Ok(Some((String::from(key.trim()), value))),
+        Err(e) => Err(Error::new(format!("Unparseable value for {}: {}", key.trim(), e).as_str())),
+    }
+}
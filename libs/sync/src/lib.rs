@@ -1,2 +1,5 @@
 pub mod cache;
 pub mod sources;
+
+#[cfg(feature = "declarative")]
+pub mod declarative;
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::marker::PhantomData;
+use flate2::read::GzDecoder;
+use tar::Archive;
+use zip::ZipArchive;
+
+use mirror_cache_core::util::{RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+// Unpacks an archive fetched by another source, for example a release tarball from GitHub, into
+// its files keyed by their path within the archive. Directories and links are skipped. The inner
+// source's version is passed through unchanged.
+pub struct ArchiveConfigSource<C, S> {
+    inner: C,
+    format: ArchiveFormat,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, S> ArchiveConfigSource<C, S> {
+    pub fn new(inner: C, format: ArchiveFormat) -> ArchiveConfigSource<C, S> {
+        ArchiveConfigSource {
+            inner,
+            format,
+            _phantom_s: PhantomData,
+        }
+    }
+
+    fn unpack<R: Read>(&self, raw: R) -> Result<HashMap<String, Vec<u8>>> {
+        match self.format {
+            ArchiveFormat::Tar => unpack_tar(raw),
+            ArchiveFormat::TarGz => unpack_tar(GzDecoder::new(raw)),
+            ArchiveFormat::Zip => unpack_zip(raw),
+        }
+    }
+}
+
+impl<E, S, C> ConfigSource<E, HashMap<String, Vec<u8>>> for ArchiveConfigSource<C, S>
+    where
+        S: Read,
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, HashMap<String, Vec<u8>>)> {
+        let (version, raw) = self.inner.fetch()?;
+        Ok((version, self.unpack(raw)?))
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, HashMap<String, Vec<u8>>)>> {
+        match self.inner.fetch_if_newer(version)? {
+            Some((version, raw)) => Ok(Some((version, self.unpack(raw)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+fn unpack_tar<R: Read>(raw: R) -> Result<HashMap<String, Vec<u8>>> {
+    let mut files = HashMap::new();
+    for entry in Archive::new(raw).entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.insert(name, content);
+    }
+
+    Ok(files)
+}
+
+// Zip keeps its index at the end of the archive, so it has to be read in full first.
+fn unpack_zip<R: Read>(mut raw: R) -> Result<HashMap<String, Vec<u8>>> {
+    let mut bytes = Vec::new();
+    raw.read_to_end(&mut bytes)?;
+
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let mut files = HashMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if !entry.is_file() {
+            continue;
+        }
+
+        let name = String::from(entry.name());
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        files.insert(name, content);
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,114 @@
+pub use reqwest::blocking::Client;
+
+use std::io::Cursor;
+
+use reqwest::blocking::RequestBuilder;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use url::form_urlencoded::byte_serialize;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const API_ROOT: &str = "https://api.bitbucket.org/2.0/repositories";
+
+enum BitbucketAuth {
+    AppPassword(String, String),
+    Token(String),
+}
+
+#[derive(Deserialize)]
+struct CommitPage {
+    values: Vec<Commit>,
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    hash: String,
+}
+
+pub struct BitbucketConfigSource {
+    client: Client,
+    repo_url: String,
+    branch: String,
+    path: String,
+    auth: Option<BitbucketAuth>,
+}
+
+impl BitbucketConfigSource {
+    pub fn new<S: Into<String>>(client: Client, workspace: S, repo: S, branch: S, path: S) -> Result<BitbucketConfigSource> {
+        let workspace: String = byte_serialize(workspace.into().as_bytes()).collect();
+        let repo: String = byte_serialize(repo.into().as_bytes()).collect();
+
+        Ok(BitbucketConfigSource {
+            client,
+            repo_url: format!("{}/{}/{}", API_ROOT, workspace, repo),
+            branch: byte_serialize(branch.into().as_bytes()).collect(),
+            path: path.into().trim_start_matches('/').to_string(),
+            auth: None,
+        })
+    }
+
+    pub fn with_app_password<S: Into<String>>(mut self, username: S, app_password: S) -> BitbucketConfigSource {
+        self.auth = Some(BitbucketAuth::AppPassword(username.into(), app_password.into()));
+        self
+    }
+
+    pub fn with_token<S: Into<String>>(mut self, token: S) -> BitbucketConfigSource {
+        self.auth = Some(BitbucketAuth::Token(token.into()));
+        self
+    }
+
+    fn authorized(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Some(BitbucketAuth::AppPassword(user, password)) => req.basic_auth(user, Some(password)),
+            Some(BitbucketAuth::Token(token)) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    fn latest_commit(&self) -> Result<String> {
+        let resp = self.authorized(self.client.get(format!("{}/commits/{}", self.repo_url, self.branch)))
+            .query(&[("path", self.path.as_str()), ("pagelen", "1")])
+            .send()?;
+
+        if !resp.status().is_success() {
+            return Err(Error::new(format!("Commit lookup failed. Status: {}", resp.status().as_str()).as_str()));
+        }
+
+        match resp.json::<CommitPage>()?.values.into_iter().next() {
+            Some(commit) => Ok(commit.hash),
+            None => Err(Error::new("File not found")),
+        }
+    }
+
+    fn fetch_at(&self, hash: String) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let resp = self.authorized(self.client.get(format!("{}/src/{}/{}", self.repo_url, hash, self.path)))
+            .send()?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            Err(Error::new("File not found"))
+        } else if resp.status().is_success() {
+            Ok((Some(hash), Cursor::new(resp.bytes()?.to_vec())))
+        } else {
+            Err(Error::new(format!("Fetch failed. Status: {}", resp.status().as_str()).as_str()))
+        }
+    }
+}
+
+impl ConfigSource<String, Cursor<Vec<u8>>> for BitbucketConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let hash = self.latest_commit()?;
+        self.fetch_at(hash)
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let hash = self.latest_commit()?;
+        if &hash == version {
+            return Ok(None);
+        }
+
+        self.fetch_at(hash).map(Some)
+    }
+}
@@ -0,0 +1,90 @@
+pub use std::sync::mpsc::{channel, Receiver, Sender};
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+struct Received<S> {
+    count: u64,
+    latest: Option<S>,
+    closed: bool,
+}
+
+// Lets application code push payloads into a cache over a channel. Each payload received fires a
+// refresh, and the number received so far serves as the version. Only the latest payload is kept,
+// anything superseded before the cache gets to it is skipped.
+pub struct ChannelConfigSource<S> {
+    received: Arc<(Mutex<Received<S>>, Condvar)>,
+    trigger: RefreshTrigger,
+}
+
+impl<S: Send + 'static> ChannelConfigSource<S> {
+    pub fn new(rx: Receiver<S>) -> ChannelConfigSource<S> {
+        let received = Arc::new((
+            Mutex::new(Received { count: 0, latest: None, closed: false }),
+            Condvar::new(),
+        ));
+        let trigger = RefreshTrigger::new();
+
+        let weak_received = Arc::downgrade(&received);
+        let on_receive = trigger.clone();
+        thread::spawn(move || {
+            for payload in rx.iter() {
+                match weak_received.upgrade() {
+                    Some(received) => {
+                        let (lock, ready) = &*received;
+                        if let Ok(mut received) = lock.lock() {
+                            received.count += 1;
+                            received.latest = Some(payload);
+                            ready.notify_all();
+                        }
+                    }
+                    None => return,
+                }
+                on_receive.fire();
+            }
+
+            // All senders are gone, wake anything still waiting on a first payload.
+            if let Some(received) = weak_received.upgrade() {
+                let (lock, ready) = &*received;
+                if let Ok(mut received) = lock.lock() {
+                    received.closed = true;
+                    ready.notify_all();
+                }
+            }
+        });
+
+        ChannelConfigSource {
+            received,
+            trigger,
+        }
+    }
+}
+
+impl<S: Clone> ConfigSource<u64, S> for ChannelConfigSource<S> {
+    fn fetch(&self) -> Result<(Option<u64>, S)> {
+        // Nothing can be returned until the first payload comes in.
+        let (lock, ready) = &*self.received;
+        let received = ready.wait_while(lock.lock()?, |r| r.latest.is_none() && !r.closed)?;
+
+        match &received.latest {
+            Some(payload) => Ok((Some(received.count), payload.clone())),
+            None => Err(Error::new("Channel closed before receiving a payload")),
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &u64) -> Result<Option<(Option<u64>, S)>> {
+        if self.received.0.lock()?.count <= *version {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
@@ -0,0 +1,165 @@
+use std::io;
+use std::io::{BufReader, Chain, Cursor, Read};
+use std::marker::PhantomData;
+use flate2::bufread::GzDecoder;
+
+use mirror_cache_core::util::{RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// The bytes read to detect the format, put back in front of the rest of the payload.
+type Raw<S> = BufReader<Chain<Cursor<Vec<u8>>, S>>;
+
+#[derive(Clone, Copy)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+// Decompresses the payload of another source as it's read. Unless a format is given, it's
+// detected from the payload's magic bytes, and payloads matching neither format are passed
+// through as they are.
+pub struct DecompressingSource<C, S> {
+    inner: C,
+    format: Option<Compression>,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, S: Read> DecompressingSource<C, S> {
+    pub fn new(inner: C) -> DecompressingSource<C, S> {
+        DecompressingSource {
+            inner,
+            format: None,
+            _phantom_s: PhantomData,
+        }
+    }
+
+    pub fn with_format(mut self, format: Compression) -> DecompressingSource<C, S> {
+        self.format = Some(format);
+        self
+    }
+
+    fn decompress(&self, mut raw: S) -> Result<Decompressed<S>> {
+        // A single read can return fewer bytes than the magic numbers, so read until there are
+        // enough to tell or the payload ends.
+        let mut head = Vec::with_capacity(ZSTD_MAGIC.len());
+        if self.format.is_none() {
+            (&mut raw).take(ZSTD_MAGIC.len() as u64).read_to_end(&mut head)?;
+        }
+
+        let format = match self.format {
+            Some(format) => Some(format),
+            None if head.starts_with(GZIP_MAGIC) => Some(Compression::Gzip),
+            None if head.starts_with(ZSTD_MAGIC) => Some(Compression::Zstd),
+            None => None,
+        };
+
+        let raw = BufReader::new(Cursor::new(head).chain(raw));
+        Ok(match format {
+            Some(Compression::Gzip) => Decompressed::Gzip(GzDecoder::new(raw)),
+            Some(Compression::Zstd) => Decompressed::Zstd(zstd::Decoder::with_buffer(raw)?),
+            None => Decompressed::Plain(raw),
+        })
+    }
+}
+
+impl<E, S, C> ConfigSource<E, Decompressed<S>> for DecompressingSource<C, S>
+    where
+        S: Read,
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, Decompressed<S>)> {
+        let (version, raw) = self.inner.fetch()?;
+        Ok((version, self.decompress(raw)?))
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, Decompressed<S>)>> {
+        match self.inner.fetch_if_newer(version)? {
+            Some((version, raw)) => Ok(Some((version, self.decompress(raw)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+pub enum Decompressed<S: Read> {
+    Plain(Raw<S>),
+    Gzip(GzDecoder<Raw<S>>),
+    Zstd(zstd::Decoder<'static, Raw<S>>),
+}
+
+impl<S: Read> Read for Decompressed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decompressed::Plain(raw) => raw.read(buf),
+            Decompressed::Gzip(decoder) => decoder.read(buf),
+            Decompressed::Zstd(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::{Cursor, Read, Write};
+    use flate2::write::GzEncoder;
+
+    use crate::sources::decompress::{Compression, DecompressingSource};
+    use crate::sources::sources::{ConfigSource, StaticConfigSource};
+
+    // Hands out a byte per read, as a slow network stream might.
+    #[derive(Clone)]
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    fn gzip(payload: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(payload).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn fetch_all(compressed: Vec<u8>, format: Option<Compression>) -> String {
+        let mut source = DecompressingSource::new(StaticConfigSource::new(Some(1), Trickle(Cursor::new(compressed))));
+        if let Some(format) = format {
+            source = source.with_format(format);
+        }
+
+        let (_, mut payload) = source.fetch().unwrap();
+        let mut read = String::new();
+        payload.read_to_string(&mut read).unwrap();
+        read
+    }
+
+    #[test]
+    fn detects_gzip() {
+        assert_eq!(fetch_all(gzip(b"key=value"), None), "key=value");
+    }
+
+    #[test]
+    fn detects_zstd() {
+        let compressed = zstd::encode_all(&b"key=value"[..], 0).unwrap();
+        assert_eq!(fetch_all(compressed, None), "key=value");
+    }
+
+    #[test]
+    fn passes_plain_through() {
+        assert_eq!(fetch_all(b"key=value".to_vec(), None), "key=value");
+        assert_eq!(fetch_all(b"k".to_vec(), None), "k");
+    }
+
+    #[test]
+    fn uses_given_format() {
+        assert_eq!(fetch_all(gzip(b"key=value"), Some(Compression::Gzip)), "key=value");
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{Cursor, Read};
+use std::marker::PhantomData;
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const NONCE_LEN: usize = 12;
+
+type KeyProvider = Box<dyn Fn() -> Result<Vec<u8>> + Send + Sync>;
+
+// Decrypts the payload of another source with AES-256-GCM before it reaches the processor, so
+// configs carrying secrets can be kept in stores that aren't fully trusted. Payloads are expected
+// as the 12 byte nonce followed by the ciphertext and tag. The key provider is called for every
+// payload so keys can be rotated, and must return a 32 byte key.
+pub struct EncryptedSource<C, S> {
+    inner: C,
+    key_provider: KeyProvider,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, S: Read> EncryptedSource<C, S> {
+    pub fn new<F>(inner: C, key_provider: F) -> EncryptedSource<C, S>
+        where F: Fn() -> Result<Vec<u8>> + Send + Sync + 'static {
+        EncryptedSource {
+            inner,
+            key_provider: Box::new(key_provider),
+            _phantom_s: PhantomData,
+        }
+    }
+
+    fn decrypt(&self, mut raw: S) -> Result<Cursor<Vec<u8>>> {
+        let mut sealed = Vec::new();
+        raw.read_to_end(&mut sealed)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(Error::new("Encrypted payload is too short to hold a nonce"));
+        }
+
+        let key = (self.key_provider)()?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| Error::new(format!("Expected a 32 byte key, got {} bytes", key.len()).as_str()))?;
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map(Cursor::new)
+            .map_err(|_| Error::new("Decryption failed, the key is wrong or the payload was altered"))
+    }
+}
+
+impl<E, S, C> ConfigSource<E, Cursor<Vec<u8>>> for EncryptedSource<C, S>
+    where
+        S: Read,
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, Cursor<Vec<u8>>)> {
+        let (version, raw) = self.inner.fetch()?;
+        Ok((version, self.decrypt(raw)?))
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, Cursor<Vec<u8>>)>> {
+        match self.inner.fetch_if_newer(version)? {
+            Some((version, raw)) => Ok(Some((version, self.decrypt(raw)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
@@ -0,0 +1,187 @@
+pub use etcd_client::ConnectOptions;
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::time::Duration;
+use etcd_client::{Client, GetOptions, KeyValue, WatchOptions};
+use tokio::runtime::Handle;
+use tokio::time;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::runtime::SourceRuntime;
+use crate::sources::sources::ConfigSource;
+
+const WATCH_RETRY: Duration = Duration::from_secs(1);
+
+pub struct EtcdConfigSource {
+    client: Client,
+    key: String,
+    trigger: Option<RefreshTrigger>,
+    rt: SourceRuntime,
+}
+
+impl EtcdConfigSource {
+    pub fn new<E: AsRef<str>, S: Into<String>>(
+        endpoints: &[E], options: Option<ConnectOptions>, key: S,
+    ) -> Result<EtcdConfigSource> {
+        // The watch task, if any, needs a worker to run on between fetches.
+        EtcdConfigSource::connect(endpoints, options, key, SourceRuntime::owned_multi_thread()?)
+    }
+
+    // Runs requests, and the watch if enabled, on an existing multi-threaded runtime rather than
+    // one of the source's own, so many sources can share it.
+    pub fn new_with_runtime<E: AsRef<str>, S: Into<String>>(
+        endpoints: &[E], options: Option<ConnectOptions>, key: S, runtime: Handle,
+    ) -> Result<EtcdConfigSource> {
+        EtcdConfigSource::connect(endpoints, options, key, SourceRuntime::Shared(runtime))
+    }
+
+    fn connect<E: AsRef<str>, S: Into<String>>(
+        endpoints: &[E], options: Option<ConnectOptions>, key: S, rt: SourceRuntime,
+    ) -> Result<EtcdConfigSource> {
+        Ok(EtcdConfigSource {
+            client: rt.block_on(Client::connect(endpoints, options))?,
+            key: key.into(),
+            trigger: None,
+            rt,
+        })
+    }
+
+    pub fn with_watch(mut self) -> EtcdConfigSource {
+        let trigger = RefreshTrigger::new();
+        self.rt.spawn(
+            watch(self.client.clone(), self.key.clone(), false, trigger.clone())
+        );
+        self.trigger = Some(trigger);
+        self
+    }
+}
+
+impl ConfigSource<i64, Cursor<Vec<u8>>> for EtcdConfigSource {
+    fn fetch(&self) -> Result<(Option<i64>, Cursor<Vec<u8>>)> {
+        let resp = self.rt.block_on(self.client.clone().get(self.key.as_str(), None))?;
+        match resp.kvs().first() {
+            Some(kv) => Ok((Some(kv.mod_revision()), Cursor::new(kv.value().to_vec()))),
+            None => Err(Error::new("Key not found")),
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &i64) -> Result<Option<(Option<i64>, Cursor<Vec<u8>>)>> {
+        let resp = self.rt.block_on(
+            self.client.clone().get(self.key.as_str(), Some(GetOptions::new().with_keys_only()))
+        )?;
+
+        match resp.kvs().first() {
+            Some(kv) if kv.mod_revision() == *version => Ok(None),
+            Some(_) => self.fetch().map(Some),
+            None => Err(Error::new("Key not found")),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.trigger.clone()
+    }
+}
+
+pub struct EtcdPrefixConfigSource {
+    client: Client,
+    prefix: String,
+    trigger: Option<RefreshTrigger>,
+    rt: SourceRuntime,
+}
+
+impl EtcdPrefixConfigSource {
+    pub fn new<E: AsRef<str>, S: Into<String>>(
+        endpoints: &[E], options: Option<ConnectOptions>, prefix: S,
+    ) -> Result<EtcdPrefixConfigSource> {
+        // The watch task, if any, needs a worker to run on between fetches.
+        EtcdPrefixConfigSource::connect(endpoints, options, prefix, SourceRuntime::owned_multi_thread()?)
+    }
+
+    pub fn new_with_runtime<E: AsRef<str>, S: Into<String>>(
+        endpoints: &[E], options: Option<ConnectOptions>, prefix: S, runtime: Handle,
+    ) -> Result<EtcdPrefixConfigSource> {
+        EtcdPrefixConfigSource::connect(endpoints, options, prefix, SourceRuntime::Shared(runtime))
+    }
+
+    fn connect<E: AsRef<str>, S: Into<String>>(
+        endpoints: &[E], options: Option<ConnectOptions>, prefix: S, rt: SourceRuntime,
+    ) -> Result<EtcdPrefixConfigSource> {
+        Ok(EtcdPrefixConfigSource {
+            client: rt.block_on(Client::connect(endpoints, options))?,
+            prefix: prefix.into(),
+            trigger: None,
+            rt,
+        })
+    }
+
+    pub fn with_watch(mut self) -> EtcdPrefixConfigSource {
+        let trigger = RefreshTrigger::new();
+        self.rt.spawn(
+            watch(self.client.clone(), self.prefix.clone(), true, trigger.clone())
+        );
+        self.trigger = Some(trigger);
+        self
+    }
+
+    // Any put raises the highest mod revision under the prefix and any delete lowers the
+    // count, so together they change whenever the contents do.
+    fn get_version(kvs: &[KeyValue]) -> (i64, usize) {
+        let revision = kvs.iter().map(|kv| kv.mod_revision()).max().unwrap_or(0);
+        (revision, kvs.len())
+    }
+}
+
+impl ConfigSource<(i64, usize), HashMap<String, Vec<u8>>> for EtcdPrefixConfigSource {
+    fn fetch(&self) -> Result<(Option<(i64, usize)>, HashMap<String, Vec<u8>>)> {
+        let resp = self.rt.block_on(
+            self.client.clone().get(self.prefix.as_str(), Some(GetOptions::new().with_prefix()))
+        )?;
+
+        let mut entries = HashMap::new();
+        for kv in resp.kvs() {
+            entries.insert(String::from(kv.key_str()?), kv.value().to_vec());
+        }
+
+        Ok((Some(EtcdPrefixConfigSource::get_version(resp.kvs())), entries))
+    }
+
+    fn fetch_if_newer(&self, version: &(i64, usize)) -> Result<Option<(Option<(i64, usize)>, HashMap<String, Vec<u8>>)>> {
+        let resp = self.rt.block_on(
+            self.client.clone().get(self.prefix.as_str(), Some(GetOptions::new().with_prefix().with_keys_only()))
+        )?;
+
+        if &EtcdPrefixConfigSource::get_version(resp.kvs()) == version {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.trigger.clone()
+    }
+}
+
+async fn watch(mut client: Client, key: String, prefix: bool, trigger: RefreshTrigger) {
+    loop {
+        let options = if prefix {
+            Some(WatchOptions::new().with_prefix())
+        } else {
+            None
+        };
+
+        // The watcher has to be held for as long as the stream is read, dropping it cancels the watch.
+        if let Ok((_watcher, mut stream)) = client.watch(key.as_str(), options).await {
+            while let Ok(Some(resp)) = stream.message().await {
+                if !resp.events().is_empty() {
+                    trigger.fire();
+                }
+            }
+        }
+
+        // The stream dropped, polling carries on at the fetch interval until it's re-established.
+        time::sleep(WATCH_RETRY).await;
+    }
+}
@@ -0,0 +1,160 @@
+pub use reqwest::blocking::Client;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const API_ROOT: &str = "https://firestore.googleapis.com/v1";
+
+// Masking on the document name returns everything but the fields, which is all that's needed to
+// check for changes.
+const METADATA_ONLY: [(&str, &str); 1] = [("mask.fieldPaths", "__name__")];
+
+type TokenProvider = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    name: String,
+    #[serde(default)]
+    fields: Value,
+    update_time: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentPage {
+    #[serde(default)]
+    documents: Vec<Document>,
+    next_page_token: Option<String>,
+}
+
+struct FirestoreClient {
+    client: Client,
+    url: String,
+    token_provider: TokenProvider,
+}
+
+impl FirestoreClient {
+    fn new<F: Fn() -> Result<String> + Send + Sync + 'static>(client: Client, project: &str, database: &str, path: &str, token_provider: F) -> FirestoreClient {
+        FirestoreClient {
+            client,
+            url: format!("{}/projects/{}/databases/{}/documents/{}", API_ROOT, project, database, path.trim_matches('/')),
+            token_provider: Arc::new(token_provider),
+        }
+    }
+
+    fn get<T: for<'de> Deserialize<'de>>(&self, query: &[(&str, &str)]) -> Result<T> {
+        let resp = self.client.get(self.url.as_str())
+            .bearer_auth((self.token_provider)()?)
+            .query(query)
+            .send()?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            Err(Error::new("Document not found"))
+        } else if resp.status().is_success() {
+            Ok(resp.json()?)
+        } else {
+            Err(Error::new(format!("Fetch failed. Status: {}", resp.status().as_str()).as_str()))
+        }
+    }
+
+    fn list(&self, metadata_only: bool) -> Result<Vec<Document>> {
+        let mut documents = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut query: Vec<(&str, &str)> = if metadata_only { METADATA_ONLY.to_vec() } else { vec![] };
+            if let Some(token) = &page_token {
+                query.push(("pageToken", token.as_str()));
+            }
+
+            let page: DocumentPage = self.get(&query)?;
+            documents.extend(page.documents);
+            match page.next_page_token {
+                Some(token) if !token.is_empty() => page_token = Some(token),
+                _ => return Ok(documents),
+            }
+        }
+    }
+}
+
+pub struct FirestoreConfigSource {
+    client: FirestoreClient,
+}
+
+impl FirestoreConfigSource {
+    pub fn new<S: AsRef<str>, F: Fn() -> Result<String> + Send + Sync + 'static>(
+        client: Client, project: S, database: S, document: S, token_provider: F,
+    ) -> FirestoreConfigSource {
+        FirestoreConfigSource {
+            client: FirestoreClient::new(client, project.as_ref(), database.as_ref(), document.as_ref(), token_provider),
+        }
+    }
+}
+
+impl ConfigSource<DateTime<Utc>, Value> for FirestoreConfigSource {
+    fn fetch(&self) -> Result<(Option<DateTime<Utc>>, Value)> {
+        let document: Document = self.client.get(&[])?;
+        Ok((Some(document.update_time), document.fields))
+    }
+
+    fn fetch_if_newer(&self, version: &DateTime<Utc>) -> Result<Option<(Option<DateTime<Utc>>, Value)>> {
+        let metadata: Document = self.client.get(&METADATA_ONLY)?;
+        if &metadata.update_time == version {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+}
+
+pub struct FirestoreCollectionSource {
+    client: FirestoreClient,
+}
+
+impl FirestoreCollectionSource {
+    pub fn new<S: AsRef<str>, F: Fn() -> Result<String> + Send + Sync + 'static>(
+        client: Client, project: S, database: S, collection: S, token_provider: F,
+    ) -> FirestoreCollectionSource {
+        FirestoreCollectionSource {
+            client: FirestoreClient::new(client, project.as_ref(), database.as_ref(), collection.as_ref(), token_provider),
+        }
+    }
+
+    // Firestore has no revision for a collection as a whole, only an updateTime on each document.
+    // Deleting a document leaves nothing behind to stamp, so the document count stands in for it.
+    fn get_version(documents: &[Document]) -> (DateTime<Utc>, usize) {
+        let latest = documents.iter().map(|d| d.update_time).max().unwrap_or_default();
+        (latest, documents.len())
+    }
+}
+
+impl ConfigSource<(DateTime<Utc>, usize), HashMap<String, Value>> for FirestoreCollectionSource {
+    fn fetch(&self) -> Result<(Option<(DateTime<Utc>, usize)>, HashMap<String, Value>)> {
+        let documents = self.client.list(false)?;
+        let version = FirestoreCollectionSource::get_version(&documents);
+
+        let entries = documents.into_iter()
+            .map(|d| (d.name.rsplit('/').next().unwrap_or_default().to_string(), d.fields))
+            .collect();
+
+        Ok((Some(version), entries))
+    }
+
+    fn fetch_if_newer(&self, version: &(DateTime<Utc>, usize)) -> Result<Option<(Option<(DateTime<Utc>, usize)>, HashMap<String, Value>)>> {
+        let documents = self.client.list(true)?;
+        if &FirestoreCollectionSource::get_version(&documents) == version {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+}
@@ -0,0 +1,95 @@
+pub use reqwest::blocking::Client;
+
+use std::io::Cursor;
+
+use reqwest::blocking::RequestBuilder;
+use reqwest::StatusCode;
+use url::form_urlencoded::byte_serialize;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const LAST_COMMIT_HEADER: &str = "X-Gitlab-Last-Commit-Id";
+
+pub struct GitLabConfigSource {
+    client: Client,
+    file_url: String,
+    branch: String,
+    token: Option<String>,
+}
+
+impl GitLabConfigSource {
+    pub fn new<S: Into<String>>(client: Client, host: S, project: S, branch: S, path: S) -> Result<GitLabConfigSource> {
+        let host = host.into();
+        let project: String = byte_serialize(project.into().as_bytes()).collect();
+        let path: String = byte_serialize(path.into().as_bytes()).collect();
+
+        Ok(GitLabConfigSource {
+            client,
+            file_url: format!(
+                "{}/api/v4/projects/{}/repository/files/{}",
+                host.trim_end_matches('/'), project, path
+            ),
+            branch: branch.into(),
+            token: None,
+        })
+    }
+
+    pub fn with_token<S: Into<String>>(mut self, token: S) -> GitLabConfigSource {
+        self.token = Some(token.into());
+        self
+    }
+
+    fn authorized(&self, req: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => req.header("PRIVATE-TOKEN", token),
+            None => req,
+        }
+    }
+
+    fn latest_commit(&self) -> Result<String> {
+        let resp = self.authorized(self.client.head(self.file_url.as_str()))
+            .query(&[("ref", self.branch.as_str())])
+            .send()?;
+
+        if !resp.status().is_success() {
+            return Err(Error::new(format!("Commit lookup failed. Status: {}", resp.status().as_str()).as_str()));
+        }
+
+        match resp.headers().get(LAST_COMMIT_HEADER).map(|h| h.to_str()) {
+            Some(Ok(sha)) => Ok(String::from(sha)),
+            _ => Err(Error::new("GitLab response had no last commit id")),
+        }
+    }
+
+    fn fetch_at(&self, sha: String) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let resp = self.authorized(self.client.get(format!("{}/raw", self.file_url)))
+            .query(&[("ref", sha.as_str())])
+            .send()?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            Err(Error::new("File not found"))
+        } else if resp.status().is_success() {
+            Ok((Some(sha), Cursor::new(resp.bytes()?.to_vec())))
+        } else {
+            Err(Error::new(format!("Fetch failed. Status: {}", resp.status().as_str()).as_str()))
+        }
+    }
+}
+
+impl ConfigSource<String, Cursor<Vec<u8>>> for GitLabConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let sha = self.latest_commit()?;
+        self.fetch_at(sha)
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let sha = self.latest_commit()?;
+        if &sha == version {
+            return Ok(None);
+        }
+
+        self.fetch_at(sha).map(Some)
+    }
+}
@@ -0,0 +1,104 @@
+pub use reqwest::blocking::Client;
+pub use serde_json::Value;
+
+use serde_json::json;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+pub struct GraphQlConfigSource {
+    client: Client,
+    url: String,
+    query: String,
+    variables: Value,
+    payload_pointer: String,
+    version_pointer: String,
+    version_query: Option<String>,
+}
+
+impl GraphQlConfigSource {
+    // Pointers are JSON pointers into the response's `data`, for example `/config/body`.
+    pub fn new<S: Into<String>>(client: Client, url: S, query: S, payload_pointer: S, version_pointer: S) -> GraphQlConfigSource {
+        GraphQlConfigSource {
+            client,
+            url: url.into(),
+            query: query.into(),
+            variables: Value::Null,
+            payload_pointer: payload_pointer.into(),
+            version_pointer: version_pointer.into(),
+            version_query: None,
+        }
+    }
+
+    pub fn with_variables(mut self, variables: Value) -> GraphQlConfigSource {
+        self.variables = variables;
+        self
+    }
+
+    // A cheaper query selecting only the version, checked before running the full query. Its
+    // response is read using the same version pointer.
+    pub fn with_version_query<S: Into<String>>(mut self, query: S) -> GraphQlConfigSource {
+        self.version_query = Some(query.into());
+        self
+    }
+
+    fn query(&self, query: &str) -> Result<Value> {
+        let resp = self.client.post(self.url.as_str())
+            .json(&json!({"query": query, "variables": self.variables}))
+            .send()?;
+
+        if !resp.status().is_success() {
+            return Err(Error::new(format!("Query failed. Status: {}", resp.status().as_str()).as_str()));
+        }
+
+        let mut body: Value = resp.json()?;
+        if let Some(errors) = body.get("errors").filter(|e| !e.is_null()) {
+            return Err(Error::new(format!("Query returned errors: {}", errors).as_str()));
+        }
+
+        match body.get_mut("data") {
+            Some(data) => Ok(data.take()),
+            None => Err(Error::new("Query response had no data")),
+        }
+    }
+
+    fn get_version(&self, data: &Value) -> Result<String> {
+        match data.pointer(self.version_pointer.as_str()) {
+            Some(Value::String(version)) => Ok(version.clone()),
+            Some(Value::Null) | None => Err(Error::new(format!("No version found at {}", self.version_pointer).as_str())),
+            Some(version) => Ok(version.to_string()),
+        }
+    }
+
+    fn extract(&self, mut data: Value) -> Result<(Option<String>, Value)> {
+        let version = self.get_version(&data)?;
+        match data.pointer_mut(self.payload_pointer.as_str()) {
+            Some(payload) => Ok((Some(version), payload.take())),
+            None => Err(Error::new(format!("No payload found at {}", self.payload_pointer).as_str())),
+        }
+    }
+}
+
+impl ConfigSource<String, Value> for GraphQlConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, Value)> {
+        let data = self.query(self.query.as_str())?;
+        self.extract(data)
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Value)>> {
+        if let Some(version_query) = &self.version_query {
+            let data = self.query(version_query.as_str())?;
+            if &self.get_version(&data)? == version {
+                return Ok(None);
+            }
+        }
+
+        let data = self.query(self.query.as_str())?;
+        if &self.get_version(&data)? == version {
+            return Ok(None);
+        }
+
+        self.extract(data).map(Some)
+    }
+}
@@ -0,0 +1,205 @@
+pub use tonic::transport::Channel;
+pub use tonic::Status;
+
+use std::future::Future;
+use std::io::Cursor;
+use std::time::Duration;
+use futures::{Stream, StreamExt};
+use tokio::runtime::Handle;
+use tokio::sync::watch;
+use tokio::time;
+use tonic::transport::Endpoint;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::runtime::SourceRuntime;
+use crate::sources::sources::ConfigSource;
+
+const STREAM_RETRY: Duration = Duration::from_secs(1);
+const FIRST_MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// The latest message received, and why the stream is down if it is.
+#[derive(Default)]
+struct Latest {
+    message: Option<(String, Vec<u8>)>,
+    down: Option<Status>,
+}
+
+// Wraps a unary RPC that returns a version alongside the raw config. The call is given the version
+// currently held, if any, so servers that support it can answer with the same version and no body
+// rather than resending unchanged data.
+pub struct GrpcConfigSource<F> {
+    channel: Channel,
+    call: F,
+    rt: SourceRuntime,
+}
+
+impl<F, Fut> GrpcConfigSource<F>
+    where
+        F: Fn(Channel, Option<String>) -> Fut,
+        Fut: Future<Output=std::result::Result<(String, Vec<u8>), Status>> {
+    pub fn new<S: Into<String>>(endpoint: S, call: F) -> Result<GrpcConfigSource<F>> {
+        GrpcConfigSource::connect(endpoint, call, SourceRuntime::owned()?)
+    }
+
+    // Runs calls on an existing multi-threaded runtime rather than one of the source's own, so
+    // many sources can share it.
+    pub fn new_with_runtime<S: Into<String>>(endpoint: S, call: F, runtime: Handle) -> Result<GrpcConfigSource<F>> {
+        GrpcConfigSource::connect(endpoint, call, SourceRuntime::Shared(runtime))
+    }
+
+    fn connect<S: Into<String>>(endpoint: S, call: F, rt: SourceRuntime) -> Result<GrpcConfigSource<F>> {
+        let endpoint = Endpoint::from_shared(endpoint.into())?;
+        Ok(GrpcConfigSource {
+            channel: rt.block_on(endpoint.connect())?,
+            call,
+            rt,
+        })
+    }
+}
+
+impl<F, Fut> ConfigSource<String, Cursor<Vec<u8>>> for GrpcConfigSource<F>
+    where
+        F: Fn(Channel, Option<String>) -> Fut,
+        Fut: Future<Output=std::result::Result<(String, Vec<u8>), Status>> {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let (version, body) = self.rt.block_on((self.call)(self.channel.clone(), None))?;
+        Ok((Some(version), Cursor::new(body)))
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let (latest, body) = self.rt.block_on((self.call)(self.channel.clone(), Some(version.clone())))?;
+        if &latest == version {
+            return Ok(None);
+        }
+
+        Ok(Some((Some(latest), Cursor::new(body))))
+    }
+}
+
+// Holds open a server-streaming RPC in the background, each message received replaces the data
+// and fires the refresh trigger so the cache picks it up immediately. The stream is re-opened
+// if it ends or fails, and fetches fail until it is, so staleness and failure callbacks notice a
+// dead stream.
+pub struct GrpcStreamingConfigSource {
+    latest: watch::Receiver<Latest>,
+    trigger: RefreshTrigger,
+    first_message_timeout: Duration,
+    rt: SourceRuntime,
+}
+
+impl GrpcStreamingConfigSource {
+    pub fn new<S, F, Fut, St>(endpoint: S, open: F) -> Result<GrpcStreamingConfigSource>
+        where
+            S: Into<String>,
+            F: Fn(Channel) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output=std::result::Result<St, Status>> + Send + 'static,
+            St: Stream<Item=std::result::Result<(String, Vec<u8>), Status>> + Send + Unpin + 'static {
+        // The stream needs a worker to run on between fetches.
+        GrpcStreamingConfigSource::connect(endpoint, open, SourceRuntime::owned_multi_thread()?)
+    }
+
+    // Holds the stream open on an existing multi-threaded runtime rather than one of the source's
+    // own, so many sources can share it.
+    pub fn new_with_runtime<S, F, Fut, St>(endpoint: S, open: F, runtime: Handle) -> Result<GrpcStreamingConfigSource>
+        where
+            S: Into<String>,
+            F: Fn(Channel) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output=std::result::Result<St, Status>> + Send + 'static,
+            St: Stream<Item=std::result::Result<(String, Vec<u8>), Status>> + Send + Unpin + 'static {
+        GrpcStreamingConfigSource::connect(endpoint, open, SourceRuntime::Shared(runtime))
+    }
+
+    fn connect<S, F, Fut, St>(endpoint: S, open: F, rt: SourceRuntime) -> Result<GrpcStreamingConfigSource>
+        where
+            S: Into<String>,
+            F: Fn(Channel) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output=std::result::Result<St, Status>> + Send + 'static,
+            St: Stream<Item=std::result::Result<(String, Vec<u8>), Status>> + Send + Unpin + 'static {
+        let endpoint = Endpoint::from_shared(endpoint.into())?;
+        let channel = rt.block_on(endpoint.connect())?;
+
+        let (tx, latest) = watch::channel(Latest::default());
+        let trigger = RefreshTrigger::new();
+        rt.spawn(listen(channel, open, tx, trigger.clone()));
+
+        Ok(GrpcStreamingConfigSource {
+            latest,
+            trigger,
+            first_message_timeout: FIRST_MESSAGE_TIMEOUT,
+            rt,
+        })
+    }
+
+    // How long a fetch waits for the first message before failing, so build() can't hang on a
+    // stream that opens but never delivers. Thirty seconds by default.
+    pub fn with_first_message_timeout(mut self, timeout: Duration) -> GrpcStreamingConfigSource {
+        self.first_message_timeout = timeout;
+        self
+    }
+}
+
+impl ConfigSource<String, Cursor<Vec<u8>>> for GrpcStreamingConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        // Nothing can be returned until the first message, or failure, comes in.
+        let mut latest = self.latest.clone();
+        let waited = self.rt.block_on(time::timeout(self.first_message_timeout, latest.wait_for(|l| l.message.is_some() || l.down.is_some())));
+        let Ok(current) = waited else {
+            return Err(Error::new(format!("No message arrived on the stream within {:?}", self.first_message_timeout).as_str()));
+        };
+        let current = current?;
+
+        match (&current.down, &current.message) {
+            (Some(status), _) => Err(status.clone().into()),
+            (None, Some((version, body))) => Ok((Some(version.clone()), Cursor::new(body.clone()))),
+            (None, None) => Err(Error::new("Stream closed before receiving data")),
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        {
+            let latest = self.latest.borrow();
+            if let Some(status) = &latest.down {
+                return Err(status.clone().into());
+            }
+
+            if matches!(&latest.message, Some((latest, _)) if latest == version) {
+                return Ok(None);
+            }
+        }
+
+        self.fetch().map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+async fn listen<F, Fut, St>(channel: Channel, open: F, tx: watch::Sender<Latest>, trigger: RefreshTrigger)
+    where
+        F: Fn(Channel) -> Fut,
+        Fut: Future<Output=std::result::Result<St, Status>>,
+        St: Stream<Item=std::result::Result<(String, Vec<u8>), Status>> + Unpin {
+    loop {
+        let status = match open(channel.clone()).await {
+            Ok(mut stream) => {
+                tx.send_if_modified(|latest| latest.down.take().is_some());
+                loop {
+                    match stream.next().await {
+                        Some(Ok(message)) => {
+                            tx.send_modify(|latest| latest.message = Some(message));
+                            trigger.fire();
+                        }
+                        Some(Err(status)) => break status,
+                        None => break Status::unavailable("Stream ended"),
+                    }
+                }
+            }
+            Err(status) => status,
+        };
+
+        tx.send_modify(|latest| latest.down = Some(status));
+        time::sleep(STREAM_RETRY).await;
+    }
+}
@@ -0,0 +1,170 @@
+use std::io::{Cursor, Read};
+use std::marker::PhantomData;
+use std::sync::{Mutex, PoisonError};
+use sha2::{Digest, Sha256};
+
+use mirror_cache_core::util::{RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+// Versions the payload of another source by its SHA-256, for backends with no usable version of
+// their own. The inner source is always fetched in full, but unchanged payloads are recognized and
+// skip processing. The hash is hex encoded.
+pub struct HashVersionedSource<C, E, S> {
+    inner: C,
+    _phantom_e: PhantomData<fn() -> E>,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, E, S: Read> HashVersionedSource<C, E, S> {
+    pub fn new(inner: C) -> HashVersionedSource<C, E, S> {
+        HashVersionedSource {
+            inner,
+            _phantom_e: PhantomData,
+            _phantom_s: PhantomData,
+        }
+    }
+}
+
+impl<E, S, C> ConfigSource<String, Cursor<Vec<u8>>> for HashVersionedSource<C, E, S>
+    where
+        S: Read,
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let (_, raw) = self.inner.fetch()?;
+        let (hash, payload) = hash(raw)?;
+        Ok((Some(hash), Cursor::new(payload)))
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let (_, raw) = self.inner.fetch()?;
+        let (hash, payload) = hash(raw)?;
+        if &hash == version {
+            Ok(None)
+        } else {
+            Ok(Some((Some(hash), Cursor::new(payload))))
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+// The payload last handed to the cache, and the newest version found to have identical content.
+#[derive(Clone)]
+struct Handed<E> {
+    version: E,
+    hash: String,
+    latest: E,
+}
+
+// Keeps the versions of another source, but recognizes a new version whose payload is identical
+// to the last one, such as a touched file or a re-upload, and reports it as unchanged so it isn't
+// reprocessed and update callbacks stay meaningful. Later checks are made against the newest
+// version seen, so an identical payload is only downloaded once.
+pub struct ContentHashedSource<C, E, S> {
+    inner: C,
+    handed: Mutex<Option<Handed<E>>>,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, E: Clone, S: Read> ContentHashedSource<C, E, S> {
+    pub fn new(inner: C) -> ContentHashedSource<C, E, S> {
+        ContentHashedSource {
+            inner,
+            handed: Mutex::new(None),
+            _phantom_s: PhantomData,
+        }
+    }
+
+    fn hand_over(&self, version: Option<E>, hash: String, payload: Vec<u8>) -> (Option<E>, Cursor<Vec<u8>>) {
+        *self.handed.lock().unwrap_or_else(PoisonError::into_inner) = version.clone()
+            .map(|v| Handed { version: v.clone(), hash, latest: v });
+        (version, Cursor::new(payload))
+    }
+}
+
+impl<E, S, C> ConfigSource<E, Cursor<Vec<u8>>> for ContentHashedSource<C, E, S>
+    where
+        E: Clone + PartialEq,
+        S: Read,
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, Cursor<Vec<u8>>)> {
+        let (version, raw) = self.inner.fetch()?;
+        let (hash, payload) = hash(raw)?;
+        Ok(self.hand_over(version, hash, payload))
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, Cursor<Vec<u8>>)>> {
+        // Only trusted while the cache is serving what we last handed it, it may have failed to
+        // process that, in which case it's fetched and handed over again.
+        let handed = self.handed.lock().unwrap_or_else(PoisonError::into_inner).clone()
+            .filter(|h| &h.version == version);
+        let check = handed.as_ref().map_or(version, |h| &h.latest);
+
+        let (new_version, raw) = match self.inner.fetch_if_newer(check)? {
+            Some(update) => update,
+            None => return Ok(None),
+        };
+
+        let (hash, payload) = hash(raw)?;
+        if let (Some(mut handed), Some(new_version)) = (handed, &new_version) {
+            if hash == handed.hash {
+                handed.latest = new_version.clone();
+                *self.handed.lock().unwrap_or_else(PoisonError::into_inner) = Some(handed);
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(self.hand_over(new_version, hash, payload)))
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+fn hash<S: Read>(mut raw: S) -> Result<(String, Vec<u8>)> {
+    let mut payload = Vec::new();
+    raw.read_to_end(&mut payload)?;
+    Ok((format!("{:x}", Sha256::digest(&payload)), payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use crate::sources::hash_versioned::HashVersionedSource;
+    use crate::sources::sources::{ConfigSource, InMemoryConfigSource, StaticConfigSource};
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn versions_by_content() {
+        let source = HashVersionedSource::new(StaticConfigSource::new(None::<u32>, Cursor::new(b"hello".to_vec())));
+
+        let (version, mut payload) = source.fetch().unwrap();
+        let mut read = String::new();
+        payload.read_to_string(&mut read).unwrap();
+        assert_eq!(version.as_deref(), Some(HELLO_SHA256));
+        assert_eq!(read, "hello");
+
+        assert!(source.fetch_if_newer(&String::from(HELLO_SHA256)).unwrap().is_none());
+        assert!(source.fetch_if_newer(&String::from("stale")).unwrap().is_some());
+    }
+
+    #[test]
+    fn ignores_inner_versions() {
+        let source = HashVersionedSource::new(InMemoryConfigSource::new(vec![
+            Ok((Some(1), Cursor::new(b"hello".to_vec()))),
+            Ok((Some(2), Cursor::new(b"hello".to_vec()))),
+            Ok((Some(3), Cursor::new(b"goodbye".to_vec()))),
+        ]));
+
+        let (version, _) = source.fetch().unwrap();
+        let version = version.unwrap();
+        assert!(source.fetch_if_newer(&version).unwrap().is_none());
+        assert!(source.fetch_if_newer(&version).unwrap().is_some());
+    }
+}
@@ -1,22 +1,170 @@
 pub use reqwest::blocking::{Client, Response};
 
+use std::io::Read;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::{ClientBuilder, RequestBuilder};
+use reqwest::{Certificate, Identity, Method, Proxy};
+
+use mirror_cache_core::processors::bytes::{Bytes, BytesPayload};
+use mirror_cache_core::processors::LimitedReader;
 use mirror_cache_core::util::{Error, Result};
 
 use crate::sources::sources::ConfigSource;
 
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+// The response body, which fails reading once more than the source's maximum size has been read.
+// The response itself, with its headers, is available from get_ref().
+pub type HttpBody = LimitedReader<Response>;
+
+type HeaderProvider = Box<dyn Fn() -> Result<Vec<(String, String)>> + Send + Sync>;
+
+enum Auth {
+    Bearer(String),
+    Basic(String, Option<String>),
+}
+
 pub struct HttpConfigSource {
     client: Client,
     url: String,
+    head_precheck: bool,
+    headers: Vec<(String, String)>,
+    auth: Option<Auth>,
+    header_provider: Option<HeaderProvider>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    max_size: Option<u64>,
 }
 
 impl HttpConfigSource {
+    // Builds the client as well as the source, for endpoints that need client certificates or
+    // custom trust roots.
+    pub fn builder<S: Into<String>>(url: S) -> HttpConfigSourceBuilder {
+        HttpConfigSourceBuilder {
+            client: Client::builder(),
+            url: url.into(),
+        }
+    }
+
     pub fn new(client: Client, url: String) -> HttpConfigSource {
         HttpConfigSource {
             client,
             url,
+            head_precheck: false,
+            headers: vec![],
+            auth: None,
+            header_provider: None,
+            max_retries: 0,
+            retry_backoff: Duration::ZERO,
+            max_size: None,
+        }
+    }
+
+    pub fn with_header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> HttpConfigSource {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_bearer_token<S: Into<String>>(mut self, token: S) -> HttpConfigSource {
+        self.auth = Some(Auth::Bearer(token.into()));
+        self
+    }
+
+    pub fn with_basic_auth<S: Into<String>>(mut self, username: S, password: Option<S>) -> HttpConfigSource {
+        self.auth = Some(Auth::Basic(username.into(), password.map(Into::into)));
+        self
+    }
+
+    // Called before every request, for headers that change over time such as short-lived tokens.
+    pub fn with_header_provider<F>(mut self, provider: F) -> HttpConfigSource
+        where F: Fn() -> Result<Vec<(String, String)>> + Send + Sync + 'static {
+        self.header_provider = Some(Box::new(provider));
+        self
+    }
+
+    // For servers that ignore If-Modified-Since on GET but report Last-Modified correctly on HEAD.
+    pub fn with_head_precheck(mut self) -> HttpConfigSource {
+        self.head_precheck = true;
+        self
+    }
+
+    // Server errors, timeouts and failures to connect are retried up to max_retries times before
+    // the fetch fails, waiting initial_backoff before the first retry and doubling it each time, up
+    // to five minutes.
+    pub fn with_retries(mut self, max_retries: u32, initial_backoff: Duration) -> HttpConfigSource {
+        self.max_retries = max_retries;
+        self.retry_backoff = initial_backoff;
+        self
+    }
+
+    // Responses declaring a larger Content-Length fail the fetch before their body is read. Those
+    // without one, such as chunked responses, fail as soon as more than the limit has been read.
+    pub fn with_max_size(mut self, bytes: u64) -> HttpConfigSource {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    // Reads the whole body before handing it over, as a BytesPayload processors can parse in
+    // place without copying it again.
+    pub fn buffered(self) -> HttpBufferedConfigSource {
+        HttpBufferedConfigSource {
+            inner: self,
+        }
+    }
+
+    fn send(&self, req: RequestBuilder) -> Result<Response> {
+        let mut backoff = self.retry_backoff;
+        for _ in 0..self.max_retries {
+            let Some(attempt) = req.try_clone() else {
+                break;
+            };
+
+            match attempt.send() {
+                Ok(resp) if !resp.status().is_server_error() => return Ok(resp),
+                Err(err) if !(err.is_connect() || err.is_timeout()) => return Err(err.into()),
+                _ => {}
+            }
+
+            thread::sleep(backoff.min(MAX_RETRY_BACKOFF));
+            backoff = backoff.saturating_mul(2);
+        }
+
+        Ok(req.send()?)
+    }
+
+    fn body(&self, resp: Response) -> Result<HttpBody> {
+        match (self.max_size, resp.content_length()) {
+            (Some(max_size), Some(length)) if length > max_size => Err(Error::new(
+                format!("Response of {} bytes exceeds the maximum size of {} bytes", length, max_size).as_str()
+            )),
+            (max_size, _) => Ok(LimitedReader::new(resp, max_size.unwrap_or(u64::MAX))),
         }
     }
 
+    fn request(&self, method: Method) -> Result<RequestBuilder> {
+        let mut req = self.client.request(method, self.url.as_str());
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+
+        req = match &self.auth {
+            Some(Auth::Bearer(token)) => req.bearer_auth(token),
+            Some(Auth::Basic(username, password)) => req.basic_auth(username, password.as_ref()),
+            None => req,
+        };
+
+        if let Some(provider) = &self.header_provider {
+            for (name, value) in provider()? {
+                req = req.header(name, value);
+            }
+        }
+
+        Ok(req)
+    }
+
     fn get_version(resp: &Response) -> Option<String> {
         let option = resp.headers()
             .get("Last-Modified")
@@ -29,24 +177,94 @@ impl HttpConfigSource {
     }
 }
 
-impl ConfigSource<String, Response> for HttpConfigSource {
-    fn fetch(&self) -> Result<(Option<String>, Response)> {
-        let resp = self.client.get(self.url.as_str()).send()?;
+pub struct HttpConfigSourceBuilder {
+    client: ClientBuilder,
+    url: String,
+}
+
+impl HttpConfigSourceBuilder {
+    // A PEM encoded certificate chain and PKCS#8 private key presented for mutual TLS.
+    pub fn with_identity_pem(mut self, cert_chain: &[u8], key: &[u8]) -> Result<HttpConfigSourceBuilder> {
+        self.client = self.client.identity(Identity::from_pkcs8_pem(cert_chain, key)?);
+        Ok(self)
+    }
+
+    pub fn with_identity_pkcs12(mut self, der: &[u8], password: &str) -> Result<HttpConfigSourceBuilder> {
+        self.client = self.client.identity(Identity::from_pkcs12_der(der, password)?);
+        Ok(self)
+    }
+
+    // Trusted in addition to the system roots unless with_only_custom_roots() is also set.
+    pub fn with_root_certificate_pem(mut self, pem: &[u8]) -> Result<HttpConfigSourceBuilder> {
+        self.client = self.client.add_root_certificate(Certificate::from_pem(pem)?);
+        Ok(self)
+    }
+
+    pub fn with_only_custom_roots(mut self) -> HttpConfigSourceBuilder {
+        self.client = self.client.tls_built_in_root_certs(false);
+        self
+    }
+
+    // Connects to the given address for requests to the hostname, which is still used for SNI and
+    // certificate validation. Useful when the endpoint is reached by IP or through a tunnel.
+    pub fn with_sni_override(mut self, hostname: &str, addr: SocketAddr) -> HttpConfigSourceBuilder {
+        self.client = self.client.resolve(hostname, addr);
+        self
+    }
+
+    // Routes all requests through the proxy, which may be http://, https:// or socks5://, in
+    // place of any set through the environment.
+    pub fn with_proxy(mut self, url: &str, credentials: Option<(&str, &str)>) -> Result<HttpConfigSourceBuilder> {
+        let mut proxy = Proxy::all(url)?;
+        if let Some((username, password)) = credentials {
+            proxy = proxy.basic_auth(username, password);
+        }
+
+        self.client = self.client.proxy(proxy);
+        Ok(self)
+    }
+
+    // Ignores proxies set through the environment.
+    pub fn with_no_proxy(mut self) -> HttpConfigSourceBuilder {
+        self.client = self.client.no_proxy();
+        self
+    }
+
+    pub fn build(self) -> Result<HttpConfigSource> {
+        Ok(HttpConfigSource::new(self.client.build()?, self.url))
+    }
+}
+
+impl ConfigSource<String, HttpBody> for HttpConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, HttpBody)> {
+        let resp = self.send(self.request(Method::GET)?)?;
 
         if resp.status().is_success() {
-            Ok((HttpConfigSource::get_version(&resp), resp))
+            Ok((HttpConfigSource::get_version(&resp), self.body(resp)?))
         } else {
             Err(Error::new(format!("Fetch failed. Status: {}", resp.status().as_str()).as_str()))
         }
     }
 
-    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Response)>> {
-        let resp = self.client.get(self.url.as_str())
-            .header("If-Modified-Since", version)
-            .send()?;
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, HttpBody)>> {
+        if self.head_precheck {
+            let head = self.send(self.request(Method::HEAD)?)?;
+            if !head.status().is_success() {
+                return Err(Error::new(format!("Precheck failed. Status: {}", head.status().as_str()).as_str()));
+            }
+
+            if HttpConfigSource::get_version(&head).as_ref() == Some(version) {
+                return Ok(None);
+            }
+
+            return self.fetch().map(Some);
+        }
+
+        let req = self.request(Method::GET)?.header("If-Modified-Since", version);
+        let resp = self.send(req)?;
 
         if resp.status().is_success() {
-            Ok(Some((HttpConfigSource::get_version(&resp), resp)))
+            Ok(Some((HttpConfigSource::get_version(&resp), self.body(resp)?)))
         } else if resp.status() == 304 {
             Ok(None)
         } else {
@@ -54,3 +272,28 @@ impl ConfigSource<String, Response> for HttpConfigSource {
         }
     }
 }
+
+pub struct HttpBufferedConfigSource {
+    inner: HttpConfigSource,
+}
+
+impl ConfigSource<String, BytesPayload> for HttpBufferedConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, BytesPayload)> {
+        let (version, body) = self.inner.fetch()?;
+        Ok((version, read_fully(body)?))
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, BytesPayload)>> {
+        match self.inner.fetch_if_newer(version)? {
+            Some((version, body)) => Ok(Some((version, read_fully(body)?))),
+            None => Ok(None),
+        }
+    }
+}
+
+// Reads through the limited body, so buffering can't get around the maximum size.
+fn read_fully(mut body: HttpBody) -> Result<BytesPayload> {
+    let mut bytes = Vec::with_capacity(body.get_ref().content_length().unwrap_or(0) as usize);
+    body.read_to_end(&mut bytes)?;
+    Ok(BytesPayload::new(Bytes::from(bytes)))
+}
@@ -0,0 +1,191 @@
+pub use ureq::Agent;
+
+use std::io::Read;
+use std::thread;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ureq::{ErrorKind, Request, Response};
+
+use mirror_cache_core::processors::LimitedReader;
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+pub type Body = Box<dyn Read + Send + Sync>;
+
+type HeaderProvider = Box<dyn Fn() -> Result<Vec<(String, String)>> + Send + Sync>;
+
+// A blocking HTTP(S) source built on ureq rather than reqwest, so purely synchronous binaries
+// don't need a tokio runtime. TLS and proxies are configured on the Agent.
+pub struct HttpConfigSource {
+    agent: Agent,
+    url: String,
+    head_precheck: bool,
+    headers: Vec<(String, String)>,
+    header_provider: Option<HeaderProvider>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    max_size: Option<u64>,
+}
+
+impl HttpConfigSource {
+    pub fn new<S: Into<String>>(agent: Agent, url: S) -> HttpConfigSource {
+        HttpConfigSource {
+            agent,
+            url: url.into(),
+            head_precheck: false,
+            headers: vec![],
+            header_provider: None,
+            max_retries: 0,
+            retry_backoff: Duration::ZERO,
+            max_size: None,
+        }
+    }
+
+    pub fn with_header<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> HttpConfigSource {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_bearer_token<S: Into<String>>(self, token: S) -> HttpConfigSource {
+        let value = format!("Bearer {}", token.into());
+        self.with_header("Authorization", value)
+    }
+
+    pub fn with_basic_auth<S: Into<String>>(self, username: S, password: Option<S>) -> HttpConfigSource {
+        let credentials = format!("{}:{}", username.into(), password.map(Into::into).unwrap_or_default());
+        self.with_header("Authorization", format!("Basic {}", STANDARD.encode(credentials)))
+    }
+
+    // Called before every request, for headers that change over time such as short-lived tokens.
+    pub fn with_header_provider<F>(mut self, provider: F) -> HttpConfigSource
+        where F: Fn() -> Result<Vec<(String, String)>> + Send + Sync + 'static {
+        self.header_provider = Some(Box::new(provider));
+        self
+    }
+
+    // For servers that ignore If-Modified-Since on GET but report Last-Modified correctly on HEAD.
+    pub fn with_head_precheck(mut self) -> HttpConfigSource {
+        self.head_precheck = true;
+        self
+    }
+
+    // Server errors and failures to connect are retried up to max_retries times before the fetch
+    // fails, waiting initial_backoff before the first retry and doubling it each time, up to five
+    // minutes.
+    pub fn with_retries(mut self, max_retries: u32, initial_backoff: Duration) -> HttpConfigSource {
+        self.max_retries = max_retries;
+        self.retry_backoff = initial_backoff;
+        self
+    }
+
+    // Responses declaring a larger Content-Length fail the fetch before their body is read. Those
+    // without one, such as chunked responses, fail as soon as more than the limit has been read.
+    pub fn with_max_size(mut self, bytes: u64) -> HttpConfigSource {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    fn request(&self, method: &str) -> Result<Request> {
+        let mut req = self.agent.request(method, self.url.as_str());
+        for (name, value) in &self.headers {
+            req = req.set(name, value);
+        }
+
+        if let Some(provider) = &self.header_provider {
+            for (name, value) in provider()? {
+                req = req.set(&name, &value);
+            }
+        }
+
+        Ok(req)
+    }
+
+    // ureq reports statuses of 400 and above as errors, they're handed back as responses here so
+    // they can be reported the same way as the reqwest based source does.
+    fn send(&self, req: Request) -> Result<Response> {
+        let mut backoff = self.retry_backoff;
+        for _ in 0..self.max_retries {
+            match req.clone().call() {
+                Ok(resp) => return Ok(resp),
+                Err(ureq::Error::Status(status, _)) if status >= 500 => {}
+                Err(ureq::Error::Status(_, resp)) => return Ok(resp),
+                Err(ureq::Error::Transport(err))
+                    if matches!(err.kind(), ErrorKind::ConnectionFailed | ErrorKind::Io) => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            thread::sleep(backoff.min(MAX_RETRY_BACKOFF));
+            backoff = backoff.saturating_mul(2);
+        }
+
+        match req.call() {
+            Ok(resp) | Err(ureq::Error::Status(_, resp)) => Ok(resp),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn check_size(&self, resp: &Response) -> Result<()> {
+        let length = resp.header("Content-Length").and_then(|length| length.parse::<u64>().ok());
+        match (self.max_size, length) {
+            (Some(max_size), Some(length)) if length > max_size => Err(Error::new(
+                format!("Response of {} bytes exceeds the maximum size of {} bytes", length, max_size).as_str()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn get_version(resp: &Response) -> Option<String> {
+        resp.header("Last-Modified").map(String::from)
+    }
+
+    fn body(&self, resp: Response) -> Result<(Option<String>, Body)> {
+        self.check_size(&resp)?;
+        let version = HttpConfigSource::get_version(&resp);
+        match self.max_size {
+            Some(max_size) => Ok((version, Box::new(LimitedReader::new(resp.into_reader(), max_size)))),
+            None => Ok((version, resp.into_reader())),
+        }
+    }
+}
+
+impl ConfigSource<String, Body> for HttpConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, Body)> {
+        let resp = self.send(self.request("GET")?)?;
+
+        if (200..300).contains(&resp.status()) {
+            self.body(resp)
+        } else {
+            Err(Error::new(format!("Fetch failed. Status: {}", resp.status()).as_str()))
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Body)>> {
+        if self.head_precheck {
+            let head = self.send(self.request("HEAD")?)?;
+            if !(200..300).contains(&head.status()) {
+                return Err(Error::new(format!("Precheck failed. Status: {}", head.status()).as_str()));
+            }
+
+            if HttpConfigSource::get_version(&head).as_ref() == Some(version) {
+                return Ok(None);
+            }
+
+            return self.fetch().map(Some);
+        }
+
+        let resp = self.send(self.request("GET")?.set("If-Modified-Since", version))?;
+
+        if (200..300).contains(&resp.status()) {
+            self.body(resp).map(Some)
+        } else if resp.status() == 304 {
+            Ok(None)
+        } else {
+            Err(Error::new(format!("Fetch failed. Status: {}", resp.status()).as_str()))
+        }
+    }
+}
@@ -0,0 +1,107 @@
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
+use tokio::runtime::Handle;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::runtime::SourceRuntime;
+use crate::sources::sources::ConfigSource;
+
+pub struct K8sConfigMapSource {
+    api: Api<ConfigMap>,
+    name: String,
+    rt: SourceRuntime,
+}
+
+impl K8sConfigMapSource {
+    pub fn new<S: Into<String>>(namespace: S, name: S) -> Result<K8sConfigMapSource> {
+        K8sConfigMapSource::connect(namespace, name, SourceRuntime::owned()?)
+    }
+
+    // Runs requests on an existing multi-threaded runtime rather than one of the source's own, so
+    // many sources can share it.
+    pub fn new_with_runtime<S: Into<String>>(namespace: S, name: S, runtime: Handle) -> Result<K8sConfigMapSource> {
+        K8sConfigMapSource::connect(namespace, name, SourceRuntime::Shared(runtime))
+    }
+
+    fn connect<S: Into<String>>(namespace: S, name: S, rt: SourceRuntime) -> Result<K8sConfigMapSource> {
+        let client = rt.block_on(Client::try_default())?;
+
+        Ok(K8sConfigMapSource {
+            api: Api::namespaced(client, namespace.into().as_str()),
+            name: name.into(),
+            rt,
+        })
+    }
+}
+
+impl ConfigSource<String, BTreeMap<String, String>> for K8sConfigMapSource {
+    fn fetch(&self) -> Result<(Option<String>, BTreeMap<String, String>)> {
+        let config_map = self.rt.block_on(self.api.get(self.name.as_str()))?;
+        Ok((config_map.metadata.resource_version, config_map.data.unwrap_or_default()))
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, BTreeMap<String, String>)>> {
+        let meta = self.rt.block_on(self.api.get_metadata(self.name.as_str()))?;
+        if meta.metadata.resource_version.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+}
+
+pub struct K8sConfigMapKeySource {
+    api: Api<ConfigMap>,
+    name: String,
+    key: String,
+    rt: SourceRuntime,
+}
+
+impl K8sConfigMapKeySource {
+    pub fn new<S: Into<String>>(namespace: S, name: S, key: S) -> Result<K8sConfigMapKeySource> {
+        K8sConfigMapKeySource::connect(namespace, name, key, SourceRuntime::owned()?)
+    }
+
+    pub fn new_with_runtime<S: Into<String>>(namespace: S, name: S, key: S, runtime: Handle) -> Result<K8sConfigMapKeySource> {
+        K8sConfigMapKeySource::connect(namespace, name, key, SourceRuntime::Shared(runtime))
+    }
+
+    fn connect<S: Into<String>>(namespace: S, name: S, key: S, rt: SourceRuntime) -> Result<K8sConfigMapKeySource> {
+        let client = rt.block_on(Client::try_default())?;
+
+        Ok(K8sConfigMapKeySource {
+            api: Api::namespaced(client, namespace.into().as_str()),
+            name: name.into(),
+            key: key.into(),
+            rt,
+        })
+    }
+}
+
+impl ConfigSource<String, Cursor<Vec<u8>>> for K8sConfigMapKeySource {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let config_map = self.rt.block_on(self.api.get(self.name.as_str()))?;
+        let version = config_map.metadata.resource_version;
+
+        if let Some(value) = config_map.data.and_then(|mut data| data.remove(&self.key)) {
+            Ok((version, Cursor::new(value.into_bytes())))
+        } else if let Some(value) = config_map.binary_data.and_then(|mut data| data.remove(&self.key)) {
+            Ok((version, Cursor::new(value.0)))
+        } else {
+            Err(Error::new(format!("Key '{}' not found in ConfigMap", self.key).as_str()))
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let meta = self.rt.block_on(self.api.get_metadata(self.name.as_str()))?;
+        if meta.metadata.resource_version.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+}
@@ -0,0 +1,76 @@
+pub use sqlx::mysql::MySqlRow;
+
+use sqlx::mysql::MySqlPool;
+use sqlx::Row;
+use tokio::runtime::Handle;
+
+use mirror_cache_core::util::Result;
+
+use crate::sources::runtime::SourceRuntime;
+use crate::sources::sources::ConfigSource;
+
+pub struct MySqlConfigSource {
+    pool: MySqlPool,
+    query: String,
+    version_query: Option<String>,
+    rt: SourceRuntime,
+}
+
+impl MySqlConfigSource {
+    pub fn new<S: Into<String>>(url: &str, query: S) -> Result<MySqlConfigSource> {
+        MySqlConfigSource::connect(url, query, SourceRuntime::owned()?)
+    }
+
+    // Runs queries on an existing multi-threaded runtime rather than one of the source's own, so
+    // many sources can share it.
+    pub fn new_with_runtime<S: Into<String>>(url: &str, query: S, runtime: Handle) -> Result<MySqlConfigSource> {
+        MySqlConfigSource::connect(url, query, SourceRuntime::Shared(runtime))
+    }
+
+    fn connect<S: Into<String>>(url: &str, query: S, rt: SourceRuntime) -> Result<MySqlConfigSource> {
+        Ok(MySqlConfigSource {
+            pool: rt.block_on(MySqlPool::connect(url))?,
+            query: query.into(),
+            version_query: None,
+            rt,
+        })
+    }
+
+    // The version query should return a single value, read as a string. Something like
+    // `SELECT CAST(MAX(updated_at) AS CHAR) FROM my_config`. Without one every fetch is
+    // unconditional.
+    pub fn with_version_query<S: Into<String>>(mut self, version_query: S) -> MySqlConfigSource {
+        self.version_query = Some(version_query.into());
+        self
+    }
+
+    async fn current_version(&self) -> Result<Option<String>> {
+        match &self.version_query {
+            Some(q) => {
+                let row = sqlx::query(q.as_str()).fetch_one(&self.pool).await?;
+                Ok(row.try_get::<Option<String>, _>(0)?)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn fetch_rows(&self) -> Result<Vec<MySqlRow>> {
+        Ok(sqlx::query(self.query.as_str()).fetch_all(&self.pool).await?)
+    }
+}
+
+impl ConfigSource<String, Vec<MySqlRow>> for MySqlConfigSource {
+    fn fetch(&self) -> Result<(Option<String>, Vec<MySqlRow>)> {
+        let version = self.rt.block_on(self.current_version())?;
+        Ok((version, self.rt.block_on(self.fetch_rows())?))
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Vec<MySqlRow>)>> {
+        let current = self.rt.block_on(self.current_version())?;
+        if current.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        Ok(Some((current, self.rt.block_on(self.fetch_rows())?)))
+    }
+}
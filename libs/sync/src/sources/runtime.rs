@@ -0,0 +1,53 @@
+use std::future::Future;
+
+use tokio::runtime::{Handle, Runtime};
+
+use mirror_cache_core::util::Result;
+
+// Drives the async client of a blocking source, either on a runtime of its own or on one shared
+// between many sources. A shared runtime must be multi-threaded, a current thread runtime's
+// handle can't drive IO on its own.
+pub(crate) enum SourceRuntime {
+    Owned(Runtime),
+    Shared(Handle),
+}
+
+impl SourceRuntime {
+    #[cfg(any(
+        feature = "azure-blob", feature = "github", feature = "grpc", feature = "kubernetes",
+        feature = "mysql", feature = "s3", feature = "secrets-manager",
+    ))]
+    pub(crate) fn owned() -> Result<SourceRuntime> {
+        Ok(SourceRuntime::Owned(tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?))
+    }
+
+    // For sources with a task running in the background, which needs a worker to run on between
+    // fetches.
+    #[cfg(any(feature = "etcd", feature = "grpc"))]
+    pub(crate) fn owned_multi_thread() -> Result<SourceRuntime> {
+        Ok(SourceRuntime::Owned(tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?))
+    }
+
+    pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+        match self {
+            SourceRuntime::Owned(rt) => rt.block_on(future),
+            SourceRuntime::Shared(handle) => handle.block_on(future),
+        }
+    }
+
+    #[cfg(any(feature = "etcd", feature = "grpc"))]
+    pub(crate) fn spawn<F>(&self, future: F)
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static {
+        match self {
+            SourceRuntime::Owned(rt) => rt.spawn(future),
+            SourceRuntime::Shared(handle) => handle.spawn(future),
+        };
+    }
+}
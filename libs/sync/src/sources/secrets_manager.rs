@@ -0,0 +1,91 @@
+pub use aws_sdk_secretsmanager::Client;
+
+use std::io::Cursor;
+use tokio::runtime::Handle;
+
+use mirror_cache_core::util::{Error, Result};
+
+use crate::sources::runtime::SourceRuntime;
+use crate::sources::sources::ConfigSource;
+
+const DEFAULT_STAGE: &str = "AWSCURRENT";
+
+pub struct SecretsManagerSource {
+    client: Client,
+    secret_id: String,
+    version_stage: String,
+    rt: SourceRuntime,
+}
+
+impl SecretsManagerSource {
+    pub fn new<S: Into<String>>(client: Client, secret_id: S) -> Result<SecretsManagerSource> {
+        Ok(SecretsManagerSource {
+            client,
+            secret_id: secret_id.into(),
+            version_stage: String::from(DEFAULT_STAGE),
+            rt: SourceRuntime::owned()?,
+        })
+    }
+
+    // Runs requests on an existing multi-threaded runtime rather than one of the source's own, so
+    // many sources can share it.
+    pub fn new_with_runtime<S: Into<String>>(client: Client, secret_id: S, runtime: Handle) -> SecretsManagerSource {
+        SecretsManagerSource {
+            client,
+            secret_id: secret_id.into(),
+            version_stage: String::from(DEFAULT_STAGE),
+            rt: SourceRuntime::Shared(runtime),
+        }
+    }
+
+    pub fn with_version_stage<S: Into<String>>(mut self, version_stage: S) -> SecretsManagerSource {
+        self.version_stage = version_stage.into();
+        self
+    }
+
+    async fn staged_version(&self) -> Result<String> {
+        let resp = self.client.describe_secret()
+            .secret_id(self.secret_id.clone())
+            .send().await?;
+
+        resp.version_ids_to_stages()
+            .and_then(|versions| versions.iter()
+                .find(|(_, stages)| stages.contains(&self.version_stage))
+                .map(|(id, _)| id.clone()))
+            .ok_or_else(|| Error::new(format!("No version of secret staged as {}", self.version_stage).as_str()))
+    }
+
+    async fn fetch_version(&self, version_id: Option<String>) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        let request = self.client.get_secret_value().secret_id(self.secret_id.clone());
+        let request = match version_id {
+            Some(id) => request.version_id(id),
+            None => request.version_stage(self.version_stage.clone()),
+        };
+        let resp = request.send().await?;
+
+        let content = if let Some(s) = resp.secret_string() {
+            s.as_bytes().to_vec()
+        } else if let Some(b) = resp.secret_binary() {
+            b.as_ref().to_vec()
+        } else {
+            return Err(Error::new("Secret had no value"));
+        };
+
+        Ok((resp.version_id().map(String::from), Cursor::new(content)))
+    }
+}
+
+impl ConfigSource<String, Cursor<Vec<u8>>> for SecretsManagerSource {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        self.rt.block_on(self.fetch_version(None))
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let staged = self.rt.block_on(self.staged_version())?;
+        if &staged == version {
+            return Ok(None);
+        }
+
+        self.rt.block_on(self.fetch_version(Some(staged))).map(Some)
+    }
+}
@@ -1,13 +1,20 @@
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::UNIX_EPOCH;
 
-use mirror_cache_core::util::Result;
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
 
 pub trait ConfigSource<E, S> {
     fn fetch(&self) -> Result<(Option<E>, S)>;
     fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>>;
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        None
+    }
 }
 
 pub struct LocalFileConfigSource<P: AsRef<Path>> {
@@ -56,3 +63,147 @@ impl<P: AsRef<Path>> ConfigSource<u128, BufReader<File>> for LocalFileConfigSour
     }
 }
 
+// Kubernetes mounts ConfigMaps as a directory of symlinks through `..data`, which itself links
+// to a timestamped directory and is swapped atomically on update. The link target changes on
+// every update, so it serves as the version.
+const K8S_DATA_LINK: &str = "..data";
+
+pub struct K8sMountedConfigSource<P: AsRef<Path>> {
+    dir: P,
+    key: String,
+}
+
+impl<P: AsRef<Path>> K8sMountedConfigSource<P> {
+    pub fn new<S: Into<String>>(dir: P, key: S) -> K8sMountedConfigSource<P> {
+        K8sMountedConfigSource {
+            dir,
+            key: key.into(),
+        }
+    }
+
+    fn current_data_dir(&self) -> Result<PathBuf> {
+        Ok(fs::read_link(self.dir.as_ref().join(K8S_DATA_LINK))?)
+    }
+
+    fn open(&self, data_dir: PathBuf) -> Result<(Option<String>, BufReader<File>)> {
+        let file = File::open(self.dir.as_ref().join(&data_dir).join(&self.key))?;
+        Ok((Some(data_dir.to_string_lossy().into_owned()), BufReader::new(file)))
+    }
+}
+
+impl<P: AsRef<Path>> ConfigSource<String, BufReader<File>> for K8sMountedConfigSource<P> {
+    fn fetch(&self) -> Result<(Option<String>, BufReader<File>)> {
+        self.open(self.current_data_dir()?)
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, BufReader<File>)>> {
+        let data_dir = self.current_data_dir()?;
+        if data_dir.to_string_lossy() == version.as_str() {
+            return Ok(None);
+        }
+
+        self.open(data_dir).map(Some)
+    }
+}
+
+// Always serves the same payload, mostly useful in tests or for data that never changes.
+pub struct StaticConfigSource<E, S> {
+    version: Option<E>,
+    payload: S,
+}
+
+impl<E, S> StaticConfigSource<E, S> {
+    pub fn new(version: Option<E>, payload: S) -> StaticConfigSource<E, S> {
+        StaticConfigSource {
+            version,
+            payload,
+        }
+    }
+}
+
+impl<E: Clone + PartialEq, S: Clone> ConfigSource<E, S> for StaticConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        Ok((self.version.clone(), self.payload.clone()))
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        if self.version.as_ref() == Some(version) {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+}
+
+// Serves a scripted sequence of results, one per fetch, so processors and callbacks can be tested
+// against updates and failures without a real backend. A conditional fetch whose step carries the
+// version already held reports no change. Once the script runs out there's never anything newer.
+#[allow(clippy::type_complexity)]
+pub struct InMemoryConfigSource<E, S> {
+    steps: Mutex<VecDeque<Result<(Option<E>, S)>>>,
+}
+
+impl<E, S> InMemoryConfigSource<E, S> {
+    pub fn new<I: IntoIterator<Item=Result<(Option<E>, S)>>>(steps: I) -> InMemoryConfigSource<E, S> {
+        InMemoryConfigSource {
+            steps: Mutex::new(steps.into_iter().collect()),
+        }
+    }
+
+    fn next(&self) -> Result<Option<(Option<E>, S)>> {
+        match self.steps.lock()?.pop_front() {
+            Some(step) => step.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<E: PartialEq, S> ConfigSource<E, S> for InMemoryConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        match self.next()? {
+            Some(step) => Ok(step),
+            None => Err(Error::new("No more scripted payloads")),
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        match self.next()? {
+            Some((Some(next), _)) if &next == version => Ok(None),
+            next => Ok(next),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mirror_cache_core::util::Error;
+
+    use crate::sources::sources::{ConfigSource, InMemoryConfigSource, StaticConfigSource};
+
+    #[test]
+    fn static_source() {
+        let source = StaticConfigSource::new(Some(1), "payload");
+
+        assert_eq!(source.fetch().unwrap(), (Some(1), "payload"));
+        assert_eq!(source.fetch_if_newer(&1).unwrap(), None);
+        assert_eq!(source.fetch_if_newer(&0).unwrap(), Some((Some(1), "payload")));
+    }
+
+    #[test]
+    fn in_memory_source_follows_script() {
+        let source = InMemoryConfigSource::new(vec![
+            Ok((Some(1), "one")),
+            Ok((Some(1), "one")),
+            Err(Error::new("Backend down")),
+            Ok((Some(2), "two")),
+        ]);
+
+        assert_eq!(source.fetch().unwrap(), (Some(1), "one"));
+        assert_eq!(source.fetch_if_newer(&1).unwrap(), None);
+        assert!(source.fetch_if_newer(&1).is_err());
+        assert_eq!(source.fetch_if_newer(&1).unwrap(), Some((Some(2), "two")));
+
+        assert_eq!(source.fetch_if_newer(&2).unwrap(), None);
+        assert!(source.fetch().is_err());
+    }
+}
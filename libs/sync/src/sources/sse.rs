@@ -0,0 +1,245 @@
+pub use reqwest::blocking::Client;
+
+use std::io::{BufRead, BufReader, Cursor};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use reqwest::header::ACCEPT;
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const STREAM_RETRY: Duration = Duration::from_secs(1);
+const FIRST_EVENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Latest = Option<std::result::Result<(String, Vec<u8>), String>>;
+
+// Wraps another source, fetching from it as soon as an event arrives on the stream. Polling
+// carries on at the fetch interval regardless, so nothing is missed while the stream is down.
+//
+// Blocking clients time out requests after 30 seconds by default, which includes reading the
+// body. The stream will be re-opened each time that happens, so clients without a timeout work
+// best here.
+pub struct SseConfigSource<C> {
+    inner: C,
+    trigger: RefreshTrigger,
+    closed: Arc<AtomicBool>,
+}
+
+impl<C> SseConfigSource<C> {
+    pub fn new<S: Into<String>>(client: Client, url: S, inner: C) -> SseConfigSource<C> {
+        let trigger = RefreshTrigger::new();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let on_event = trigger.clone();
+        let url = url.into();
+        let stream_closed = closed.clone();
+        thread::spawn(move || listen(client, url, stream_closed, move |_| on_event.fire(), |_| {}));
+
+        SseConfigSource {
+            inner,
+            trigger,
+            closed,
+        }
+    }
+}
+
+impl<C> Drop for SseConfigSource<C> {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<E, S, C: ConfigSource<E, S>> ConfigSource<E, S> for SseConfigSource<C> {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        self.inner.fetch()
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        self.inner.fetch_if_newer(version)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+// Uses the data of each event as the full dataset. The event id is used as the version where the
+// server sends one, otherwise events are numbered as they arrive.
+pub struct SsePayloadSource {
+    latest: Arc<(Mutex<Latest>, Condvar)>,
+    trigger: RefreshTrigger,
+    closed: Arc<AtomicBool>,
+    first_event_timeout: Duration,
+}
+
+impl SsePayloadSource {
+    pub fn new<S: Into<String>>(client: Client, url: S) -> SsePayloadSource {
+        let latest: Arc<(Mutex<Latest>, Condvar)> = Arc::new((Mutex::new(None), Condvar::new()));
+        let trigger = RefreshTrigger::new();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let events = latest.clone();
+        let failures = latest.clone();
+        let on_event = trigger.clone();
+        let url = url.into();
+        let stream_closed = closed.clone();
+
+        let mut received: u64 = 0;
+        thread::spawn(move || listen(client, url, stream_closed, move |event| {
+            received += 1;
+            let version = event.id.unwrap_or_else(|| received.to_string());
+
+            let (lock, ready) = &*events;
+            if let Ok(mut latest) = lock.lock() {
+                *latest = Some(Ok((version, event.data.into_bytes())));
+                ready.notify_all();
+            }
+            on_event.fire();
+        }, move |err| record_failure(&failures, err)));
+
+        SsePayloadSource {
+            latest,
+            trigger,
+            closed,
+            first_event_timeout: FIRST_EVENT_TIMEOUT,
+        }
+    }
+
+    // How long a fetch waits for the first event before failing, so build() can't hang on a
+    // stream that connects but never delivers. Thirty seconds by default.
+    pub fn with_first_event_timeout(mut self, timeout: Duration) -> SsePayloadSource {
+        self.first_event_timeout = timeout;
+        self
+    }
+}
+
+impl Drop for SsePayloadSource {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl ConfigSource<String, Cursor<Vec<u8>>> for SsePayloadSource {
+    fn fetch(&self) -> Result<(Option<String>, Cursor<Vec<u8>>)> {
+        // Nothing can be returned until the first event, or failure, comes in.
+        let (lock, ready) = &*self.latest;
+        let (current, waited) = ready.wait_timeout_while(lock.lock()?, self.first_event_timeout, |l| l.is_none())?;
+        if waited.timed_out() {
+            return Err(Error::new(format!("No event arrived on the stream within {:?}", self.first_event_timeout).as_str()));
+        }
+
+        match current.as_ref() {
+            Some(Ok((version, body))) => Ok((Some(version.clone()), Cursor::new(body.clone()))),
+            Some(Err(msg)) => Err(Error::new(msg.as_str())),
+            None => Err(Error::new("Stream closed before receiving data")),
+        }
+    }
+
+    fn fetch_if_newer(&self, version: &String) -> Result<Option<(Option<String>, Cursor<Vec<u8>>)>> {
+        let unchanged = matches!(self.latest.0.lock()?.as_ref(), Some(Ok((latest, _))) if latest == version);
+        if unchanged {
+            return Ok(None);
+        }
+
+        self.fetch().map(Some)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+// Once data has arrived it keeps being served while the stream is re-established, failures are
+// only surfaced if there's nothing else to return.
+fn record_failure(latest: &(Mutex<Latest>, Condvar), err: String) {
+    let (lock, ready) = latest;
+    if let Ok(mut latest) = lock.lock() {
+        if !matches!(latest.as_ref(), Some(Ok(_))) {
+            *latest = Some(Err(err));
+            ready.notify_all();
+        }
+    }
+}
+
+struct Event {
+    id: Option<String>,
+    data: String,
+}
+
+// Follows the field rules from the HTML spec, event names and retry hints are ignored.
+#[derive(Default)]
+struct EventParser {
+    last_id: Option<String>,
+    data: Vec<String>,
+}
+
+impl EventParser {
+    fn line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            if self.data.is_empty() {
+                return None;
+            }
+
+            return Some(Event {
+                id: self.last_id.clone(),
+                data: self.data.drain(..).collect::<Vec<String>>().join("\n"),
+            });
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "data" => self.data.push(String::from(value)),
+            "id" => self.last_id = Some(String::from(value)),
+            _ => {}
+        }
+
+        None
+    }
+}
+
+fn listen<F, G>(client: Client, url: String, closed: Arc<AtomicBool>, mut on_event: F, on_failure: G)
+    where
+        F: FnMut(Event),
+        G: Fn(String) {
+    let mut parser = EventParser::default();
+    while !closed.load(Ordering::Relaxed) {
+        let mut req = client.get(url.as_str()).header(ACCEPT, "text/event-stream");
+        if let Some(id) = &parser.last_id {
+            req = req.header("Last-Event-ID", id);
+        }
+
+        match req.send().and_then(|r| r.error_for_status()) {
+            Ok(resp) => {
+                for line in BufReader::new(resp).lines() {
+                    if closed.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    match line {
+                        Ok(line) => {
+                            if let Some(event) = parser.line(line.trim_end_matches('\r')) {
+                                on_event(event);
+                            }
+                        }
+                        Err(err) => {
+                            on_failure(err.to_string());
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(err) => on_failure(err.to_string()),
+        }
+
+        // Any partial event from the dropped stream is discarded.
+        parser.data.clear();
+        thread::sleep(STREAM_RETRY);
+    }
+}
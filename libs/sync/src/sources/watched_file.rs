@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::{ConfigSource, LocalFileConfigSource};
+
+// A local file that's refreshed as soon as it's created, modified or renamed into place, rather
+// than on the next fetch interval. Versions are still the file's mtime, so interval polling keeps
+// working if an event is missed.
+pub struct WatchedFileConfigSource<P: AsRef<Path>> {
+    inner: LocalFileConfigSource<P>,
+    trigger: RefreshTrigger,
+
+    // Watching stops when dropped.
+    #[allow(dead_code)]
+    watcher: RecommendedWatcher,
+}
+
+impl<P: AsRef<Path>> WatchedFileConfigSource<P> {
+    pub fn new(path: P) -> Result<WatchedFileConfigSource<P>> {
+        let file_name = match path.as_ref().file_name() {
+            Some(name) => name.to_os_string(),
+            None => return Err(Error::new(format!("{} is not a file", path.as_ref().display()).as_str())),
+        };
+
+        // Editors and deploy tooling often write a new file and rename it over the old one, which
+        // a watch on the file itself would miss, so the containing directory is watched instead.
+        let dir = match path.as_ref().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from("."),
+        };
+
+        let trigger = RefreshTrigger::new();
+        let fire = trigger.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            if let Ok(event) = event {
+                let relevant = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+                if relevant && event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str())) {
+                    fire.fire();
+                }
+            }
+        })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+        Ok(WatchedFileConfigSource {
+            inner: LocalFileConfigSource::new(path),
+            trigger,
+            watcher,
+        })
+    }
+}
+
+impl<P: AsRef<Path>> ConfigSource<u128, BufReader<File>> for WatchedFileConfigSource<P> {
+    fn fetch(&self) -> Result<(Option<u128>, BufReader<File>)> {
+        self.inner.fetch()
+    }
+
+    fn fetch_if_newer(&self, version: &u128) -> Result<Option<(Option<u128>, BufReader<File>)>> {
+        self.inner.fetch_if_newer(version)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
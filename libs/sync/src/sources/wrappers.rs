@@ -0,0 +1,771 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mirror_cache_core::util::{Error, RefreshTrigger, Result};
+
+use crate::sources::sources::ConfigSource;
+
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+type Retryable = Box<dyn Fn(&Error) -> bool + Send + Sync>;
+
+type BoxedSource<E, S> = Box<dyn ConfigSource<E, S> + Send + Sync>;
+
+// Retries failed fetches from another source, waiting initial_backoff before the first retry and
+// doubling it each time, up to five minutes unless with_max_backoff() says otherwise. By default
+// every error is retried except those carrying a retry_at, which the cache already waits out.
+pub struct RetryingSource<C> {
+    inner: C,
+    attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retryable: Retryable,
+}
+
+impl<C> RetryingSource<C> {
+    // Attempts includes the first, so 1 never retries.
+    pub fn new(inner: C, attempts: u32, initial_backoff: Duration) -> RetryingSource<C> {
+        RetryingSource {
+            inner,
+            attempts: attempts.max(1),
+            initial_backoff,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            retryable: Box::new(|err| err.retry_at().is_none()),
+        }
+    }
+
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> RetryingSource<C> {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_retryable<F>(mut self, retryable: F) -> RetryingSource<C>
+        where F: Fn(&Error) -> bool + Send + Sync + 'static {
+        self.retryable = Box::new(retryable);
+        self
+    }
+
+    fn retry<T, F: Fn() -> Result<T>>(&self, attempt: F) -> Result<T> {
+        let mut backoff = self.initial_backoff;
+        for _ in 1..self.attempts {
+            match attempt() {
+                Err(err) if (self.retryable)(&err) => {}
+                result => return result,
+            }
+
+            thread::sleep(backoff.min(self.max_backoff));
+            backoff = backoff.saturating_mul(2);
+        }
+
+        attempt()
+    }
+}
+
+impl<E, S, C: ConfigSource<E, S>> ConfigSource<E, S> for RetryingSource<C> {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        self.retry(|| self.inner.fetch())
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        self.retry(|| self.inner.fetch_if_newer(version))
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+// Fails fetches from another source that take longer than the timeout, so a hung backend can't
+// stall the cache indefinitely. Blocking calls can't be interrupted, so each fetch runs on a
+// thread of its own and one that times out is left to finish in the background.
+pub struct TimeoutSource<C> {
+    inner: Arc<C>,
+    timeout: Duration,
+}
+
+impl<C> TimeoutSource<C> {
+    pub fn new(inner: C, timeout: Duration) -> TimeoutSource<C> {
+        TimeoutSource {
+            inner: Arc::new(inner),
+            timeout,
+        }
+    }
+
+    fn with_timeout<T, F>(&self, fetch: F) -> Result<T>
+        where T: Send + 'static, F: FnOnce(&C) -> Result<T> + Send + 'static, C: Send + Sync + 'static {
+        let (tx, rx) = mpsc::channel();
+        let inner = self.inner.clone();
+        thread::spawn(move || {
+            // The receiver is gone if we timed out, nobody is left to care about the result.
+            let _ = tx.send(fetch(&inner));
+        });
+
+        match rx.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(_) => Err(Error::new(format!("Fetch timed out after {:?}", self.timeout).as_str())),
+        }
+    }
+}
+
+impl<E, S, C> ConfigSource<E, S> for TimeoutSource<C>
+    where
+        E: Clone + Send + 'static,
+        S: Send + 'static,
+        C: ConfigSource<E, S> + Send + Sync + 'static {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        self.with_timeout(|inner| inner.fetch())
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        let version = version.clone();
+        self.with_timeout(move |inner| inner.fetch_if_newer(&version))
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+// Spaces out fetches from another source by at least min_interval, however often refreshes are
+// requested, to protect upstreams shared by many caches. Conditional fetches that come too soon
+// report no change, unconditional ones wait their turn.
+pub struct RateLimitedSource<C> {
+    inner: C,
+    min_interval: Duration,
+    last_fetch: Mutex<Option<Instant>>,
+}
+
+impl<C> RateLimitedSource<C> {
+    pub fn new(inner: C, min_interval: Duration) -> RateLimitedSource<C> {
+        RateLimitedSource {
+            inner,
+            min_interval,
+            last_fetch: Mutex::new(None),
+        }
+    }
+
+    // Claims the next free slot, which may be in the future.
+    fn reserve(&self) -> Instant {
+        let mut last_fetch = self.last_fetch.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let next = match *last_fetch {
+            Some(last) => now.max(last + self.min_interval),
+            None => now,
+        };
+
+        *last_fetch = Some(next);
+        next
+    }
+
+    // Claims a slot only if one is free now.
+    fn try_reserve(&self) -> bool {
+        let mut last_fetch = self.last_fetch.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        match *last_fetch {
+            Some(last) if now < last + self.min_interval => false,
+            _ => {
+                *last_fetch = Some(now);
+                true
+            }
+        }
+    }
+}
+
+impl<E, S, C> ConfigSource<E, S> for RateLimitedSource<C>
+    where
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        let next = self.reserve();
+        thread::sleep(next.saturating_duration_since(Instant::now()));
+        self.inner.fetch()
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        if !self.try_reserve() {
+            return Ok(None);
+        }
+
+        self.inner.fetch_if_newer(version)
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+// Keeps a copy of the last payload fetched from another source, along with its version, in a
+// local file. When the inner source fails on startup the copy is served instead, so processes can
+// come up with real data while the upstream is down. Versions are stored as strings, and the
+// payload is handed on as bytes.
+pub struct PersistentSource<C, S> {
+    inner: C,
+    path: PathBuf,
+    _phantom_s: PhantomData<fn() -> S>,
+}
+
+impl<C, S: Read> PersistentSource<C, S> {
+    pub fn new<P: AsRef<Path>>(inner: C, path: P) -> PersistentSource<C, S> {
+        PersistentSource {
+            inner,
+            path: path.as_ref().to_path_buf(),
+            _phantom_s: PhantomData,
+        }
+    }
+
+    fn persist<E: ToString>(&self, version: Option<E>, mut raw: S) -> Result<(Option<E>, Cursor<Vec<u8>>)> {
+        let mut payload = Vec::new();
+        raw.read_to_end(&mut payload)?;
+
+        // Failing to save a copy shouldn't fail the fetch, the next success will try again.
+        let _ = self.write(version.as_ref().map(ToString::to_string), &payload);
+        Ok((version, Cursor::new(payload)))
+    }
+
+    // The first line holds the version, prefixed with '+', or just '-' if there was none. Written
+    // to a temporary file first and renamed over the old copy so a crash can't leave it torn.
+    fn write(&self, version: Option<String>, payload: &[u8]) -> Result<()> {
+        let header = match version {
+            Some(version) if version.contains('\n') => return Err(Error::new("Version contains a newline")),
+            Some(version) => format!("+{}\n", version),
+            None => String::from("-\n"),
+        };
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(header.as_bytes())?;
+        file.write_all(payload)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn read<E: FromStr>(&self) -> Result<(Option<E>, Cursor<Vec<u8>>)> {
+        let mut file = BufReader::new(fs::File::open(&self.path)?);
+        let mut header = String::new();
+        file.read_line(&mut header)?;
+
+        let version = match header.trim_end_matches('\n').strip_prefix('+') {
+            Some(version) => match E::from_str(version) {
+                Ok(version) => Some(version),
+                Err(_) => return Err(Error::new(format!("Unparseable persisted version {}", version).as_str())),
+            },
+            None => None,
+        };
+
+        let mut payload = Vec::new();
+        file.read_to_end(&mut payload)?;
+        Ok((version, Cursor::new(payload)))
+    }
+}
+
+impl<E, S, C> ConfigSource<E, Cursor<Vec<u8>>> for PersistentSource<C, S>
+    where
+        E: ToString + FromStr,
+        S: Read,
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, Cursor<Vec<u8>>)> {
+        match self.inner.fetch() {
+            Ok((version, raw)) => self.persist(version, raw),
+            Err(err) => self.read().map_err(|_| err),
+        }
+    }
+
+    // Only called once the cache holds data, which was saved when it was fetched, so there's
+    // nothing to fall back on here.
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, Cursor<Vec<u8>>)>> {
+        match self.inner.fetch_if_newer(version)? {
+            Some((version, raw)) => self.persist(version, raw).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        self.inner.refresh_trigger()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailoverVersion<E> {
+    // The position of the source the data came from, in the order they were added.
+    pub source: usize,
+    pub version: Option<E>,
+}
+
+// Tries each of a list of sources in order until one succeeds, for example an internal mirror
+// backed by GitHub. Conditional fetches go to the source the current data came from, while the
+// sources ahead of it are fetched in full so the cache moves back to them once they recover.
+pub struct FailoverSource<E, S> {
+    sources: Vec<BoxedSource<E, S>>,
+    trigger: RefreshTrigger,
+}
+
+impl<E, S> FailoverSource<E, S> {
+    pub fn new() -> FailoverSource<E, S> {
+        FailoverSource {
+            sources: vec![],
+            trigger: RefreshTrigger::new(),
+        }
+    }
+
+    pub fn with_source<C>(mut self, source: C) -> FailoverSource<E, S>
+        where C: ConfigSource<E, S> + Send + Sync + 'static {
+        if let Some(inner) = source.refresh_trigger() {
+            let trigger = self.trigger.clone();
+            inner.bind(move || trigger.fire());
+        }
+
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    fn all_failed(errors: Vec<String>) -> Error {
+        if errors.is_empty() {
+            Error::new("No sources to fetch from")
+        } else {
+            Error::new(format!("All sources failed: {}", errors.join("; ")).as_str())
+        }
+    }
+}
+
+impl<E, S> Default for FailoverSource<E, S> {
+    fn default() -> Self {
+        FailoverSource::new()
+    }
+}
+
+impl<E, S> ConfigSource<FailoverVersion<E>, S> for FailoverSource<E, S>
+ {
+    fn fetch(&self) -> Result<(Option<FailoverVersion<E>>, S)> {
+        let mut errors = vec![];
+        for (source, inner) in self.sources.iter().enumerate() {
+            match inner.fetch() {
+                Ok((version, payload)) => return Ok((Some(FailoverVersion { source, version }), payload)),
+                Err(err) => errors.push(err.msg),
+            }
+        }
+
+        Err(Self::all_failed(errors))
+    }
+
+    fn fetch_if_newer(&self, current: &FailoverVersion<E>) -> Result<Option<(Option<FailoverVersion<E>>, S)>> {
+        let mut errors = vec![];
+        for (source, inner) in self.sources.iter().enumerate() {
+            let result = match &current.version {
+                Some(version) if source == current.source => inner.fetch_if_newer(version),
+                _ => inner.fetch().map(Some),
+            };
+
+            match result {
+                Ok(fetched) => {
+                    return Ok(fetched.map(|(version, payload)| (Some(FailoverVersion { source, version }), payload)));
+                }
+                Err(err) => errors.push(err.msg),
+            }
+        }
+
+        Err(Self::all_failed(errors))
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+struct Fetched<E, S> {
+    version: Option<E>,
+    payload: S,
+    at: Instant,
+}
+
+struct Shared<C, E, S> {
+    inner: C,
+    window: Duration,
+    last: Mutex<Option<Fetched<E, S>>>,
+    // Each handle's trigger, keyed by an id so it can be removed when the handle is dropped.
+    triggers: Mutex<HashMap<usize, RefreshTrigger>>,
+    next_id: AtomicUsize,
+}
+
+// Lets several caches poll the same upstream with one fetch between them. Clone a handle for each
+// cache; fetches made within window of the last one reuse its payload, and concurrent ones wait
+// for the fetch in flight rather than starting their own. Payloads are cloned out to each cache.
+pub struct SharedSource<C, E, S> {
+    shared: Arc<Shared<C, E, S>>,
+    id: usize,
+    trigger: RefreshTrigger,
+}
+
+impl<C, E, S> SharedSource<C, E, S>
+    where
+        C: ConfigSource<E, S> + Send + Sync + 'static,
+        E: Send + Sync + 'static,
+        S: Send + 'static {
+    pub fn new(inner: C, window: Duration) -> SharedSource<C, E, S> {
+        let trigger = RefreshTrigger::new();
+        let shared = Arc::new(Shared {
+            inner,
+            window,
+            last: Mutex::new(None),
+            triggers: Mutex::new(HashMap::from([(0, trigger.clone())])),
+            next_id: AtomicUsize::new(1),
+        });
+
+        // Pushed refreshes go out to every handle.
+        if let Some(inner) = shared.inner.refresh_trigger() {
+            let fan_out = Arc::downgrade(&shared);
+            inner.bind(move || {
+                if let Some(shared) = fan_out.upgrade() {
+                    for trigger in shared.triggers.lock().unwrap_or_else(PoisonError::into_inner).values() {
+                        trigger.fire();
+                    }
+                }
+            });
+        }
+
+        SharedSource {
+            shared,
+            id: 0,
+            trigger,
+        }
+    }
+}
+
+impl<C, E, S> SharedSource<C, E, S>
+    where
+        E: Clone,
+        S: Clone,
+        C: ConfigSource<E, S> {
+    fn latest(&self) -> Result<(Option<E>, S)> {
+        let mut last = self.shared.last.lock().unwrap_or_else(PoisonError::into_inner);
+        let stale = last.as_ref().is_none_or(|fetched| fetched.at.elapsed() >= self.shared.window);
+        if stale {
+            let known = last.as_ref().and_then(|fetched| fetched.version.clone());
+            let newer = match &known {
+                Some(version) => self.shared.inner.fetch_if_newer(version)?,
+                None => Some(self.shared.inner.fetch()?),
+            };
+
+            match newer {
+                Some((version, payload)) => *last = Some(Fetched { version, payload, at: Instant::now() }),
+                None => {
+                    if let Some(fetched) = last.as_mut() {
+                        fetched.at = Instant::now();
+                    }
+                }
+            }
+        }
+
+        match last.as_ref() {
+            Some(fetched) => Ok((fetched.version.clone(), fetched.payload.clone())),
+            None => Err(Error::new("Nothing fetched from the shared source")),
+        }
+    }
+}
+
+impl<C, E, S> Clone for SharedSource<C, E, S> {
+    fn clone(&self) -> Self {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let trigger = RefreshTrigger::new();
+        self.shared.triggers.lock().unwrap_or_else(PoisonError::into_inner).insert(id, trigger.clone());
+
+        SharedSource {
+            shared: self.shared.clone(),
+            id,
+            trigger,
+        }
+    }
+}
+
+impl<C, E, S> Drop for SharedSource<C, E, S> {
+    fn drop(&mut self) {
+        self.shared.triggers.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.id);
+    }
+}
+
+impl<C, E, S> ConfigSource<E, S> for SharedSource<C, E, S>
+    where
+        E: Clone + PartialEq,
+        S: Clone,
+        C: ConfigSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        self.latest()
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        let (latest, payload) = self.latest()?;
+        if latest.as_ref() == Some(version) {
+            Ok(None)
+        } else {
+            Ok(Some((latest, payload)))
+        }
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+// Fetches from several mirrors serving the same content, starting with the next mirror in turn on
+// each fetch and moving on through the rest if it fails. Versions are passed between mirrors, so
+// they need to agree on them, as with ETags or content hashes.
+pub struct MirrorSource<E, S> {
+    mirrors: Vec<BoxedSource<E, S>>,
+    next: AtomicUsize,
+    trigger: RefreshTrigger,
+}
+
+impl<E, S> MirrorSource<E, S> {
+    pub fn new() -> MirrorSource<E, S> {
+        MirrorSource {
+            mirrors: vec![],
+            next: AtomicUsize::new(0),
+            trigger: RefreshTrigger::new(),
+        }
+    }
+
+    pub fn with_mirror<C>(mut self, mirror: C) -> MirrorSource<E, S>
+        where C: ConfigSource<E, S> + Send + Sync + 'static {
+        if let Some(inner) = mirror.refresh_trigger() {
+            let trigger = self.trigger.clone();
+            inner.bind(move || trigger.fire());
+        }
+
+        self.mirrors.push(Box::new(mirror));
+        self
+    }
+
+    fn rotate<T, F: Fn(&BoxedSource<E, S>) -> Result<T>>(&self, attempt: F) -> Result<T> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut errors = vec![];
+        for i in 0..self.mirrors.len() {
+            match attempt(&self.mirrors[(start + i) % self.mirrors.len()]) {
+                Ok(fetched) => return Ok(fetched),
+                Err(err) => errors.push(err.msg),
+            }
+        }
+
+        Err(all_mirrors_failed(errors))
+    }
+}
+
+impl<E, S> Default for MirrorSource<E, S> {
+    fn default() -> Self {
+        MirrorSource::new()
+    }
+}
+
+impl<E, S> ConfigSource<E, S> for MirrorSource<E, S> {
+    fn fetch(&self) -> Result<(Option<E>, S)> {
+        self.rotate(|mirror| mirror.fetch())
+    }
+
+    fn fetch_if_newer(&self, version: &E) -> Result<Option<(Option<E>, S)>> {
+        self.rotate(|mirror| mirror.fetch_if_newer(version))
+    }
+
+    fn refresh_trigger(&self) -> Option<RefreshTrigger> {
+        Some(self.trigger.clone())
+    }
+}
+
+fn all_mirrors_failed(errors: Vec<String>) -> Error {
+    if errors.is_empty() {
+        Error::new("No mirrors to fetch from")
+    } else {
+        Error::new(format!("All mirrors failed: {}", errors.join("; ")).as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Cursor, Read};
+    use std::thread;
+    use std::time::Duration;
+    use chrono::Utc;
+
+    use mirror_cache_core::util::{Error, Result};
+
+    use crate::sources::sources::{ConfigSource, InMemoryConfigSource, StaticConfigSource};
+    use crate::sources::wrappers::{
+        FailoverSource, FailoverVersion, MirrorSource, PersistentSource, RateLimitedSource, RetryingSource,
+        SharedSource, TimeoutSource,
+    };
+
+    // Takes a while to answer, like a hung backend.
+    struct Slow(Duration);
+
+    impl ConfigSource<u32, &'static str> for Slow {
+        fn fetch(&self) -> Result<(Option<u32>, &'static str)> {
+            thread::sleep(self.0);
+            Ok((Some(1), "slow"))
+        }
+
+        fn fetch_if_newer(&self, _version: &u32) -> Result<Option<(Option<u32>, &'static str)>> {
+            self.fetch().map(Some)
+        }
+    }
+
+    fn failed<T>() -> Result<T> {
+        Err(Error::new("Backend down"))
+    }
+
+    fn read(mut payload: Cursor<Vec<u8>>) -> String {
+        let mut read = String::new();
+        payload.read_to_string(&mut read).unwrap();
+        read
+    }
+
+    #[test]
+    fn retrying_source_retries_until_success() {
+        let source = RetryingSource::new(
+            InMemoryConfigSource::new(vec![failed(), failed(), Ok((Some(1), "one"))]),
+            3,
+            Duration::from_millis(1),
+        );
+
+        assert_eq!(source.fetch().unwrap(), (Some(1), "one"));
+    }
+
+    #[test]
+    fn retrying_source_gives_up_after_attempts() {
+        let source = RetryingSource::new(
+            InMemoryConfigSource::new(vec![failed(), failed(), Ok((Some(1), "one"))]),
+            2,
+            Duration::from_millis(1),
+        );
+
+        assert!(source.fetch().is_err());
+        assert_eq!(source.fetch().unwrap(), (Some(1), "one"));
+    }
+
+    #[test]
+    fn retrying_source_leaves_retry_at_to_the_cache() {
+        let source = RetryingSource::new(
+            InMemoryConfigSource::new(vec![
+                Err(Error::new("Rate limited").with_retry_at(Utc::now())),
+                Ok((Some(1), "one")),
+            ]),
+            3,
+            Duration::from_millis(1),
+        );
+
+        assert!(source.fetch().is_err());
+    }
+
+    #[test]
+    fn timeout_source() {
+        let timed_out = TimeoutSource::new(Slow(Duration::from_millis(500)), Duration::from_millis(10));
+        assert!(timed_out.fetch().is_err());
+
+        let in_time = TimeoutSource::new(Slow(Duration::from_millis(1)), Duration::from_secs(5));
+        assert_eq!(in_time.fetch().unwrap(), (Some(1), "slow"));
+    }
+
+    #[test]
+    fn rate_limited_source_skips_early_checks() {
+        let source = RateLimitedSource::new(
+            InMemoryConfigSource::new(vec![Ok((Some(1), "one")), Ok((Some(2), "two"))]),
+            Duration::from_secs(3600),
+        );
+
+        assert_eq!(source.fetch().unwrap(), (Some(1), "one"));
+        assert_eq!(source.fetch_if_newer(&1).unwrap(), None);
+        // The check never reached the inner source.
+        assert_eq!(source.inner.fetch().unwrap(), (Some(2), "two"));
+    }
+
+    #[test]
+    fn persistent_source_serves_copy_when_inner_fails() {
+        let path = std::env::temp_dir().join(format!("mirror-cache-persistent-{}", std::process::id()));
+        let source = PersistentSource::new(
+            InMemoryConfigSource::new(vec![Ok((Some(3), Cursor::new(b"payload".to_vec()))), failed()]),
+            &path,
+        );
+
+        let (version, payload) = source.fetch().unwrap();
+        assert_eq!((version, read(payload).as_str()), (Some(3), "payload"));
+
+        let (version, payload) = source.fetch().unwrap();
+        assert_eq!((version, read(payload).as_str()), (Some(3), "payload"));
+
+        fs::remove_file(&path).unwrap();
+        assert!(source.fetch().is_err());
+    }
+
+    #[test]
+    fn failover_source_prefers_earlier_sources() {
+        let source = FailoverSource::new()
+            .with_source(InMemoryConfigSource::new(vec![failed(), Ok((Some(5), "primary"))]))
+            .with_source(StaticConfigSource::new(Some(1), "backup"));
+
+        let (version, payload) = source.fetch().unwrap();
+        let version = version.unwrap();
+        assert_eq!((&version, payload), (&FailoverVersion { source: 1, version: Some(1) }, "backup"));
+
+        let update = source.fetch_if_newer(&version).unwrap();
+        assert_eq!(update, Some((Some(FailoverVersion { source: 0, version: Some(5) }), "primary")));
+    }
+
+    #[test]
+    fn failover_source_fails_when_all_do() {
+        let source = FailoverSource::<u32, &str>::new()
+            .with_source(InMemoryConfigSource::new(vec![failed()]))
+            .with_source(InMemoryConfigSource::new(vec![failed()]));
+
+        assert!(source.fetch().unwrap_err().msg.starts_with("All sources failed"));
+    }
+
+    #[test]
+    fn shared_source_fetches_once_per_window() {
+        let first = SharedSource::new(
+            InMemoryConfigSource::new(vec![Ok((Some(1), "one")), Ok((Some(2), "two"))]),
+            Duration::from_secs(3600),
+        );
+        let second = first.clone();
+
+        assert_eq!(first.fetch().unwrap(), (Some(1), "one"));
+        assert_eq!(second.fetch().unwrap(), (Some(1), "one"));
+        assert_eq!(second.fetch_if_newer(&1).unwrap(), None);
+    }
+
+    #[test]
+    fn shared_source_drops_triggers_with_handles() {
+        let first = SharedSource::new(StaticConfigSource::new(Some(1), "one"), Duration::from_secs(1));
+        let second = first.clone();
+        assert_eq!(first.shared.triggers.lock().unwrap().len(), 2);
+
+        drop(second);
+        assert_eq!(first.shared.triggers.lock().unwrap().len(), 1);
+        assert!(first.shared.triggers.lock().unwrap().contains_key(&first.id));
+    }
+
+    #[test]
+    fn mirror_source_rotates() {
+        let source = MirrorSource::new()
+            .with_mirror(StaticConfigSource::new(Some(1), "first"))
+            .with_mirror(StaticConfigSource::new(Some(1), "second"));
+
+        assert_eq!(source.fetch().unwrap().1, "first");
+        assert_eq!(source.fetch().unwrap().1, "second");
+        assert_eq!(source.fetch().unwrap().1, "first");
+    }
+
+    #[test]
+    fn mirror_source_skips_failed_mirrors() {
+        let source = MirrorSource::new()
+            .with_mirror(InMemoryConfigSource::new(vec![failed()]))
+            .with_mirror(StaticConfigSource::new(Some(1), "second"));
+
+        assert_eq!(source.fetch().unwrap(), (Some(1), "second"));
+    }
+}